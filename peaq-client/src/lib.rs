@@ -1,8 +1,13 @@
-use std::ops::Deref;
+use std::{future::Future, ops::Deref, sync::Arc};
 
 use peaq_gen::api::{
     peaq_did,
-    peaq_rbac::{self, calls::types::fetch_role::Entity, events::FetchedUserPermissions},
+    peaq_rbac::{
+        self,
+        calls::types::fetch_role::Entity,
+        events::{FetchedUserGroups, FetchedUserPermissions, FetchedUserRoles},
+    },
+    runtime_types::pallet_balances::types::ExtraFlags,
 };
 use rand::RngCore;
 use subxt::{
@@ -17,21 +22,62 @@ use subxt::{
     config::Header,
     error::{RpcError, TransactionError},
     events::{EventDetails, Events, StaticEvent},
-    ext::{codec::DecodeAll, sp_core::H256},
+    ext::{
+        codec::DecodeAll,
+        sp_core::{sr25519, Pair, H256},
+    },
     rpc_params,
-    tx::{Signer, TxInBlock, TxPayload, TxStatus},
-    utils::AccountId32,
+    tx::{Signer, SubmittableExtrinsic, TxInBlock, TxPayload, TxStatus},
+    utils::{AccountId32, MultiAddress, MultiSignature},
     OnlineClient, PolkadotConfig,
 };
-use subxt_signer::{
-    bip39::{self, Mnemonic},
-    sr25519::Keypair,
-};
+use subxt_signer::bip39::{self, Mnemonic};
 
 pub use peaq_gen;
+pub use remote_signer::RemoteSigner;
+pub use subxt::tx::PairSigner;
+
+mod remote_signer;
 
 pub type Error = Box<dyn std::error::Error>;
 
+/// Signer which can fail to sign, e.g. [RemoteSigner] which asks external process.
+///
+/// Transactions are signed only with this method, so signing error is returned
+/// instead of submitting transaction with invalid signature. Unlike [Signer],
+/// it has no infallible signing, which remote signer can't provide.
+pub trait TrySigner {
+    fn account_id(&self) -> AccountId32;
+
+    fn address(&self) -> MultiAddress<AccountId32, ()> {
+        self.account_id().into()
+    }
+
+    fn try_sign(
+        &self,
+        payload: &[u8],
+    ) -> impl Future<Output = Result<MultiSignature, Error>> + Send;
+}
+
+impl<P> TrySigner for PairSigner<PolkadotConfig, P>
+where
+    Self: Signer<PolkadotConfig>,
+{
+    fn account_id(&self) -> AccountId32 {
+        Signer::account_id(self)
+    }
+
+    fn try_sign(
+        &self,
+        payload: &[u8],
+    ) -> impl Future<Output = Result<MultiSignature, Error>> + Send {
+        let signature = self.sign(payload);
+        async move { Ok(signature) }
+    }
+}
+
+pub type AccountData = peaq_gen::api::runtime_types::pallet_balances::types::AccountData<u128>;
+
 pub struct Client {
     api: OnlineClient<PolkadotConfig>,
     rpc: RpcClient,
@@ -55,15 +101,25 @@ impl Client {
     }
 
     pub async fn get_balance(&self, address: &AccountId32) -> Result<u128, Error> {
+        Ok(self.get_account_data(address).await?.free)
+    }
+
+    /// Returns free, reserved and frozen balance of account.
+    pub async fn get_account_data(&self, address: &AccountId32) -> Result<AccountData, Error> {
         let last_block = self.get_last_block().await?;
         let balance_address = peaq_gen::api::storage().system().account(address);
         let info =
             self.api.storage().at(last_block.block.header.hash()).fetch(&balance_address).await?;
         if let Some(info) = info {
-            return Ok(info.data.free);
+            return Ok(info.data);
         }
         // Account is not initialized yet.
-        Ok(0)
+        Ok(AccountData {
+            free: 0,
+            reserved: 0,
+            frozen: 0,
+            flags: ExtraFlags(0),
+        })
     }
 
     pub async fn get_block(
@@ -105,6 +161,17 @@ impl Client {
         Ok(block)
     }
 
+    /// Returns number of the last finalized block, which can't be reverted.
+    pub async fn get_finalized_block_number(&self) -> Result<u64, Error> {
+        let hash = self.rpc_legacy.chain_get_finalized_head().await?;
+        let header = self
+            .rpc_legacy
+            .chain_get_header(Some(hash))
+            .await?
+            .ok_or_else(|| subxt::Error::Other("finalized block is not found".into()))?;
+        Ok(header.number().into())
+    }
+
     pub async fn get_nonce(&self, account_id: &AccountId32) -> Result<u64, Error> {
         let last_block = self.get_last_block().await?;
         let account_nonce = self
@@ -122,30 +189,175 @@ impl Client {
         amount: u128,
         address: AccountId32,
         signer: &S,
-    ) -> Result<(), Error>
+    ) -> Result<H256, Error>
     where
-        S: Signer<PolkadotConfig>,
+        S: TrySigner,
     {
         let tx = peaq_gen::api::tx()
             .balances()
             .transfer_allow_death(subxt::utils::MultiAddress::Id(address), amount);
-        self.submit_tx(&tx, signer).await?;
-        Ok(())
+        let tx = self.submit_tx(&tx, signer).await?;
+        Ok(tx.extrinsic_hash())
+    }
+
+    /// Transfers amount to address, but fails if sender account would be reaped.
+    pub async fn transfer_keep_alive<S>(
+        &self,
+        amount: u128,
+        address: AccountId32,
+        signer: &S,
+    ) -> Result<H256, Error>
+    where
+        S: TrySigner,
+    {
+        let tx = peaq_gen::api::tx()
+            .balances()
+            .transfer_keep_alive(subxt::utils::MultiAddress::Id(address), amount);
+        let tx = self.submit_tx(&tx, signer).await?;
+        Ok(tx.extrinsic_hash())
+    }
+
+    /// Stores data in block without changing state, e.g. as payment memo.
+    pub async fn remark<S>(&self, data: Vec<u8>, signer: &S) -> Result<H256, Error>
+    where
+        S: TrySigner,
+    {
+        let tx = peaq_gen::api::tx().system().remark(data);
+        let tx = self.submit_tx(&tx, signer).await?;
+        Ok(tx.extrinsic_hash())
+    }
+
+    /// Stores data in block and emits event with its hash, returns block and extrinsic hashes.
+    pub async fn remark_with_event<S>(
+        &self,
+        data: Vec<u8>,
+        signer: &S,
+    ) -> Result<(H256, H256), Error>
+    where
+        S: TrySigner,
+    {
+        let tx = peaq_gen::api::tx().system().remark_with_event(data);
+        let tx = self.submit_tx(&tx, signer).await?;
+        Ok((tx.block_hash(), tx.extrinsic_hash()))
+    }
+
+    /// Transfers whole free balance to address; account is reaped unless `keep_alive` is set.
+    pub async fn transfer_all<S>(
+        &self,
+        address: AccountId32,
+        keep_alive: bool,
+        signer: &S,
+    ) -> Result<H256, Error>
+    where
+        S: TrySigner,
+    {
+        let tx = peaq_gen::api::tx()
+            .balances()
+            .transfer_all(subxt::utils::MultiAddress::Id(address), keep_alive);
+        let tx = self.submit_tx(&tx, signer).await?;
+        Ok(tx.extrinsic_hash())
     }
 
-    pub async fn submit_tx<Call: TxPayload, S: Signer<PolkadotConfig>>(
+    /// Returns DID attributes created by owner together with DID accounts they belong to.
+    pub async fn get_owned_attributes(
+        &self,
+        owner: &AccountId32,
+    ) -> Result<Vec<(AccountId32, DIDAttribute)>, Error> {
+        let last_block = self.get_last_block().await?;
+        let storage = self.api.storage().at(last_block.block.header.hash());
+        let owner_address = peaq_gen::api::storage().peaq_did().owner_store_iter1(owner);
+        let mut keys = storage.iter(owner_address).await?;
+        let mut attributes = Vec::new();
+        while let Some(entry) = keys.next().await {
+            let (key, did_account) = entry?;
+            // Attribute key is the last part of storage key, hashed with concatenating hasher.
+            let attribute_key: [u8; 32] = key
+                .get(key.len().saturating_sub(32)..)
+                .and_then(|key| key.try_into().ok())
+                .ok_or("invalid owner store key")?;
+            let attribute_address =
+                peaq_gen::api::storage().peaq_did().attribute_store(attribute_key);
+            if let Some(attribute) = storage.fetch(&attribute_address).await? {
+                attributes.push((did_account, attribute));
+            }
+        }
+        Ok(attributes)
+    }
+
+    /// Returns RBAC roles, permissions and groups created by owner.
+    pub async fn get_owned_entities(&self, owner: &AccountId32) -> Result<OwnedEntities, Error> {
+        let last_block = self.get_last_block().await?;
+        let storage = self.api.storage().at(last_block.block.header.hash());
+        let rbac = peaq_gen::api::storage().peaq_rbac();
+        Ok(OwnedEntities {
+            roles: storage.fetch(&rbac.role_store(owner)).await?.unwrap_or_default(),
+            permissions: storage.fetch(&rbac.permission_store(owner)).await?.unwrap_or_default(),
+            groups: storage.fetch(&rbac.group_store(owner)).await?.unwrap_or_default(),
+        })
+    }
+
+    /// Returns RBAC assignments of entities created by owner.
+    ///
+    /// Assignment stores are keyed by hash, so they are read in full and filtered by owned ids.
+    pub async fn get_owned_assignments(
+        &self,
+        owner: &AccountId32,
+    ) -> Result<OwnedAssignments, Error> {
+        let owned = self.get_owned_entities(owner).await?;
+        let roles: Vec<Entity> = owned.roles.iter().map(|v| v.id).collect();
+        let groups: Vec<Entity> = owned.groups.iter().map(|v| v.id).collect();
+        let last_block = self.get_last_block().await?;
+        let storage = self.api.storage().at(last_block.block.header.hash());
+        let rbac = peaq_gen::api::storage().peaq_rbac();
+        let mut assignments = OwnedAssignments::default();
+        let mut values = storage.iter(rbac.permission2_role_store_iter()).await?;
+        while let Some(entry) = values.next().await {
+            for v in entry?.1.into_iter().filter(|v| roles.contains(&v.role)) {
+                assignments.permission_roles.push((v.permission, v.role));
+            }
+        }
+        let mut values = storage.iter(rbac.role2_group_store_iter()).await?;
+        while let Some(entry) = values.next().await {
+            for v in entry?.1.into_iter().filter(|v| groups.contains(&v.group)) {
+                assignments.role_groups.push((v.role, v.group));
+            }
+        }
+        let mut values = storage.iter(rbac.role2_user_store_iter()).await?;
+        while let Some(entry) = values.next().await {
+            for v in entry?.1.into_iter().filter(|v| roles.contains(&v.role)) {
+                assignments.role_users.push((v.role, v.user));
+            }
+        }
+        let mut values = storage.iter(rbac.user2_group_store_iter()).await?;
+        while let Some(entry) = values.next().await {
+            for v in entry?.1.into_iter().filter(|v| groups.contains(&v.group)) {
+                assignments.user_groups.push((v.user, v.group));
+            }
+        }
+        Ok(assignments)
+    }
+
+    /// Returns ids of groups user is assigned to, unlike [RBAC::fetch_user_groups]
+    /// it reads storage and costs no fee.
+    pub async fn get_user_groups(&self, user: &Entity) -> Result<Vec<Entity>, Error> {
+        let last_block = self.get_last_block().await?;
+        let storage = self.api.storage().at(last_block.block.header.hash());
+        let rbac = peaq_gen::api::storage().peaq_rbac();
+        let mut groups = Vec::new();
+        let mut values = storage.iter(rbac.user2_group_store_iter()).await?;
+        while let Some(entry) = values.next().await {
+            groups.extend(entry?.1.into_iter().filter(|v| v.user == *user).map(|v| v.group));
+        }
+        Ok(groups)
+    }
+
+    pub async fn submit_tx<Call: TxPayload, S: TrySigner>(
         &self,
         call: &Call,
         signer: &S,
     ) -> Result<TxInBlock<PolkadotConfig, OnlineClient<PolkadotConfig>>, Error> {
-        let account_id = signer.account_id();
-        let account_nonce = self.get_nonce(&account_id).await?;
-        let mut tx = self
-            .api
-            .tx()
-            .create_signed_with_nonce(call, signer, account_nonce, Default::default())?
-            .submit_and_watch()
-            .await?;
+        let tx = self.create_signed(call, signer).await?;
+        let mut tx = tx.submit_and_watch().await?;
         while let Some(status) = tx.next().await {
             match status? {
                 TxStatus::InBestBlock(tx_in_block) | TxStatus::InFinalizedBlock(tx_in_block) => {
@@ -164,6 +376,38 @@ impl Client {
         Err(RpcError::SubscriptionDropped.into())
     }
 
+    pub async fn estimate_fee<Call: TxPayload, S: TrySigner>(
+        &self,
+        call: &Call,
+        signer: &S,
+    ) -> Result<u128, Error> {
+        let tx = self.create_signed(call, signer).await?;
+        Ok(tx.partial_fee_estimate().await?)
+    }
+
+    /// Signs transaction before it is built, so signing error is returned as is.
+    async fn create_signed<Call: TxPayload, S: TrySigner>(
+        &self,
+        call: &Call,
+        signer: &S,
+    ) -> Result<SubmittableExtrinsic<PolkadotConfig, OnlineClient<PolkadotConfig>>, Error> {
+        let account_nonce = self.get_nonce(&signer.account_id()).await?;
+        let partial = || {
+            self.api
+                .tx()
+                .create_partial_signed_with_nonce(call, account_nonce, Default::default())
+                .map_err(Error::from)
+        };
+        let payload = partial()?.signer_payload();
+        let signature = signer.try_sign(&payload).await?;
+        // Partial transaction can't be kept across await, so it is built again.
+        let partial = partial()?;
+        if partial.signer_payload() != payload {
+            return Err("transaction changed while it was signed".into());
+        }
+        Ok(partial.sign_with_address_and_signature(&signer.address(), &signature))
+    }
+
     async fn process_events<T, F>(
         &self,
         tx: TxInBlock<PolkadotConfig, OnlineClient<PolkadotConfig>>,
@@ -187,29 +431,63 @@ impl Client {
     }
 }
 
-pub struct SignerClient {
-    client: Client,
-    keypair: Keypair,
+/// Client which signs transactions with any signer, e.g. [PairSigner] or [RemoteSigner].
+pub struct SignerClient<S: TrySigner> {
+    client: Arc<Client>,
+    signer: S,
 }
 
-impl SignerClient {
-    pub async fn new(rpc_url: &str, keypair: Keypair) -> Result<Self, Error> {
+impl<S: TrySigner> SignerClient<S> {
+    pub async fn new(rpc_url: &str, signer: S) -> Result<Self, Error> {
         let client = Client::new(rpc_url).await?;
-        Ok(Self { client, keypair })
+        Ok(Self::with_client(Arc::new(client), signer))
+    }
+
+    /// Creates signer client which shares connection with other clients.
+    pub fn with_client(client: Arc<Client>, signer: S) -> Self {
+        Self { client, signer }
+    }
+
+    pub async fn transfer(&self, amount: u128, address: AccountId32) -> Result<H256, Error> {
+        self.client.transfer(amount, address, &self.signer).await
     }
 
-    pub async fn transfer(&self, amount: u128, address: AccountId32) -> Result<(), Error> {
-        self.client.transfer(amount, address, &self.keypair).await
+    pub async fn transfer_keep_alive(
+        &self,
+        amount: u128,
+        address: AccountId32,
+    ) -> Result<H256, Error> {
+        self.client.transfer_keep_alive(amount, address, &self.signer).await
+    }
+
+    pub async fn transfer_all(
+        &self,
+        address: AccountId32,
+        keep_alive: bool,
+    ) -> Result<H256, Error> {
+        self.client.transfer_all(address, keep_alive, &self.signer).await
+    }
+
+    pub async fn remark(&self, data: Vec<u8>) -> Result<H256, Error> {
+        self.client.remark(data, &self.signer).await
+    }
+
+    pub async fn remark_with_event(&self, data: Vec<u8>) -> Result<(H256, H256), Error> {
+        self.client.remark_with_event(data, &self.signer).await
     }
 
     pub async fn submit_tx<Call: TxPayload>(
         &self,
         call: &Call,
     ) -> Result<TxInBlock<PolkadotConfig, OnlineClient<PolkadotConfig>>, Error> {
-        self.client.submit_tx(call, &self.keypair).await
+        self.client.submit_tx(call, &self.signer).await
+    }
+
+    pub async fn estimate_fee<Call: TxPayload>(&self, call: &Call) -> Result<u128, Error> {
+        self.client.estimate_fee(call, &self.signer).await
     }
 
-    pub fn did(&self) -> DID {
+    pub fn did(&self) -> DID<'_, S> {
         DID {
             client: &self.client,
             signer_client: self,
@@ -217,7 +495,7 @@ impl SignerClient {
         }
     }
 
-    pub fn rbac(&self) -> RBAC {
+    pub fn rbac(&self) -> RBAC<'_, S> {
         RBAC {
             client: &self.client,
             signer_client: self,
@@ -225,12 +503,16 @@ impl SignerClient {
         }
     }
 
-    fn address(&self) -> AccountId32 {
-        <subxt_signer::sr25519::Keypair as Signer<PolkadotConfig>>::account_id(&self.keypair)
+    pub fn address(&self) -> AccountId32 {
+        self.signer.account_id()
+    }
+
+    pub fn signer(&self) -> &S {
+        &self.signer
     }
 }
 
-impl Deref for SignerClient {
+impl<S: TrySigner> Deref for SignerClient<S> {
     type Target = Client;
     fn deref(&self) -> &Self::Target {
         &self.client
@@ -238,22 +520,22 @@ impl Deref for SignerClient {
 }
 
 /// RBAC structure contains methods to interact with PEAQ DID pallet.
-pub struct DID<'a> {
+pub struct DID<'a, S: TrySigner> {
     client: &'a Client,
-    signer_client: &'a SignerClient,
+    signer_client: &'a SignerClient<S>,
     peaq_did_api: peaq_did::calls::TransactionApi,
 }
 
-impl<'a> DID<'a> {
-    pub async fn add_attribute(&self, name: &str, value: Vec<u8>) -> Result<(), Error> {
+impl<'a, S: TrySigner> DID<'a, S> {
+    pub async fn add_attribute(&self, name: &str, value: Vec<u8>) -> Result<H256, Error> {
         let call = self.peaq_did_api.add_attribute(
             self.signer_client.address(),
             name.as_bytes().to_vec(),
             value,
             None,
         );
-        self.signer_client.submit_tx(&call).await?;
-        Ok(())
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
     }
 
     pub async fn read_attribute<T, F>(
@@ -289,39 +571,68 @@ impl<'a> DID<'a> {
         }
     }
 
-    pub async fn update_attribute(&self, name: &str, value: Vec<u8>) -> Result<(), Error> {
+    pub async fn update_attribute(&self, name: &str, value: Vec<u8>) -> Result<H256, Error> {
         let call = self.peaq_did_api.update_attribute(
             self.signer_client.address(),
             name.as_bytes().to_vec(),
             value,
             None,
         );
-        self.signer_client.submit_tx(&call).await?;
-        Ok(())
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
     }
 
-    pub async fn remove_attribute(&self, name: &str) -> Result<(), Error> {
-        let call = self
-            .peaq_did_api
-            .remove_attribute(self.signer_client.address(), name.as_bytes().to_vec());
-        self.signer_client.submit_tx(&call).await?;
-        Ok(())
+    pub async fn remove_attribute(&self, name: &str) -> Result<H256, Error> {
+        self.remove_account_attribute(self.signer_client.address(), name.as_bytes().to_vec()).await
+    }
+
+    /// Removes attribute of any DID account owned by signer.
+    pub async fn remove_account_attribute(
+        &self,
+        did_account: AccountId32,
+        name: Vec<u8>,
+    ) -> Result<H256, Error> {
+        let call = self.peaq_did_api.remove_attribute(did_account, name);
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
     }
 }
 
+pub type DIDAttribute = peaq_gen::api::runtime_types::peaq_pallet_did::structs::Attribute<u32, u64>;
+
 pub const ENTITY_ID_LENGTH: usize = 32;
 
 pub type RBACRecord = peaq_gen::api::runtime_types::peaq_pallet_rbac::structs::Entity<Entity>;
 
+/// RBAC entities created by one owner.
+pub struct OwnedEntities {
+    pub roles: Vec<RBACRecord>,
+    pub permissions: Vec<RBACRecord>,
+    pub groups: Vec<RBACRecord>,
+}
+
+/// RBAC assignments of entities created by one owner.
+#[derive(Default)]
+pub struct OwnedAssignments {
+    /// Permission and role pairs.
+    pub permission_roles: Vec<(Entity, Entity)>,
+    /// Role and group pairs.
+    pub role_groups: Vec<(Entity, Entity)>,
+    /// Role and user pairs.
+    pub role_users: Vec<(Entity, Entity)>,
+    /// User and group pairs.
+    pub user_groups: Vec<(Entity, Entity)>,
+}
+
 /// RBAC structure contains methods to interact with PEAQ RBAC pallet.
 #[allow(clippy::upper_case_acronyms)]
-pub struct RBAC<'a> {
+pub struct RBAC<'a, S: TrySigner> {
     client: &'a Client,
-    signer_client: &'a SignerClient,
+    signer_client: &'a SignerClient<S>,
     peaq_rbac_api: peaq_rbac::calls::TransactionApi,
 }
 
-impl<'a> RBAC<'a> {
+impl<'a, S: TrySigner> RBAC<'a, S> {
     pub async fn add_role(&self, name: String) -> Result<Entity, Error> {
         let mut role_id = [0u8; ENTITY_ID_LENGTH];
         rand::thread_rng().fill_bytes(&mut role_id);
@@ -350,30 +661,143 @@ impl<'a> RBAC<'a> {
         &self,
         permission_id: Entity,
         role_id: Entity,
-    ) -> Result<(), Error> {
+    ) -> Result<H256, Error> {
         let call = self.peaq_rbac_api.assign_permission_to_role(permission_id, role_id);
-        self.signer_client.submit_tx(&call).await?;
-        Ok(())
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
+    }
+
+    pub async fn unassign_permission_to_role(
+        &self,
+        permission_id: Entity,
+        role_id: Entity,
+    ) -> Result<H256, Error> {
+        let call = self.peaq_rbac_api.unassign_permission_to_role(permission_id, role_id);
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
     }
 
     pub async fn assign_role_to_group(
         &self,
         role_id: Entity,
         group_id: Entity,
-    ) -> Result<(), Error> {
+    ) -> Result<H256, Error> {
         let call = self.peaq_rbac_api.assign_role_to_group(role_id, group_id);
-        self.signer_client.submit_tx(&call).await?;
-        Ok(())
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
+    }
+
+    pub async fn unassign_role_to_group(
+        &self,
+        role_id: Entity,
+        group_id: Entity,
+    ) -> Result<H256, Error> {
+        let call = self.peaq_rbac_api.unassign_role_to_group(role_id, group_id);
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
     }
 
     pub async fn assign_user_to_group(
         &self,
         user_id: Entity,
         group_id: Entity,
-    ) -> Result<(), Error> {
+    ) -> Result<H256, Error> {
         let call = self.peaq_rbac_api.assign_user_to_group(user_id, group_id);
-        self.signer_client.submit_tx(&call).await?;
-        Ok(())
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
+    }
+
+    pub async fn disable_role(&self, role_id: Entity) -> Result<H256, Error> {
+        let call = self.peaq_rbac_api.disable_role(role_id);
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
+    }
+
+    pub async fn disable_permission(&self, permission_id: Entity) -> Result<H256, Error> {
+        let call = self.peaq_rbac_api.disable_permission(permission_id);
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
+    }
+
+    pub async fn disable_group(&self, group_id: Entity) -> Result<H256, Error> {
+        let call = self.peaq_rbac_api.disable_group(group_id);
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
+    }
+
+    pub async fn assign_role_to_user(
+        &self,
+        role_id: Entity,
+        user_id: Entity,
+    ) -> Result<H256, Error> {
+        let call = self.peaq_rbac_api.assign_role_to_user(role_id, user_id);
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
+    }
+
+    pub async fn unassign_role_to_user(
+        &self,
+        role_id: Entity,
+        user_id: Entity,
+    ) -> Result<H256, Error> {
+        let call = self.peaq_rbac_api.unassign_role_to_user(role_id, user_id);
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
+    }
+
+    pub async fn unassign_user_to_group(
+        &self,
+        user_id: Entity,
+        group_id: Entity,
+    ) -> Result<H256, Error> {
+        let call = self.peaq_rbac_api.unassign_user_to_group(user_id, group_id);
+        let tx = self.signer_client.submit_tx(&call).await?;
+        Ok(tx.extrinsic_hash())
+    }
+
+    /// Returns ids of groups user is assigned to by owner.
+    pub async fn fetch_user_groups(
+        &self,
+        owner: AccountId32,
+        user_id: Entity,
+    ) -> Result<Vec<Entity>, Error> {
+        let call = self.peaq_rbac_api.fetch_user_groups(owner, user_id);
+        let groups = self.fetch(&call, filter_fetched_user_groups).await?;
+        Ok(groups.into_iter().map(|v| v.group).collect())
+    }
+
+    /// Returns ids of roles user is assigned to by owner.
+    pub async fn fetch_user_roles(
+        &self,
+        owner: AccountId32,
+        user_id: Entity,
+    ) -> Result<Vec<Entity>, Error> {
+        let call = self.peaq_rbac_api.fetch_user_roles(owner, user_id);
+        let roles = self.fetch(&call, filter_fetched_user_roles).await?;
+        Ok(roles.into_iter().map(|v| v.role).collect())
+    }
+
+    // Submits fetch call and returns empty list if user has no assignments.
+    async fn fetch<Call, T, F>(&self, call: &Call, filter: F) -> Result<Vec<T>, Error>
+    where
+        Call: TxPayload,
+        F: Fn(EventDetails<PolkadotConfig>) -> Option<Vec<T>>,
+    {
+        let tx = self.signer_client.submit_tx(call).await?;
+        match self.client.process_events(tx, Some(filter)).await {
+            Ok(data) => Ok(data.unwrap_or_default()),
+            Err(subxt::Error::Runtime(subxt::error::DispatchError::Module(e))) => {
+                // Error index is the second byte, see DID::read_attribute.
+                let a = e.bytes()[1..2].to_vec();
+                let rbac_err = peaq_rbac::Error::decode_all(&mut a.as_slice())?;
+                if let peaq_rbac::Error::EntityDoesNotExist = rbac_err {
+                    Ok(Vec::new())
+                } else {
+                    Err(e.into())
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
     }
 
     pub async fn fetch_user_permissions(
@@ -392,11 +816,11 @@ impl<'a> RBAC<'a> {
     }
 }
 
-pub fn generate_account() -> Result<(Mnemonic, Keypair, AccountId32), Error> {
+pub fn generate_account() -> Result<(Mnemonic, sr25519::Pair, AccountId32), Error> {
     let phrase = bip39::Mnemonic::generate(12)?;
-    let keypair = Keypair::from_phrase(&phrase, None)?;
-    let account_id: AccountId32 =
-        <subxt_signer::sr25519::Keypair as Signer<PolkadotConfig>>::account_id(&keypair);
+    let (keypair, _) = sr25519::Pair::from_phrase(&phrase.to_string(), None)
+        .map_err(|e| format!("failed to create keypair from phrase: {e:?}"))?;
+    let account_id = AccountId32(keypair.public().0);
     Ok((phrase, keypair, account_id))
 }
 
@@ -409,14 +833,38 @@ fn filter_fetched_user_permissions(event: EventDetails<PolkadotConfig>) -> Optio
     None
 }
 
+fn filter_fetched_user_groups(
+    event: EventDetails<PolkadotConfig>,
+) -> Option<peaq_rbac::events::fetched_user_groups::Field0> {
+    if event.variant_name() == FetchedUserGroups::EVENT {
+        if let Ok(Some(evt)) = event.as_event::<FetchedUserGroups>() {
+            return Some(evt.0);
+        }
+    }
+    None
+}
+
+fn filter_fetched_user_roles(
+    event: EventDetails<PolkadotConfig>,
+) -> Option<peaq_rbac::events::fetched_user_roles::Field0> {
+    if event.variant_name() == FetchedUserRoles::EVENT {
+        if let Ok(Some(evt)) = event.as_event::<FetchedUserRoles>() {
+            return Some(evt.0);
+        }
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
-    use std::str::{from_utf8, FromStr};
+    use std::str::from_utf8;
 
-    use subxt::{tx::Signer, utils::AccountId32, PolkadotConfig};
-    use subxt_signer::{bip39::Mnemonic, sr25519::Keypair};
+    use subxt::{
+        ext::sp_core::{sr25519, Pair},
+        utils::AccountId32,
+    };
 
-    use crate::{Client, SignerClient};
+    use crate::{Client, PairSigner, SignerClient};
 
     #[tokio::test]
     async fn get_token_information() {
@@ -432,16 +880,12 @@ mod tests {
     #[ignore = "requires mnemonic phrase"]
     #[tokio::test]
     async fn test_rbac() {
-        let keypair = Keypair::from_phrase(
-            &Mnemonic::from_str(
-                "weather asthma become jealous hurry option canal boring hedgehog rule heavy spawn",
-            )
-            .unwrap(),
+        let (keypair, _) = sr25519::Pair::from_phrase(
+            "weather asthma become jealous hurry option canal boring hedgehog rule heavy spawn",
             None,
         )
         .unwrap();
-        let owner: AccountId32 =
-            <subxt_signer::sr25519::Keypair as Signer<PolkadotConfig>>::account_id(&keypair);
+        let owner = AccountId32(keypair.public().0);
 
         let user_id = [
             122, 190, 82, 250, 244, 222, 128, 103, 71, 215, 50, 122, 3, 178, 251, 167, 35, 47, 138,
@@ -449,7 +893,9 @@ mod tests {
         ];
 
         let client =
-            SignerClient::new("wss://rpcpc1-qa.agung.peaq.network", keypair).await.unwrap();
+            SignerClient::new("wss://rpcpc1-qa.agung.peaq.network", PairSigner::new(keypair))
+                .await
+                .unwrap();
         let rbac = client.rbac();
 
         // let permission_name = String::from("mqtt_access");
@@ -0,0 +1,249 @@
+use std::{
+    future::Future,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::de::DeserializeOwned;
+use subxt::{
+    ext::sp_core::{blake2_256, ecdsa, ed25519, sr25519, Pair},
+    utils::{AccountId32, MultiSignature},
+};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+use crate::{Error, TrySigner};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Signer which keeps keys in external process and asks it to sign payloads over unix socket.
+///
+/// Every request is a single line JSON message and every response is a single line JSON
+/// message with either `ok` or `error` field:
+///
+/// ```text
+/// > {"method":"account"}
+/// < {"ok":{"scheme":"sr25519","account_id":"5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"}}
+/// > {"method":"sign","payload":"0x..."}
+/// < {"ok":{"signature":"0x..."}}
+/// ```
+#[derive(Clone)]
+pub struct RemoteSigner {
+    path: PathBuf,
+    scheme: Scheme,
+    account_id: AccountId32,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Scheme {
+    Sr25519,
+    Ed25519,
+    Ecdsa,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "method", rename_all = "lowercase")]
+enum Request {
+    Account,
+    Sign { payload: String },
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Response<T> {
+    Ok(T),
+    Error(String),
+}
+
+#[derive(serde::Deserialize)]
+struct AccountResponse {
+    scheme: Scheme,
+    account_id: AccountId32,
+}
+
+#[derive(serde::Deserialize)]
+struct SignResponse {
+    signature: String,
+}
+
+impl RemoteSigner {
+    /// Connects to remote signer and requests its account.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let account: AccountResponse = call(&path, &Request::Account)?;
+        Ok(Self {
+            path,
+            scheme: account.scheme,
+            account_id: account.account_id,
+        })
+    }
+
+    /// Decodes signature and checks that it is made by signer account.
+    fn signature(&self, payload: &[u8], signature: &str) -> Result<MultiSignature, Error> {
+        let signature = hex::decode(signature.trim_start_matches("0x"))?;
+        let public = self.account_id.0;
+        let (signature, valid) = match self.scheme {
+            Scheme::Sr25519 => {
+                let signature = signature.as_slice().try_into()?;
+                let valid = sr25519::Pair::verify(
+                    &sr25519::Signature::from_raw(signature),
+                    payload,
+                    &sr25519::Public::from_raw(public),
+                );
+                (MultiSignature::Sr25519(signature), valid)
+            }
+            Scheme::Ed25519 => {
+                let signature = signature.as_slice().try_into()?;
+                let valid = ed25519::Pair::verify(
+                    &ed25519::Signature::from_raw(signature),
+                    payload,
+                    &ed25519::Public::from_raw(public),
+                );
+                (MultiSignature::Ed25519(signature), valid)
+            }
+            // Ecdsa account is hash of public key, so public key is recovered from signature.
+            Scheme::Ecdsa => {
+                let signature = signature.as_slice().try_into()?;
+                let valid = ecdsa::Signature::from_raw(signature)
+                    .recover(payload)
+                    .is_some_and(|v| blake2_256(&v.0) == public);
+                (MultiSignature::Ecdsa(signature), valid)
+            }
+        };
+        if !valid {
+            return Err(format!(
+                "remote signer {} returned signature which is not made by {}",
+                self.path.display(),
+                self.account_id
+            )
+            .into());
+        }
+        Ok(signature)
+    }
+}
+
+impl TrySigner for RemoteSigner {
+    fn account_id(&self) -> AccountId32 {
+        self.account_id.clone()
+    }
+
+    fn try_sign(
+        &self,
+        payload: &[u8],
+    ) -> impl Future<Output = Result<MultiSignature, Error>> + Send {
+        let request = Request::Sign {
+            payload: format!("0x{}", hex::encode(payload)),
+        };
+        async move {
+            let response: SignResponse = call_async(&self.path, &request).await?;
+            self.signature(payload, &response.signature)
+        }
+    }
+}
+
+// Account is requested once on start, so blocking call is fine there.
+fn call<T: DeserializeOwned>(path: &Path, request: &Request) -> Result<T, Error> {
+    let mut stream = UnixStream::connect(path)
+        .map_err(|e| format!("failed to connect to remote signer {}: {e}", path.display()))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream.write_all(request_line(request)?.as_bytes())?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    parse_response(&line)
+}
+
+async fn call_async<T: DeserializeOwned>(path: &Path, request: &Request) -> Result<T, Error> {
+    let request = request_line(request)?;
+    let exchange = async {
+        let mut stream = tokio::net::UnixStream::connect(path).await?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut line = String::new();
+        tokio::io::BufReader::new(stream).read_line(&mut line).await?;
+        Ok::<_, std::io::Error>(line)
+    };
+    let line = tokio::time::timeout(TIMEOUT, exchange)
+        .await
+        .map_err(|_| format!("remote signer {} timed out", path.display()))?
+        .map_err(|e| format!("failed to call remote signer {}: {e}", path.display()))?;
+    parse_response(&line)
+}
+
+fn request_line(request: &Request) -> Result<String, Error> {
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    Ok(line)
+}
+
+fn parse_response<T: DeserializeOwned>(line: &str) -> Result<T, Error> {
+    match serde_json::from_str(line)? {
+        Response::Ok(result) => Ok(result),
+        Response::Error(e) => Err(format!("remote signer error: {e}").into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixListener, thread};
+
+    use super::*;
+
+    // Serves account of one keypair, but signs with other one.
+    fn serve(path: &Path, account: sr25519::Pair, key: sr25519::Pair) {
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path).unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).unwrap();
+                let request: serde_json::Value = serde_json::from_str(&line).unwrap();
+                let response = match request["method"].as_str().unwrap() {
+                    "account" => serde_json::json!({"ok": {
+                        "scheme": "sr25519",
+                        "account_id": AccountId32(account.public().0),
+                    }}),
+                    _ => {
+                        let payload = request["payload"].as_str().unwrap();
+                        let payload = hex::decode(payload.trim_start_matches("0x")).unwrap();
+                        let signature = key.sign(&payload);
+                        serde_json::json!({"ok": {"signature": hex::encode(signature.0)}})
+                    }
+                };
+                writeln!(&stream, "{}", response).unwrap();
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn remote_signer() {
+        let dir = std::env::temp_dir().join(format!("remote-signer-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (pair, _) = sr25519::Pair::generate();
+        let account_id = AccountId32(pair.public().0);
+        let path = dir.join("signer.sock");
+        serve(&path, pair.clone(), pair.clone());
+
+        let signer = RemoteSigner::new(&path).unwrap();
+        assert_eq!(signer.account_id(), account_id);
+        let signature = match signer.try_sign(b"payload").await.unwrap() {
+            MultiSignature::Sr25519(signature) => sr25519::Signature::from_raw(signature),
+            _ => panic!("unexpected signature scheme"),
+        };
+        assert!(sr25519::Pair::verify(&signature, b"payload", &pair.public()));
+
+        // Signature of other key is error rather than transaction which node rejects.
+        let path = dir.join("other.sock");
+        serve(&path, pair, sr25519::Pair::generate().0);
+        let signer = RemoteSigner::new(&path).unwrap();
+        let error = signer.try_sign(b"payload").await.unwrap_err();
+        assert!(error.to_string().contains("is not made by"), "{error}");
+
+        // Unreachable signer is error too.
+        std::fs::remove_file(&path).unwrap();
+        assert!(signer.try_sign(b"payload").await.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
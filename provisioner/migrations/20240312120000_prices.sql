@@ -0,0 +1,5 @@
+alter table devices
+add column price_access text;
+
+alter table devices
+add column price_pin text;
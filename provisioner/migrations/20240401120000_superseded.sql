@@ -0,0 +1,5 @@
+create table superseded (
+  address text primary key,
+  superseded_by text not null,
+  updated_at integer not null
+);
@@ -0,0 +1,10 @@
+-- Merkle roots of device readings found in remarks sent by devices.
+create table anchors (
+  device text not null,
+  batch integer not null,
+  root text not null,
+  size integer not null,
+  block integer not null,
+  block_hash text not null,
+  primary key (device, root)
+);
@@ -0,0 +1,3 @@
+-- Whether device attributes are signed by device key, devices saved before have no attestation.
+alter table devices
+add column attested integer not null default 0;
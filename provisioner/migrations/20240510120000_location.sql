@@ -0,0 +1,31 @@
+-- Coordinates of published location for geo search, accuracy is radius in meters
+-- around them which device is within.
+alter table devices
+add column latitude real;
+
+alter table devices
+add column longitude real;
+
+alter table devices
+add column location_accuracy real not null default 0;
+
+update devices
+set
+  latitude = cast(
+    trim(
+      substr(
+        json_extract(data, '$.location'),
+        1,
+        instr(json_extract(data, '$.location'), ',') - 1
+      )
+    ) as real
+  ),
+  longitude = cast(
+    trim(
+      substr(
+        json_extract(data, '$.location'),
+        instr(json_extract(data, '$.location'), ',') + 1
+      )
+    ) as real
+  ),
+  location_accuracy = coalesce(json_extract(data, '$.location_accuracy'), 0);
@@ -0,0 +1,15 @@
+-- Heartbeat remarks of devices in the last uptime window, time is block time in unix seconds.
+create table heartbeats (
+  device text not null,
+  block integer not null,
+  time integer not null,
+  interval integer not null,
+  primary key (device, block)
+);
+
+-- The latest heartbeat of every device, it is kept after heartbeats are pruned.
+create table liveness (
+  device text primary key,
+  last_seen integer not null,
+  interval integer not null
+);
@@ -0,0 +1,24 @@
+-- Merkle roots of device readings submitted on chain with remark_with_event.
+create table batches (
+  id integer primary key autoincrement,
+  device text not null,
+  root text not null,
+  size integer not null,
+  block_hash text not null,
+  tx_hash text not null,
+  created_at integer not null
+);
+
+-- Readings received from devices, batch, position and inclusion proof are set once anchored.
+create table readings (
+  id integer primary key autoincrement,
+  device text not null,
+  data text not null,
+  received_at integer not null,
+  leaf text not null,
+  batch_id integer references batches (id),
+  leaf_index integer,
+  proof text
+);
+
+create index readings_pending on readings (device, batch_id);
@@ -0,0 +1,2 @@
+-- Sequence number of batch among batches of its device, it is part of anchor remark.
+alter table batches add column number integer;
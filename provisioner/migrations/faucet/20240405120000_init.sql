@@ -0,0 +1,14 @@
+create table requests (
+  id integer primary key autoincrement,
+  address text not null,
+  ip text not null,
+  amount text not null,
+  tx text not null,
+  created_at integer not null
+);
+
+create index requests_address on requests (address, created_at);
+
+create index requests_ip on requests (ip, created_at);
+
+create index requests_created_at on requests (created_at);
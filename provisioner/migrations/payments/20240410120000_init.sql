@@ -0,0 +1,34 @@
+create table cursor (
+  id integer primary key check (id = 1),
+  block integer not null
+);
+
+create table memos (
+  payer text primary key,
+  memo text not null,
+  block integer not null
+);
+
+create table payments (
+  id integer primary key autoincrement,
+  block integer not null,
+  event_index integer not null,
+  payer text not null,
+  recipient text not null,
+  amount text not null,
+  memo text,
+  device text,
+  kind text,
+  reference text,
+  status text not null,
+  error text,
+  attempts integer not null default 0,
+  grant_tx text,
+  created_at integer not null,
+  updated_at integer not null,
+  unique (block, event_index)
+);
+
+create index payments_status on payments (status);
+
+create index payments_payer on payments (payer, device);
@@ -0,0 +1,18 @@
+-- Membership in access group bought by payer, renewed by every payment for the same group.
+create table grants (
+  id integer primary key autoincrement,
+  payer text not null,
+  access_group text not null,
+  device text not null,
+  expires_block integer,
+  expires_at integer,
+  status text not null,
+  error text,
+  attempts integer not null default 0,
+  revoke_tx text,
+  created_at integer not null,
+  updated_at integer not null,
+  unique (payer, access_group)
+);
+
+create index grants_status on grants (status);
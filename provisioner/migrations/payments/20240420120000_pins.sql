@@ -0,0 +1,15 @@
+-- Range of device data which device keeps until expiry, one per pin payment.
+create table pins (
+  id integer primary key autoincrement,
+  payment_id integer not null unique references payments (id),
+  device text not null,
+  buyer text not null,
+  range_from integer not null,
+  range_to integer not null,
+  reference text,
+  expires_block integer,
+  expires_at integer,
+  created_at integer not null
+);
+
+create index pins_device on pins (device, buyer);
@@ -1,5 +1,5 @@
 #!/bin/sh
 
-for file in /migrations/*.sql; do
+for file in /migrations/*.sql /migrations/*/*.sql; do
   npx sql-formatter -l sqlite --fix "${file}"
 done
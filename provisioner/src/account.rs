@@ -0,0 +1,422 @@
+use std::path::PathBuf;
+
+use clap::Subcommand;
+use peaq_client::{DIDAttribute, OwnedEntities, TrySigner};
+use serde::Serialize;
+use subxt::{ext::sp_core::Pair, utils::AccountId32};
+
+use crate::{
+    amount::{Amount, Planck, Token},
+    config::{self, Config},
+    keystore::Keystore,
+    onboard::is_valid_name,
+    signer::AnySigner,
+    write_keystore, App, Error,
+};
+
+#[derive(Subcommand)]
+pub(crate) enum AccountCommand {
+    /// Show free, reserved and frozen balance.
+    Balance {
+        /// Account address, device account by default.
+        address: Option<AccountId32>,
+        /// Device name, main device by default.
+        #[arg(long, default_value = config::MAIN_DEVICE_NAME)]
+        device: String,
+    },
+    /// Show nonce, DID attributes and owned RBAC entities.
+    Info {
+        /// Account address, device account by default.
+        address: Option<AccountId32>,
+        /// Device name, main device by default.
+        #[arg(long, default_value = config::MAIN_DEVICE_NAME)]
+        device: String,
+    },
+    /// Transfer tokens from device account.
+    Transfer {
+        /// Address to send tokens to.
+        to: AccountId32,
+        /// Amount to send, e.g. "0.5 AGUNG".
+        amount: String,
+        /// Allow sender account to be reaped if its balance drops below existential deposit.
+        #[arg(long)]
+        allow_death: bool,
+        /// Device name, main device by default.
+        #[arg(long, default_value = config::MAIN_DEVICE_NAME)]
+        device: String,
+    },
+    /// Export device signer to encrypted keystore file.
+    Export {
+        /// Keystore file to create.
+        #[arg(long)]
+        keystore: String,
+        /// Device name, main device by default.
+        #[arg(long, default_value = config::MAIN_DEVICE_NAME)]
+        device: String,
+        /// Environment variable with keystore passphrase.
+        #[arg(long, conflicts_with = "password_file")]
+        password_env: Option<String>,
+        /// File with keystore passphrase.
+        #[arg(long)]
+        password_file: Option<String>,
+    },
+    /// Check keystore file, e.g. from polkadot.js, and copy it to keystore directory.
+    Import {
+        /// Keystore file to import.
+        keystore: PathBuf,
+        /// Name of imported keystore [default: name from keystore or file name].
+        #[arg(long)]
+        name: Option<String>,
+        /// Directory for keystores.
+        #[arg(long, default_value = "keystores")]
+        keystore_dir: PathBuf,
+        /// Environment variable with keystore passphrase.
+        #[arg(long, conflicts_with = "password_file")]
+        password_env: Option<String>,
+        /// File with keystore passphrase.
+        #[arg(long)]
+        password_file: Option<String>,
+    },
+}
+
+#[derive(Serialize)]
+struct BalanceOutput {
+    address: String,
+    free: Planck,
+    reserved: Planck,
+    frozen: Planck,
+}
+
+#[derive(Serialize)]
+struct InfoOutput {
+    address: String,
+    nonce: u64,
+    attributes: Vec<AttributeOutput>,
+    entities: Vec<EntityOutput>,
+}
+
+#[derive(Serialize)]
+struct AttributeOutput {
+    did_account: String,
+    name: String,
+    size: usize,
+}
+
+#[derive(Serialize)]
+struct EntityOutput {
+    kind: &'static str,
+    id: String,
+    name: String,
+    enabled: bool,
+}
+
+#[derive(Serialize)]
+struct TransferOutput {
+    from: String,
+    to: String,
+    amount: Planck,
+    keep_alive: bool,
+    tx: String,
+}
+
+#[derive(Serialize)]
+struct KeystoreOutput {
+    address: String,
+    keystore: String,
+}
+
+pub(crate) async fn run(cfg: Config, command: AccountCommand, json: bool) -> Result<(), Error> {
+    match command {
+        AccountCommand::Balance { address, device } => {
+            // Only node connection is needed to show balance of any account.
+            let address = resolve_address(&cfg, address, &device)?;
+            let client = peaq_client::Client::new(&cfg.rpc_url).await?;
+            let token = Token::fetch(&client).await?;
+            let data = client.get_account_data(&address).await?;
+            let output = BalanceOutput {
+                address: address.to_string(),
+                free: Planck(data.free),
+                reserved: Planck(data.reserved),
+                frozen: Planck(data.frozen),
+            };
+            print(json, &output, |output| eprint!("{}", output.human(&token)))
+        }
+        AccountCommand::Info { address, device } => {
+            let address = resolve_address(&cfg, address, &device)?;
+            let client = peaq_client::Client::new(&cfg.rpc_url).await?;
+            let nonce = client.get_nonce(&address).await?;
+            let attributes = client.get_owned_attributes(&address).await?;
+            let owned = client.get_owned_entities(&address).await?;
+            let output = InfoOutput::new(&address, nonce, attributes, owned);
+            print(json, &output, |output| eprint!("{}", output.human()))
+        }
+        AccountCommand::Transfer {
+            to,
+            amount,
+            allow_death,
+            device,
+        } => {
+            let app = App::new(cfg).await?;
+            let peaq_client = &app.device(&device)?.peaq_client;
+            let amount = amount.parse::<Amount>()?.to_planck(&app.token)?;
+            let tx = if allow_death {
+                peaq_client.transfer(amount.0, to.clone()).await?
+            } else {
+                peaq_client.transfer_keep_alive(amount.0, to.clone()).await?
+            };
+            let output = TransferOutput {
+                from: peaq_client.address().to_string(),
+                to: to.to_string(),
+                amount,
+                keep_alive: !allow_death,
+                tx: format!("{tx:?}"),
+            };
+            print(json, &output, |output| {
+                eprintln!(
+                    "Sent {} from {} to {} in {} transaction",
+                    app.token.format(output.amount),
+                    output.from,
+                    output.to,
+                    output.tx
+                );
+            })
+        }
+        AccountCommand::Export {
+            keystore,
+            device,
+            password_env,
+            password_file,
+        } => {
+            let password = keystore_password(password_env, password_file)?;
+            let keypair = match AnySigner::from_config(cfg.device_signer(&device)?)? {
+                AnySigner::Sr25519(signer) => signer.signer().clone(),
+                _ => return Err("only sr25519 signers can be exported to keystore".into()),
+            };
+            let keystore_json = Keystore::encrypt(&keypair, &password, &device)?;
+            write_keystore(&keystore, &keystore_json)?;
+            let output = KeystoreOutput {
+                address: keystore_json.address,
+                keystore,
+            };
+            print(json, &output, |output| {
+                eprintln!("Keystore: {}", output.keystore);
+                eprintln!("Address: {}", output.address);
+            })
+        }
+        AccountCommand::Import {
+            keystore,
+            name,
+            keystore_dir,
+            password_env,
+            password_file,
+        } => {
+            let password = keystore_password(password_env, password_file)?;
+            let keystore_json: Keystore = serde_json::from_str(
+                &std::fs::read_to_string(&keystore)
+                    .map_err(|e| format!("failed to read {}: {e}", keystore.display()))?,
+            )?;
+            // Keystore is decrypted only to check passphrase.
+            let keypair = keystore_json.decrypt(&password)?;
+            let address = AccountId32(keypair.public().0).to_string();
+            let name = name
+                .or_else(|| {
+                    keystore_json.meta.get("name").and_then(|v| v.as_str()).map(ToString::to_string)
+                })
+                .or_else(|| keystore.file_stem().map(|v| v.to_string_lossy().to_string()))
+                .unwrap_or_default();
+            if !is_valid_name(&name) {
+                return Err(format!("invalid keystore name {name:?}; set it with --name").into());
+            }
+            std::fs::create_dir_all(&keystore_dir)?;
+            let path = keystore_dir.join(format!("{name}.json")).to_string_lossy().to_string();
+            write_keystore(&path, &keystore_json)?;
+            let output = KeystoreOutput {
+                address,
+                keystore: path,
+            };
+            print(json, &output, |output| {
+                eprintln!("Keystore: {}", output.keystore);
+                eprintln!("Address: {}", output.address);
+                eprintln!("Signer config:");
+                eprintln!("  typ = \"Keystore\"");
+                eprintln!("  val_file = {:?}", output.keystore);
+                eprintln!("  password_env = \"...\"");
+            })
+        }
+    }
+}
+
+impl BalanceOutput {
+    fn human(&self, token: &Token) -> String {
+        format!(
+            "Address: {}\nFree: {}\nReserved: {}\nFrozen: {}\n",
+            self.address,
+            token.format(self.free),
+            token.format(self.reserved),
+            token.format(self.frozen)
+        )
+    }
+}
+
+impl InfoOutput {
+    fn new(
+        address: &AccountId32,
+        nonce: u64,
+        attributes: Vec<(AccountId32, DIDAttribute)>,
+        owned: OwnedEntities,
+    ) -> Self {
+        let attributes = attributes
+            .into_iter()
+            .map(|(did_account, attribute)| AttributeOutput {
+                did_account: did_account.to_string(),
+                name: String::from_utf8_lossy(&attribute.name).to_string(),
+                size: attribute.value.len(),
+            })
+            .collect();
+        let entities = [
+            ("role", owned.roles),
+            ("permission", owned.permissions),
+            ("group", owned.groups),
+        ]
+        .into_iter()
+        .flat_map(|(kind, entities)| {
+            entities.into_iter().map(move |entity| EntityOutput {
+                kind,
+                id: format!("0x{}", hex::encode(entity.id)),
+                name: String::from_utf8_lossy(&entity.name).to_string(),
+                enabled: entity.enabled,
+            })
+        })
+        .collect();
+        Self {
+            address: address.to_string(),
+            nonce,
+            attributes,
+            entities,
+        }
+    }
+
+    fn human(&self) -> String {
+        let mut lines = vec![
+            format!("Address: {}", self.address),
+            format!("Nonce: {}", self.nonce),
+            format!("DID attributes: {}", self.attributes.len()),
+        ];
+        for attribute in &self.attributes {
+            lines.push(format!(
+                "  {} of {} ({} bytes)",
+                attribute.name, attribute.did_account, attribute.size
+            ));
+        }
+        lines.push(format!("RBAC entities: {}", self.entities.len()));
+        for entity in &self.entities {
+            let disabled = if entity.enabled { "" } else { " (disabled)" };
+            lines.push(format!("  {} {} {}{}", entity.kind, entity.name, entity.id, disabled));
+        }
+        lines.iter().map(|line| format!("{line}\n")).collect()
+    }
+}
+
+/// Returns given address or address of device signer, which is the only signer created.
+fn resolve_address(
+    cfg: &Config,
+    address: Option<AccountId32>,
+    device: &str,
+) -> Result<AccountId32, Error> {
+    match address {
+        Some(address) => Ok(address),
+        None => Ok(AnySigner::from_config(cfg.device_signer(device)?)?.account_id()),
+    }
+}
+
+fn keystore_password(
+    password_env: Option<String>,
+    password_file: Option<String>,
+) -> Result<String, Error> {
+    config::Signer {
+        password_env,
+        password_file,
+        ..Default::default()
+    }
+    .password()?
+    .ok_or_else(|| "keystore requires --password-env or --password-file".into())
+}
+
+// JSON goes to stdout for scripts, human readable output goes to stderr as everywhere else.
+fn print<T: Serialize>(json: bool, output: &T, human: impl FnOnce(&T)) -> Result<(), Error> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(output)?);
+    } else {
+        human(output);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use peaq_client::{
+        peaq_gen::api::runtime_types::peaq_pallet_did::structs::Attribute, RBACRecord,
+    };
+
+    use super::*;
+    use crate::testing::agung;
+
+    #[test]
+    fn address_resolution() {
+        let cfg = Config::default();
+        let alice: AccountId32 =
+            "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY".parse().unwrap();
+        let other = AccountId32([1; 32]);
+        assert_eq!(resolve_address(&cfg, None, config::MAIN_DEVICE_NAME).unwrap(), alice);
+        assert!(resolve_address(&cfg, None, "sensor-1").is_err());
+        // Explicit address doesn't need device signer.
+        assert_eq!(resolve_address(&cfg, Some(other.clone()), "sensor-1").unwrap(), other);
+    }
+
+    #[test]
+    fn output() {
+        let token = agung();
+        let balance = BalanceOutput {
+            address: AccountId32([1; 32]).to_string(),
+            free: Planck(1_500_000_000_000_000_000),
+            reserved: Planck(0),
+            frozen: Planck(1),
+        };
+        let json = serde_json::to_value(&balance).unwrap();
+        assert_eq!(json["free"], "1500000000000000000");
+        let human = balance.human(&token);
+        assert!(human.contains("Free: 1.5 AGUNG\n"), "{human}");
+        assert!(human.contains("Frozen: 0.000000000000000001 AGUNG\n"), "{human}");
+
+        let address = AccountId32([1; 32]);
+        let attribute = Attribute {
+            name: b"staex-ioa-device".to_vec(),
+            value: vec![0; 42],
+            validity: 0,
+            created: 0,
+        };
+        let owned = OwnedEntities {
+            roles: vec![RBACRecord {
+                id: [0xab; 32],
+                name: b"reader".to_vec(),
+                enabled: false,
+            }],
+            permissions: vec![],
+            groups: vec![],
+        };
+        let info = InfoOutput::new(&address, 7, vec![(address.clone(), attribute)], owned);
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["nonce"], 7);
+        assert_eq!(json["attributes"][0]["size"], 42);
+        assert_eq!(json["entities"][0]["kind"], "role");
+        assert_eq!(
+            info.human(),
+            format!(
+                "Address: {address}\nNonce: 7\nDID attributes: 1\n  \
+                 staex-ioa-device of {address} (42 bytes)\nRBAC entities: 1\n  \
+                 role reader 0x{} (disabled)\n",
+                "ab".repeat(32)
+            )
+        );
+    }
+}
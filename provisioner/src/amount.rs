@@ -0,0 +1,336 @@
+use std::{fmt, str::FromStr};
+
+use peaq_client::Client;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject, StringValidation},
+    JsonSchema,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Error;
+
+// Raw amounts can be set with this symbol instead of token symbol.
+const PLANCK_SYMBOL: &str = "planck";
+
+/// Token amount in planck, the smallest indivisible token unit.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
+pub(crate) struct Planck(pub(crate) u128);
+
+impl Planck {
+    // Number of digits in u128::MAX.
+    const MAX_DIGITS: usize = 39;
+
+    /// Zero padded decimal representation which keeps numeric order
+    /// when compared as string, e.g. in SQL.
+    pub(crate) fn to_padded(self) -> String {
+        format!("{:0width$}", self.0, width = Self::MAX_DIGITS)
+    }
+}
+
+impl fmt::Display for Planck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for Planck {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("invalid planck amount: {s}").into());
+        }
+        Ok(Self(s.parse()?))
+    }
+}
+
+// JSON numbers can't hold u128 without precision loss in most consumers,
+// so human readable formats store planck as decimal string.
+impl Serialize for Planck {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.0.to_string())
+        } else {
+            serializer.serialize_u128(self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Planck {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let value = String::deserialize(deserializer)?;
+            value.parse().map_err(serde::de::Error::custom)
+        } else {
+            Ok(Self(u128::deserialize(deserializer)?))
+        }
+    }
+}
+
+impl JsonSchema for Planck {
+    fn schema_name() -> String {
+        "Planck".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(format!("^[0-9]{{1,{}}}$", Self::MAX_DIGITS)),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// Native token of the network.
+#[derive(Clone, Debug)]
+pub(crate) struct Token {
+    pub(crate) symbol: String,
+    pub(crate) decimals: u32,
+}
+
+impl Token {
+    pub(crate) async fn fetch(client: &Client) -> Result<Self, Error> {
+        let properties = client.get_system_properties().await?;
+        // Multi-token chains return arrays, the first one is the native token.
+        let first = |value: &serde_json::Value| match value {
+            serde_json::Value::Array(values) => values.first().cloned(),
+            value => Some(value.clone()),
+        };
+        let decimals = properties
+            .get("tokenDecimals")
+            .and_then(first)
+            .and_then(|v| v.as_u64())
+            .ok_or("token decimals are not found in system properties")?;
+        let symbol = properties
+            .get("tokenSymbol")
+            .and_then(first)
+            .and_then(|v| v.as_str().map(ToString::to_string))
+            .ok_or("token symbol is not found in system properties")?;
+        Ok(Self {
+            symbol,
+            decimals: decimals.try_into()?,
+        })
+    }
+
+    /// Formats planck amount as human readable amount, e.g. "0.9 AGUNG".
+    pub(crate) fn format(&self, amount: Planck) -> String {
+        let decimals = self.decimals as usize;
+        let digits = format!("{:0width$}", amount.0, width = decimals + 1);
+        let (int, frac) = digits.split_at(digits.len() - decimals);
+        let frac = frac.trim_end_matches('0');
+        if frac.is_empty() {
+            format!("{} {}", int, self.symbol)
+        } else {
+            format!("{}.{} {}", int, frac, self.symbol)
+        }
+    }
+}
+
+/// Human readable token amount like "0.9 AGUNG", "42" or "1000 planck".
+///
+/// Token decimals are known only after connecting to the node, so amount
+/// is kept in decimal form and converted with [Amount::to_planck].
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Amount {
+    digits: u128,
+    scale: u32,
+    symbol: Option<String>,
+}
+
+impl Amount {
+    pub(crate) fn to_planck(&self, token: &Token) -> Result<Planck, Error> {
+        match &self.symbol {
+            Some(symbol) if symbol.eq_ignore_ascii_case(PLANCK_SYMBOL) => {
+                return Ok(Planck(self.digits));
+            }
+            Some(symbol) if !symbol.eq_ignore_ascii_case(&token.symbol) => {
+                return Err(format!(
+                    "amount {self} is in {symbol} but network token is {}",
+                    token.symbol
+                )
+                .into());
+            }
+            _ => {}
+        }
+        if self.scale > token.decimals {
+            return Err(format!(
+                "amount {self} has more than {} fractional digits of {}",
+                token.decimals, token.symbol
+            )
+            .into());
+        }
+        10u128
+            .checked_pow(token.decimals - self.scale)
+            .and_then(|multiplier| self.digits.checked_mul(multiplier))
+            .map(Planck)
+            .ok_or_else(|| format!("amount {self} is too big").into())
+    }
+}
+
+impl FromStr for Amount {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let number = parts.next().ok_or("amount is empty")?;
+        let symbol = parts.next().map(ToString::to_string);
+        if parts.next().is_some() {
+            return Err(format!("invalid amount: {s}").into());
+        }
+        let (int, frac) = number.split_once('.').unwrap_or((number, ""));
+        let is_digits = |v: &str| v.bytes().all(|b| b.is_ascii_digit());
+        if int.is_empty() || !is_digits(int) || !is_digits(frac) || number.ends_with('.') {
+            return Err(format!("invalid amount: {s}").into());
+        }
+        let is_planck = symbol.as_deref().is_some_and(|v| v.eq_ignore_ascii_case(PLANCK_SYMBOL));
+        if is_planck && !frac.is_empty() {
+            return Err(format!("planck amount can't be fractional: {s}").into());
+        }
+        let digits: u128 =
+            format!("{int}{frac}").parse().map_err(|_| format!("amount {s} is too big"))?;
+        Ok(Self {
+            digits,
+            scale: frac.len().try_into()?,
+            symbol,
+        })
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scale = self.scale as usize;
+        let digits = format!("{:0width$}", self.digits, width = scale + 1);
+        let (int, frac) = digits.split_at(digits.len() - scale);
+        if frac.is_empty() {
+            write!(f, "{int}")?;
+        } else {
+            write!(f, "{int}.{frac}")?;
+        }
+        if let Some(symbol) = &self.symbol {
+            write!(f, " {symbol}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+// Configs written before amounts were strings have float prices and integer planck amounts.
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(AmountVisitor)
+    }
+}
+
+struct AmountVisitor;
+
+impl serde::de::Visitor<'_> for AmountVisitor {
+    type Value = Amount;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "amount string like \"0.9 AGUNG\" or \"1000 planck\"")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Amount, E> {
+        value.parse().map_err(E::custom)
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, value: f64) -> Result<Amount, E> {
+        // Float is formatted with the shortest representation, so it keeps written digits.
+        let amount: Amount = value.to_string().parse().map_err(E::custom)?;
+        eprintln!(
+            "warning: amount {value} is a number, which is deprecated; write it as \"{amount}\""
+        );
+        Ok(amount)
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Amount, E> {
+        // Old prices were token amounts and old faucet amount was planck, so integer is ambiguous.
+        Err(E::custom(format!(
+            "amount {value} must be a string: \"{value}\" for token amount or \"{value} planck\" \
+             for planck amount, see \"Amounts\" in README"
+        )))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Amount, E> {
+        self.visit_i64(value.try_into().unwrap_or(i64::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::agung;
+
+    #[test]
+    fn amount_to_planck() {
+        let planck = |s: &str| s.parse::<Amount>().unwrap().to_planck(&agung());
+        assert_eq!(planck("0.9 AGUNG").unwrap(), Planck(900_000_000_000_000_000));
+        assert_eq!(planck("42").unwrap(), Planck(42_000_000_000_000_000_000));
+        assert_eq!(planck("1.5 agung").unwrap(), Planck(1_500_000_000_000_000_000));
+        assert_eq!(planck("999993264201726756 planck").unwrap(), Planck(999993264201726756));
+        assert!(planck("1 DOT").is_err());
+        assert!(planck("0.0000000000000000001").is_err());
+        assert!(planck("1000000000000000000000").is_err());
+    }
+
+    #[test]
+    fn invalid_amount() {
+        for s in [
+            "",
+            "-1",
+            "1.",
+            ".5",
+            "1,5",
+            "1e5",
+            "1 AGUNG x",
+            "0.5 planck",
+        ] {
+            assert!(s.parse::<Amount>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn amount_display() {
+        for s in ["0.9 AGUNG", "42", "0.05", "445.12222 AGUNG"] {
+            assert_eq!(s.parse::<Amount>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn deprecated_amount() {
+        #[derive(Deserialize)]
+        struct Prices {
+            price: Amount,
+        }
+        let price = |s: &str| toml::from_str::<Prices>(s).map(|v| v.price);
+        assert_eq!(price("price = \"42.03995\"").unwrap(), "42.03995".parse().unwrap());
+        assert_eq!(price("price = 42.03995").unwrap(), "42.03995".parse().unwrap());
+        let e = price("price = 999993264201726756").err().unwrap().to_string();
+        assert!(e.contains("\"999993264201726756 planck\""), "{e}");
+        assert!(price("price = -1.5").is_err());
+    }
+
+    #[test]
+    fn format_planck() {
+        assert_eq!(agung().format(Planck(900_000_000_000_000_000)), "0.9 AGUNG");
+        assert_eq!(agung().format(Planck(42_000_000_000_000_000_000)), "42 AGUNG");
+        assert_eq!(agung().format(Planck(1)), "0.000000000000000001 AGUNG");
+        assert_eq!(agung().format(Planck(0)), "0 AGUNG");
+    }
+
+    #[test]
+    fn padded_planck() {
+        assert!(Planck(9).to_padded() < Planck(10).to_padded());
+        assert_eq!(Planck(u128::MAX).to_padded().len(), Planck(0).to_padded().len());
+    }
+}
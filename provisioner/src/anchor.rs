@@ -0,0 +1,488 @@
+use std::{
+    fmt,
+    str::from_utf8,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clap::Subcommand;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sqlx::{Connection, FromRow, SqliteConnection};
+use subxt::{
+    ext::sp_core::{blake2_256, H256},
+    utils::AccountId32,
+};
+use tokio::sync::Mutex;
+
+use crate::{indexer::open_sqlite, App, DeviceClient, Error};
+
+/// Prefix of system remark with Merkle root of device readings.
+pub(crate) const ANCHOR_PREFIX: &str = "staex-ioa-anchor";
+
+pub(crate) type AnchorsPointer = Arc<Mutex<Anchors>>;
+
+pub(crate) type Hash = [u8; 32];
+
+/// Remark which device sends to anchor batch of its readings.
+#[derive(PartialEq, Eq, Debug)]
+pub(crate) struct AnchorRemark {
+    pub(crate) device: AccountId32,
+    /// Sequence number of batch among batches of device.
+    pub(crate) batch: u64,
+    pub(crate) root: Hash,
+    pub(crate) size: u64,
+}
+
+impl AnchorRemark {
+    /// Returns None if remark is not anchor.
+    pub(crate) fn parse(remark: &[u8]) -> Option<Self> {
+        let remark = from_utf8(remark).ok()?;
+        let mut parts = remark.split(':');
+        if parts.next()? != ANCHOR_PREFIX {
+            return None;
+        }
+        let anchor = Self {
+            device: parts.next()?.parse().ok()?,
+            batch: parts.next()?.parse().ok()?,
+            root: decode_hash(parts.next()?).ok()?,
+            size: parts.next()?.parse().ok()?,
+        };
+        parts.next().is_none().then_some(anchor)
+    }
+}
+
+impl fmt::Display for AnchorRemark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{ANCHOR_PREFIX}:{}:{}:{}:{}",
+            self.device,
+            self.batch,
+            encode_hash(&self.root),
+            self.size
+        )
+    }
+}
+
+#[derive(Subcommand)]
+pub(crate) enum AnchorCommand {
+    /// Print inclusion proof of anchored reading.
+    Proof {
+        /// Reading id returned by gateway.
+        id: i64,
+    },
+}
+
+/// Sibling of node on the path from leaf to root.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Sibling {
+    Left(String),
+    Right(String),
+}
+
+/// Reading together with everything needed to prove that it is part of anchored batch.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ReadingProof {
+    pub(crate) id: i64,
+    pub(crate) device: String,
+    pub(crate) data: String,
+    pub(crate) received_at: u64,
+    pub(crate) leaf: String,
+    pub(crate) proof: Vec<Sibling>,
+    pub(crate) root: String,
+    /// Batch number, it is not set for batches anchored before numbers were introduced.
+    pub(crate) batch: Option<u64>,
+    pub(crate) size: u64,
+    pub(crate) block_hash: String,
+    pub(crate) tx_hash: String,
+}
+
+impl ReadingProof {
+    /// Checks that reading hashes to leaf and proof leads from leaf to root.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        let leaf = decode_hash(&self.leaf)?;
+        if leaf != leaf_hash(self.received_at, &self.data) {
+            return Err("reading doesn't match leaf".into());
+        }
+        if proof_root(&leaf, &self.proof)? != decode_hash(&self.root)? {
+            return Err("proof doesn't lead to root".into());
+        }
+        Ok(())
+    }
+}
+
+impl App {
+    pub(crate) async fn anchor(&self, command: AnchorCommand) -> Result<(), Error> {
+        match command {
+            AnchorCommand::Proof { id } => {
+                let mut anchors = Anchors::new(&self.anchor.dsn).await?;
+                let proof = anchors
+                    .proof(id)
+                    .await?
+                    .ok_or_else(|| format!("reading {id} is not found or not anchored yet"))?;
+                proof.check()?;
+                println!("{}", serde_json::to_string_pretty(&proof)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Submits Merkle root of readings received since previous batch for every device.
+    pub(crate) async fn anchor_readings(&self, anchors: AnchorsPointer) -> Result<(), Error> {
+        let mut ticker = tokio::time::interval(Duration::from_secs(self.anchor.interval));
+        // The first tick completes immediately.
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            for device in &self.devices {
+                if let Err(e) = anchor_device(device, &anchors).await {
+                    error!("{}: failed to anchor readings: {e}", device.name);
+                }
+            }
+        }
+    }
+}
+
+async fn anchor_device(device: &DeviceClient, anchors: &AnchorsPointer) -> Result<(), Error> {
+    let address = device.peaq_client.address().to_string();
+    let (pending, number) = {
+        let mut anchors = anchors.lock().await;
+        (anchors.pending(&address).await?, anchors.next_batch(&address).await?)
+    };
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let leaves = pending.iter().map(|v| decode_hash(&v.leaf)).collect::<Result<Vec<_>, _>>()?;
+    let (root, proofs) = merkle_tree(&leaves);
+    let remark = AnchorRemark {
+        device: device.peaq_client.address(),
+        batch: number,
+        root,
+        size: leaves.len() as u64,
+    };
+    let (block_hash, tx_hash) =
+        device.peaq_client.remark_with_event(remark.to_string().into_bytes()).await?;
+    info!(
+        "{}: anchored {} readings in batch {} with root {} in {:?}",
+        device.name,
+        leaves.len(),
+        number,
+        encode_hash(&root),
+        tx_hash
+    );
+    let batch = NewBatch {
+        device: address,
+        number,
+        root,
+        block_hash,
+        tx_hash,
+    };
+    let readings = pending.iter().map(|v| v.id).zip(proofs);
+    anchors.lock().await.save_batch(&batch, readings).await
+}
+
+/// Hashes reading as Merkle leaf, prefix byte separates leaves from nodes.
+pub(crate) fn leaf_hash(received_at: u64, data: &str) -> Hash {
+    let mut buf = vec![0];
+    buf.extend(received_at.to_le_bytes());
+    buf.extend(data.as_bytes());
+    blake2_256(&buf)
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut buf = vec![1];
+    buf.extend(left);
+    buf.extend(right);
+    blake2_256(&buf)
+}
+
+/// Returns Merkle root and proofs of all leaves; odd node is carried to the next level as is.
+pub(crate) fn merkle_tree(leaves: &[Hash]) -> (Hash, Vec<Vec<Sibling>>) {
+    let mut proofs = vec![Vec::new(); leaves.len()];
+    // Every node of the level with indices of leaves under it.
+    let mut level: Vec<(Hash, Vec<usize>)> =
+        leaves.iter().enumerate().map(|(i, leaf)| (*leaf, vec![i])).collect();
+    while level.len() > 1 {
+        let mut next = Vec::with_capacity(level.len().div_ceil(2));
+        let mut nodes = level.into_iter();
+        while let Some((left, mut left_leaves)) = nodes.next() {
+            let Some((right, right_leaves)) = nodes.next() else {
+                next.push((left, left_leaves));
+                break;
+            };
+            for i in &left_leaves {
+                proofs[*i].push(Sibling::Right(encode_hash(&right)));
+            }
+            for i in &right_leaves {
+                proofs[*i].push(Sibling::Left(encode_hash(&left)));
+            }
+            left_leaves.extend(right_leaves);
+            next.push((node_hash(&left, &right), left_leaves));
+        }
+        level = next;
+    }
+    (level.first().map(|(root, _)| *root).unwrap_or_default(), proofs)
+}
+
+/// Returns root which proof leads to from leaf.
+pub(crate) fn proof_root(leaf: &Hash, proof: &[Sibling]) -> Result<Hash, Error> {
+    proof.iter().try_fold(*leaf, |node, sibling| {
+        Ok(match sibling {
+            Sibling::Left(hash) => node_hash(&decode_hash(hash)?, &node),
+            Sibling::Right(hash) => node_hash(&node, &decode_hash(hash)?),
+        })
+    })
+}
+
+pub(crate) fn encode_hash(hash: &Hash) -> String {
+    format!("0x{}", hex::encode(hash))
+}
+
+pub(crate) fn decode_hash(value: &str) -> Result<Hash, Error> {
+    let bytes = hex::decode(value.trim_start_matches("0x"))?;
+    bytes.try_into().map_err(|_| "hash must be 32 bytes".into())
+}
+
+struct NewBatch {
+    device: String,
+    number: u64,
+    root: Hash,
+    block_hash: H256,
+    tx_hash: H256,
+}
+
+#[derive(FromRow)]
+struct PendingReading {
+    id: i64,
+    leaf: String,
+}
+
+#[derive(FromRow)]
+struct ProofRow {
+    id: i64,
+    device: String,
+    data: String,
+    received_at: i64,
+    leaf: String,
+    proof: String,
+    root: String,
+    number: Option<i64>,
+    size: i64,
+    block_hash: String,
+    tx_hash: String,
+}
+
+/// Local store of device readings, anchored batches and inclusion proofs.
+pub(crate) struct Anchors {
+    conn: SqliteConnection,
+}
+
+impl Anchors {
+    pub(crate) async fn new(dsn: &str) -> Result<Self, Error> {
+        let mut conn = open_sqlite(dsn).await?;
+        let migrator = sqlx::migrate!("./migrations/anchors/");
+        migrator.run_direct(&mut conn).await?;
+        Ok(Self { conn })
+    }
+
+    /// Saves reading until the next batch and returns its id.
+    pub(crate) async fn add_reading(&mut self, device: &str, data: &str) -> Result<i64, Error> {
+        let received_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let leaf = encode_hash(&leaf_hash(received_at, data));
+        let id = sqlx::query_scalar(
+            "insert into readings (device, data, received_at, leaf) values (?1, ?2, ?3, ?4) \
+             returning id",
+        )
+        .bind(device)
+        .bind(data)
+        .bind(received_at as i64)
+        .bind(leaf)
+        .fetch_one(&mut self.conn)
+        .await?;
+        Ok(id)
+    }
+
+    /// Returns number of the next batch of device.
+    async fn next_batch(&mut self, device: &str) -> Result<u64, Error> {
+        let number: i64 = sqlx::query_scalar(
+            "select coalesce(max(number), 0) + 1 from batches where device = ?1",
+        )
+        .bind(device)
+        .fetch_one(&mut self.conn)
+        .await?;
+        Ok(number as u64)
+    }
+
+    /// Returns readings of device which are not anchored yet in order they were received.
+    async fn pending(&mut self, device: &str) -> Result<Vec<PendingReading>, Error> {
+        let readings = sqlx::query_as(
+            "select id, leaf from readings where device = ?1 and batch_id is null order by id",
+        )
+        .bind(device)
+        .fetch_all(&mut self.conn)
+        .await?;
+        Ok(readings)
+    }
+
+    async fn save_batch(
+        &mut self,
+        batch: &NewBatch,
+        readings: impl Iterator<Item = (i64, Vec<Sibling>)>,
+    ) -> Result<(), Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let readings: Vec<(i64, Vec<Sibling>)> = readings.collect();
+        let mut db_tx = self.conn.begin().await?;
+        let batch_id: i64 = sqlx::query_scalar(
+            r#"
+                insert into batches (device, number, root, size, block_hash, tx_hash, created_at)
+                values (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                returning id
+            "#,
+        )
+        .bind(&batch.device)
+        .bind(batch.number as i64)
+        .bind(encode_hash(&batch.root))
+        .bind(readings.len() as i64)
+        .bind(format!("{:?}", batch.block_hash))
+        .bind(format!("{:?}", batch.tx_hash))
+        .bind(now)
+        .fetch_one(&mut *db_tx)
+        .await?;
+        for (index, (id, proof)) in readings.iter().enumerate() {
+            sqlx::query(
+                "update readings set batch_id = ?2, leaf_index = ?3, proof = ?4 where id = ?1",
+            )
+            .bind(id)
+            .bind(batch_id)
+            .bind(index as i64)
+            .bind(serde_json::to_string(proof)?)
+            .execute(&mut *db_tx)
+            .await?;
+        }
+        db_tx.commit().await?;
+        Ok(())
+    }
+
+    /// Returns proof of reading, None if reading is unknown or not anchored yet.
+    pub(crate) async fn proof(&mut self, id: i64) -> Result<Option<ReadingProof>, Error> {
+        let row: Option<ProofRow> = sqlx::query_as(
+            r#"
+                select r.id, r.device, r.data, r.received_at, r.leaf, r.proof,
+                  b.root, b.number, b.size, b.block_hash, b.tx_hash
+                from readings r
+                join batches b on b.id = r.batch_id
+                where r.id = ?1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut self.conn)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        Ok(Some(ReadingProof {
+            id: row.id,
+            device: row.device,
+            data: row.data,
+            received_at: row.received_at as u64,
+            leaf: row.leaf,
+            proof: serde_json::from_str(&row.proof)?,
+            root: row.root,
+            batch: row.number.map(|v| v as u64),
+            size: row.size as u64,
+            block_hash: row.block_hash,
+            tx_hash: row.tx_hash,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDb;
+
+    #[test]
+    fn merkle() {
+        for size in 1..=9 {
+            let leaves: Vec<Hash> = (0..size).map(|i| leaf_hash(i, &format!("{i}"))).collect();
+            let (root, proofs) = merkle_tree(&leaves);
+            for (leaf, proof) in leaves.iter().zip(&proofs) {
+                assert_eq!(proof_root(leaf, proof).unwrap(), root, "size {size}");
+            }
+            let other = leaf_hash(size, "other");
+            assert_ne!(proof_root(&other, &proofs[0]).unwrap(), root);
+        }
+        let leaves = [leaf_hash(1, "a"), leaf_hash(2, "b"), leaf_hash(3, "c")];
+        let (root, proofs) = merkle_tree(&leaves);
+        assert_eq!(root, node_hash(&node_hash(&leaves[0], &leaves[1]), &leaves[2]));
+        assert_eq!(
+            proofs[1],
+            [
+                Sibling::Left(encode_hash(&leaves[0])),
+                Sibling::Right(encode_hash(&leaves[2]))
+            ]
+        );
+        assert!(proofs[2].len() == 1);
+        assert_ne!(leaf_hash(1, "a"), leaf_hash(2, "a"));
+    }
+
+    #[test]
+    fn anchor_remark() {
+        let remark = AnchorRemark {
+            device: AccountId32([1; 32]),
+            batch: 3,
+            root: [2; 32],
+            size: 10,
+        };
+        assert_eq!(AnchorRemark::parse(remark.to_string().as_bytes()), Some(remark));
+        let device = AccountId32([1; 32]);
+        let root = encode_hash(&[2; 32]);
+        for remark in [
+            format!("staex-ioa-purchase:{device}:3:{root}:10"),
+            format!("{ANCHOR_PREFIX}:{device}:3:{root}"),
+            format!("{ANCHOR_PREFIX}:{device}:3:{root}:10:1"),
+            format!("{ANCHOR_PREFIX}:{device}:3:0x02:10"),
+            format!("{ANCHOR_PREFIX}:device:3:{root}:10"),
+        ] {
+            assert_eq!(AnchorRemark::parse(remark.as_bytes()), None, "{remark}");
+        }
+    }
+
+    #[tokio::test]
+    async fn proofs() {
+        let db = TempDb::new("anchors");
+        let mut anchors = Anchors::new(&db.dsn()).await.unwrap();
+        let first = anchors.add_reading("device", r#"{"t":1}"#).await.unwrap();
+        let second = anchors.add_reading("device", r#"{"t":2}"#).await.unwrap();
+        anchors.add_reading("other", r#"{"t":3}"#).await.unwrap();
+        assert!(anchors.proof(first).await.unwrap().is_none());
+
+        assert_eq!(anchors.next_batch("device").await.unwrap(), 1);
+        let pending = anchors.pending("device").await.unwrap();
+        assert_eq!(pending.iter().map(|v| v.id).collect::<Vec<_>>(), [first, second]);
+        let leaves: Vec<Hash> = pending.iter().map(|v| decode_hash(&v.leaf).unwrap()).collect();
+        let (root, proofs) = merkle_tree(&leaves);
+        let batch = NewBatch {
+            device: "device".to_string(),
+            number: 1,
+            root,
+            block_hash: H256::zero(),
+            tx_hash: H256::zero(),
+        };
+        anchors.save_batch(&batch, pending.iter().map(|v| v.id).zip(proofs)).await.unwrap();
+        assert!(anchors.pending("device").await.unwrap().is_empty());
+        assert_eq!(anchors.next_batch("device").await.unwrap(), 2);
+        assert_eq!(anchors.next_batch("other").await.unwrap(), 1);
+        assert_eq!(anchors.pending("other").await.unwrap().len(), 1);
+
+        let proof = anchors.proof(second).await.unwrap().unwrap();
+        assert_eq!(proof.data, r#"{"t":2}"#);
+        assert_eq!((proof.batch, proof.size), (Some(1), 2));
+        proof.check().unwrap();
+        let mut altered = proof;
+        altered.data = r#"{"t":0}"#.to_string();
+        assert!(altered.check().is_err());
+    }
+}
@@ -0,0 +1,570 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crypto_secretbox::aead::{rand_core::RngCore, OsRng};
+use log::info;
+use peaq_client::{peaq_gen, Client, OwnedAssignments, OwnedEntities, ENTITY_ID_LENGTH};
+use serde::{Deserialize, Serialize};
+use subxt::utils::{AccountId32, MultiAddress};
+
+use crate::{
+    amount::Planck,
+    decommission::confirm,
+    device::{self, Device},
+    onboard::parse_group,
+    payments::{is_valid_reference, GrantResponse, Memo, PinRange, PinResponse, PurchaseKind},
+    seal, App, Error, DEVICE_ATTRIBUTE_NAME,
+};
+
+const GRANT_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Purchase options from command line.
+pub(crate) struct Buy {
+    pub(crate) device_address: AccountId32,
+    pub(crate) kind: PurchaseKind,
+    /// Pinned range, required for pins.
+    pub(crate) range: Option<PinRange>,
+    pub(crate) indexer: Option<String>,
+    /// Payments API of seller, grant expiry and renewals are known only with it.
+    pub(crate) payments: Option<String>,
+    pub(crate) reference: Option<String>,
+    /// Device which account pays.
+    pub(crate) device: String,
+    pub(crate) receipts_dir: PathBuf,
+    pub(crate) timeout: Duration,
+    pub(crate) yes: bool,
+}
+
+/// Local purchase record, it is written after payment and updated when access is granted.
+#[derive(Serialize, Deserialize)]
+struct Receipt {
+    reference: String,
+    kind: String,
+    device: String,
+    payer: String,
+    price: Planck,
+    fee: Planck,
+    memo: String,
+    memo_tx: String,
+    payment_tx: String,
+    status: ReceiptStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    permissions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    range: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+    created_at: u64,
+    updated_at: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ReceiptStatus {
+    Paid,
+    Granted,
+    Pinned,
+}
+
+/// Device from indexer API, other fields are not needed.
+#[derive(Deserialize)]
+struct IndexedDevice {
+    address: String,
+    version: String,
+    device: serde_json::Value,
+}
+
+impl App {
+    /// Pays device price with memo and waits until seller grants access or pins data.
+    pub(crate) async fn buy(&self, opts: &Buy) -> Result<(), Error> {
+        if (opts.kind == PurchaseKind::Pin) != opts.range.is_some() {
+            return Err("pin range is required for pins only".into());
+        }
+        let reference = match &opts.reference {
+            Some(reference) => reference.clone(),
+            None => {
+                let mut bytes = [0u8; 8];
+                OsRng.fill_bytes(&mut bytes);
+                hex::encode(bytes)
+            }
+        };
+        if reference.is_empty() || !is_valid_reference(&reference) {
+            return Err("reference must have at most 64 letters, digits, '-' and '_'".into());
+        }
+        let receipt_path = opts.receipts_dir.join(format!("{reference}.json"));
+        if receipt_path.exists() {
+            return Err(format!("receipt {} already exists", receipt_path.display()).into());
+        }
+
+        let (device_address, device) =
+            self.find_device(&opts.device_address, opts.indexer.as_deref()).await?;
+        let (price_access, price_pin) = device.prices(&self.token)?;
+        let price = match opts.kind {
+            PurchaseKind::Access => price_access,
+            PurchaseKind::Pin => price_pin,
+        };
+        let peaq_client = &self.device(&opts.device)?.peaq_client;
+        let payer = peaq_client.address();
+        let memo = Memo {
+            kind: opts.kind,
+            device: device_address.clone(),
+            reference: reference.clone(),
+            range: opts.range,
+        };
+        let remark_call = peaq_gen::api::tx().system().remark(memo.to_string().into_bytes());
+        let transfer_call = peaq_gen::api::tx()
+            .balances()
+            .transfer_keep_alive(MultiAddress::Id(device_address.clone()), price.0);
+        let remark_fee = peaq_client.estimate_fee(&remark_call).await?;
+        let transfer_fee = peaq_client.estimate_fee(&transfer_call).await?;
+        let fee = Planck(remark_fee.saturating_add(transfer_fee));
+        let total = Planck(price.0.saturating_add(fee.0));
+        let balance = Planck(peaq_client.get_balance(&payer).await?);
+
+        eprintln!("Device: {}", device_address);
+        eprintln!("Purchase: {} ({})", opts.kind, reference);
+        if let Some(range) = &opts.range {
+            eprintln!("Range: {range}");
+        }
+        eprintln!("Price: {}", self.token.format(price));
+        eprintln!("Fees: {}", self.token.format(fee));
+        eprintln!("Total: {}", self.token.format(total));
+        eprintln!("Balance of {}: {}", payer, self.token.format(balance));
+        if balance.0 < total.0 {
+            return Err(format!("balance of {payer} is less than total cost").into());
+        }
+        // Grants before payment are compared with grants after it, since renewal only extends them.
+        let grants = match (&opts.payments, opts.kind) {
+            (Some(url), PurchaseKind::Access) => fetch_grants(url, &payer).await?,
+            _ => Vec::new(),
+        };
+        // Membership is read from storage, so checking it costs no fee.
+        let groups = match opts.kind {
+            PurchaseKind::Access => self.client.get_user_groups(&payer.0).await?,
+            PurchaseKind::Pin => Vec::new(),
+        };
+        if grants.iter().any(|v| {
+            v.device == device_address.to_string()
+                && v.status == "active"
+                && v.expires_block.is_none()
+                && v.expires_at.is_none()
+        }) {
+            return Err(format!("{payer} already has access to {device_address} forever").into());
+        }
+        if !opts.yes && !confirm(&format!("Pay {}?", self.token.format(total)))? {
+            eprintln!("Purchase is cancelled");
+            return Ok(());
+        }
+
+        // Memo goes first, payment watcher matches it with the next payment of the same account.
+        let memo_tx = peaq_client.remark(memo.to_string().into_bytes()).await?;
+        let payment_tx = peaq_client.transfer_keep_alive(price.0, device_address.clone()).await?;
+        info!(
+            "paid {} to {} in {:?} transaction",
+            self.token.format(price),
+            device_address,
+            payment_tx
+        );
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let mut receipt = Receipt {
+            reference: reference.clone(),
+            kind: opts.kind.to_string(),
+            device: device_address.to_string(),
+            payer: payer.to_string(),
+            price,
+            fee,
+            memo: memo.to_string(),
+            memo_tx: format!("{memo_tx:?}"),
+            payment_tx: format!("{payment_tx:?}"),
+            status: ReceiptStatus::Paid,
+            group: None,
+            permissions: Vec::new(),
+            range: opts.range.map(|v| v.to_string()),
+            expires_block: None,
+            expires_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+        std::fs::create_dir_all(&opts.receipts_dir)?;
+        write_receipt(&receipt_path, &receipt)?;
+        if opts.kind == PurchaseKind::Pin {
+            return self.wait_for_pin(opts, &payer, &device_address, receipt, &receipt_path).await;
+        }
+        eprintln!("Paid in {:?} transaction, waiting for access", payment_tx);
+        let not_granted = || {
+            format!(
+                "access is not granted in {} seconds; receipt is saved to {}, \
+                 contact seller with reference {}",
+                opts.timeout.as_secs(),
+                receipt_path.display(),
+                reference
+            )
+        };
+        let grant = match &opts.payments {
+            Some(url) => Some(
+                wait_for_grant(url, &payer, &device_address, &grants, opts.timeout)
+                    .await?
+                    .ok_or_else(not_granted)?,
+            ),
+            None => None,
+        };
+        let group = grant.as_ref().map(|v| parse_group(&v.access_group)).transpose()?;
+        // Without payments API access group is unknown, so it is any group payer is new in;
+        // renewal keeps payer in the same group, so it is seen only in payments API.
+        let Some(group) = wait_for_group(&self.client, &payer, opts.timeout, |v| match group {
+            Some(group) => *v == group,
+            None => !groups.contains(v),
+        })
+        .await?
+        else {
+            return Err(not_granted().into());
+        };
+        let access_group = format!("0x{}", hex::encode(group));
+        let permissions = match &grant {
+            Some(grant) => {
+                let owner: AccountId32 = grant.owner.parse()?;
+                let owned = self.client.get_owned_entities(&owner).await?;
+                let assignments = self.client.get_owned_assignments(&owner).await?;
+                permission_names(&owned, &assignments, &payer.0, &group)
+            }
+            None => Vec::new(),
+        };
+        receipt.status = ReceiptStatus::Granted;
+        receipt.group = Some(access_group.clone());
+        receipt.permissions = permissions;
+        receipt.expires_block = grant.as_ref().and_then(|v| v.expires_block);
+        receipt.expires_at = grant.as_ref().and_then(|v| v.expires_at);
+        receipt.updated_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        write_receipt(&receipt_path, &receipt)?;
+        eprintln!("Access is granted: group {access_group}");
+        match (receipt.expires_block, receipt.expires_at) {
+            (Some(block), _) => eprintln!("Expires at block {block}"),
+            (_, Some(time)) => eprintln!("Expires at {time} unix seconds"),
+            _ => {}
+        }
+        if !receipt.permissions.is_empty() {
+            eprintln!("Permissions: {}", receipt.permissions.join(", "));
+        }
+        eprintln!("Receipt: {}", receipt_path.display());
+        Ok(())
+    }
+
+    /// Decrypts private fields of device with account of local device and prints them.
+    pub(crate) async fn reveal(
+        &self,
+        device_address: &AccountId32,
+        indexer: Option<&str>,
+        device: &str,
+    ) -> Result<(), Error> {
+        let key = self
+            .device(device)?
+            .peaq_client
+            .signer()
+            .sr25519()
+            .ok_or_else(|| format!("device {device} must have local sr25519 signer"))?
+            .clone();
+        let (device_address, device) = self.find_device(device_address, indexer).await?;
+        let Device::V2(device) = device else {
+            return Err(format!("device {device_address} has no private fields").into());
+        };
+        let sealed = device
+            .private
+            .ok_or_else(|| format!("device {device_address} has no private fields"))?;
+        let fields = seal::open(&sealed, &key)?;
+        println!("{}", serde_json::to_string_pretty(&fields)?);
+        Ok(())
+    }
+
+    /// Returns current device address and device from indexer or chain.
+    async fn find_device(
+        &self,
+        address: &AccountId32,
+        indexer: Option<&str>,
+    ) -> Result<(AccountId32, Device), Error> {
+        if let Some(url) = indexer {
+            // Rotated device is redirected to its current address.
+            let url = format!("{}/devices/{}", url.trim_end_matches('/'), address);
+            let response = reqwest::get(&url).await?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Err(format!("device {address} is not found by indexer").into());
+            }
+            return response.error_for_status()?.json::<IndexedDevice>().await?.decode();
+        }
+        let attribute = self
+            .client
+            .get_owned_attributes(address)
+            .await?
+            .into_iter()
+            .find(|(did_account, attribute)| {
+                did_account == address && attribute.name == DEVICE_ATTRIBUTE_NAME.as_bytes()
+            })
+            .map(|(_, attribute)| attribute)
+            .ok_or_else(|| format!("device {address} is not found on chain"))?;
+        Ok((address.clone(), device::decode(&attribute.value)?))
+    }
+
+    // Pins are not on chain, so they can be seen only in indexer which serves pin ledger.
+    async fn wait_for_pin(
+        &self,
+        opts: &Buy,
+        payer: &AccountId32,
+        device_address: &AccountId32,
+        mut receipt: Receipt,
+        receipt_path: &Path,
+    ) -> Result<(), Error> {
+        let Some(indexer) = &opts.indexer else {
+            eprintln!("Paid, check pin with --indexer; receipt: {}", receipt_path.display());
+            return Ok(());
+        };
+        eprintln!("Paid, waiting for pin");
+        let url = format!(
+            "{}/devices/{}/pins?buyer={}",
+            indexer.trim_end_matches('/'),
+            device_address,
+            payer
+        );
+        let deadline = Instant::now() + opts.timeout;
+        loop {
+            let pins: Vec<PinResponse> =
+                reqwest::get(&url).await?.error_for_status()?.json().await?;
+            if let Some(pin) =
+                pins.into_iter().find(|v| v.reference.as_ref() == Some(&receipt.reference))
+            {
+                receipt.status = ReceiptStatus::Pinned;
+                receipt.expires_block = pin.expires_block;
+                receipt.expires_at = pin.expires_at;
+                receipt.updated_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                write_receipt(receipt_path, &receipt)?;
+                eprintln!("Data is pinned");
+                eprintln!("Receipt: {}", receipt_path.display());
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(format!(
+                    "pin is not recorded in {} seconds; receipt is saved to {}, \
+                     contact seller with reference {}",
+                    opts.timeout.as_secs(),
+                    receipt_path.display(),
+                    receipt.reference
+                )
+                .into());
+            }
+            tokio::time::sleep(GRANT_POLL_INTERVAL).await;
+        }
+    }
+}
+
+impl IndexedDevice {
+    fn decode(self) -> Result<(AccountId32, Device), Error> {
+        // Indexer returns device without version key, it is restored to validate device as usual.
+        let data = serde_json::to_vec(&serde_json::json!({ self.version: self.device }))?;
+        Ok((self.address.parse()?, device::decode(&data)?))
+    }
+}
+
+async fn fetch_grants(url: &str, payer: &AccountId32) -> Result<Vec<GrantResponse>, Error> {
+    let url = format!("{}/grants/{}", url.trim_end_matches('/'), payer);
+    Ok(reqwest::get(&url).await?.error_for_status()?.json().await?)
+}
+
+// Seller renews existing grant without RBAC transaction, so grant is awaited in payments API.
+async fn wait_for_grant(
+    url: &str,
+    payer: &AccountId32,
+    device_address: &AccountId32,
+    before: &[GrantResponse],
+    timeout: Duration,
+) -> Result<Option<GrantResponse>, Error> {
+    let device = device_address.to_string();
+    let deadline = Instant::now() + timeout;
+    loop {
+        let grant = fetch_grants(url, payer).await?.into_iter().find(|grant| {
+            let previous = before.iter().find(|v| v.access_group == grant.access_group);
+            grant.device == device && is_extended(previous, grant)
+        });
+        if grant.is_some() {
+            return Ok(grant);
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        tokio::time::sleep(GRANT_POLL_INTERVAL).await;
+    }
+}
+
+// Membership is polled in storage, since fetching it with RBAC call costs a transaction.
+async fn wait_for_group(
+    client: &Client,
+    payer: &AccountId32,
+    timeout: Duration,
+    accept: impl Fn(&[u8; ENTITY_ID_LENGTH]) -> bool,
+) -> Result<Option<[u8; ENTITY_ID_LENGTH]>, Error> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let group = client.get_user_groups(&payer.0).await?.into_iter().find(&accept);
+        if group.is_some() {
+            return Ok(group);
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        tokio::time::sleep(GRANT_POLL_INTERVAL).await;
+    }
+}
+
+/// Returns names of enabled permissions of user through its roles and roles of group.
+fn permission_names(
+    owned: &OwnedEntities,
+    assignments: &OwnedAssignments,
+    user: &[u8; ENTITY_ID_LENGTH],
+    group: &[u8; ENTITY_ID_LENGTH],
+) -> Vec<String> {
+    let group_roles = assignments.role_groups.iter().filter(|v| v.1 == *group).map(|v| v.0);
+    let user_roles = assignments.role_users.iter().filter(|v| v.1 == *user).map(|v| v.0);
+    let roles: Vec<_> = group_roles.chain(user_roles).collect();
+    let mut names: Vec<String> = owned
+        .permissions
+        .iter()
+        .filter(|permission| {
+            permission.enabled
+                && assignments
+                    .permission_roles
+                    .iter()
+                    .any(|v| v.0 == permission.id && roles.contains(&v.1))
+        })
+        .map(|v| String::from_utf8_lossy(&v.name).to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Returns true if grant is active and expires later than the same grant before payment.
+fn is_extended(before: Option<&GrantResponse>, after: &GrantResponse) -> bool {
+    if after.status != "active" {
+        return false;
+    }
+    match before.filter(|v| v.status == "active") {
+        Some(before) => {
+            after.expires_block > before.expires_block || after.expires_at > before.expires_at
+        }
+        None => true,
+    }
+}
+
+fn write_receipt(path: &Path, receipt: &Receipt) -> Result<(), Error> {
+    std::fs::write(path, serde_json::to_string_pretty(receipt)?)
+        .map_err(|e| format!("failed to write {}: {e}", path.display()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::agung;
+
+    #[test]
+    fn indexed_device() {
+        let address = AccountId32([1; 32]);
+        let indexed: IndexedDevice = serde_json::from_value(serde_json::json!({
+            "address": address.to_string(),
+            "version": "v2",
+            "device": {
+                "data_type": "temperature-sensor",
+                "location": "40.19,44.55",
+                "price_access": "1500",
+                "price_pin": "2000",
+            },
+            "updated_at": 0,
+        }))
+        .unwrap();
+        let (decoded_address, device) = indexed.decode().unwrap();
+        assert_eq!(decoded_address, address);
+        let token = agung();
+        let (price_access, _) = device.prices(&token).unwrap();
+        assert_eq!(price_access, Planck(1500));
+
+        let invalid: IndexedDevice = serde_json::from_value(serde_json::json!({
+            "address": address.to_string(),
+            "version": "v2",
+            "device": {"data_type": "temperature-sensor"},
+        }))
+        .unwrap();
+        assert!(invalid.decode().is_err());
+    }
+
+    #[test]
+    fn extended_grant() {
+        let grant = |status: &str, expires_at: Option<u64>| GrantResponse {
+            access_group: format!("0x{}", "ab".repeat(32)),
+            owner: AccountId32([9; 32]).to_string(),
+            device: AccountId32([1; 32]).to_string(),
+            status: status.to_string(),
+            expires_block: None,
+            expires_at,
+            remaining_blocks: None,
+            remaining_seconds: None,
+        };
+        let active = grant("active", Some(1000));
+        assert!(is_extended(None, &active));
+        assert!(!is_extended(None, &grant("revoked", Some(1000))));
+        // Renewal is seen only when expiry moves.
+        assert!(!is_extended(Some(&active), &active));
+        assert!(is_extended(Some(&active), &grant("active", Some(2000))));
+        // Revoked grant is assigned again.
+        let revoked = grant("revoked", Some(1000));
+        assert!(!is_extended(Some(&revoked), &revoked));
+        assert!(is_extended(Some(&revoked), &grant("active", Some(3000))));
+
+        let response: GrantResponse = serde_json::from_value(serde_json::json!({
+            "access_group": active.access_group,
+            "owner": active.owner,
+            "device": active.device,
+            "status": "active",
+            "expires_at": 1000,
+            "remaining_seconds": 10,
+        }))
+        .unwrap();
+        assert!(!is_extended(Some(&active), &response));
+    }
+
+    #[test]
+    fn permissions() {
+        let record = |id: u8, name: &str, enabled: bool| peaq_client::RBACRecord {
+            id: [id; ENTITY_ID_LENGTH],
+            name: name.as_bytes().to_vec(),
+            enabled,
+        };
+        let owned = OwnedEntities {
+            permissions: vec![
+                record(1, "mqtt_access", true),
+                record(2, "disabled", false),
+                record(3, "other_group", true),
+                record(4, "user_role", true),
+            ],
+            roles: Vec::new(),
+            groups: Vec::new(),
+        };
+        let (user, group, other) = ([7; 32], [5; 32], [6; 32]);
+        let assignments = OwnedAssignments {
+            permission_roles: vec![
+                ([1; 32], [10; 32]),
+                ([2; 32], [10; 32]),
+                ([3; 32], [11; 32]),
+                ([4; 32], [12; 32]),
+            ],
+            role_groups: vec![([10; 32], group), ([11; 32], other)],
+            role_users: vec![([12; 32], user)],
+            ..Default::default()
+        };
+        assert_eq!(
+            permission_names(&owned, &assignments, &user, &group),
+            ["mqtt_access", "user_role"]
+        );
+        assert!(permission_names(&owned, &assignments, &[8; 32], &[9; 32]).is_empty());
+    }
+}
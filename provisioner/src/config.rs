@@ -1,15 +1,60 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    io::ErrorKind,
+};
 
 use log::Level;
+use subxt::utils::AccountId32;
+
+use crate::{
+    amount::{Amount, Token},
+    device::{self, Encoding, MAX_ADDITIONAL_PROPERTIES},
+    location::MAX_GEOHASH_LEVEL,
+    onboard::parse_group,
+    payments::Period,
+    Error,
+};
+
+// Environment variables with this prefix override config fields,
+// nested fields are separated with double underscore,
+// e.g. PROVISIONER_INDEXER__PORT overrides indexer.port.
+const ENV_PREFIX: &str = "PROVISIONER_";
+const ENV_SEPARATOR: &str = "__";
+// Max grid size and offset radius in meters.
+const MAX_LOCATION_PRIVACY_SIZE: u32 = 1_000_000;
+
+// Secret URIs without phrase are derived from this well-known development phrase.
+const DEV_PHRASE: &str = "bottom drive obey lake curtain smoke basket hold race lonely fit walk";
+
+/// Name of device from device section, fleet devices have their own names.
+pub(crate) const MAIN_DEVICE_NAME: &str = "main";
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct Config {
     pub(crate) log_level: String,
     pub(crate) rpc_url: String,
     pub(crate) signer: Signer,
     pub(crate) faucet: Faucet,
+    #[serde(default)]
+    pub(crate) top_up: TopUp,
     pub(crate) device: Device,
+    /// Signers of fleet devices by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) signers: BTreeMap<String, Signer>,
+    /// Fleet devices which are synced together with main device.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) devices: Vec<FleetDevice>,
     pub(crate) indexer: Indexer,
+    #[serde(default)]
+    pub(crate) payments: Payments,
+    #[serde(default)]
+    pub(crate) gateway: Gateway,
+    #[serde(default)]
+    pub(crate) anchor: Anchor,
+    #[serde(default)]
+    pub(crate) heartbeat: Heartbeat,
 }
 
 impl Default for Config {
@@ -19,37 +64,432 @@ impl Default for Config {
             rpc_url: "wss://rpcpc1-qa.agung.peaq.network".to_string(),
             signer: Default::default(),
             faucet: Default::default(),
+            top_up: Default::default(),
             device: Default::default(),
+            signers: Default::default(),
+            devices: Default::default(),
             indexer: Indexer::default(),
+            payments: Payments::default(),
+            gateway: Gateway::default(),
+            anchor: Anchor::default(),
+            heartbeat: Heartbeat::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads config file, applies environment variable overrides and validates result.
+    pub(crate) fn load(path: &str) -> Result<Self, Error> {
+        let buf = match std::fs::read_to_string(path) {
+            Ok(buf) => buf,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {
+                return Err(format!(
+                    "config file {path} is not found; use `provisioner config` to get default one"
+                )
+                .into())
+            }
+            Err(e) => return Err(format!("failed to read config file {path}: {e}").into()),
+        };
+        let mut table: toml::Table =
+            toml::from_str(&buf).map_err(|e| format!("failed to parse config file {path}: {e}"))?;
+        apply_env(&mut table, std::env::vars())?;
+        let cfg: Config = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| format!("invalid config file {path}: {e}"))?;
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), ConfigErrors> {
+        let mut errors = ConfigErrors::default();
+        if self.log_level.parse::<Level>().is_err() {
+            errors.push("log_level", format!("unknown log level {}", self.log_level));
+        }
+        if !self.rpc_url.starts_with("ws://") && !self.rpc_url.starts_with("wss://") {
+            errors.push("rpc_url", "only ws:// and wss:// urls are supported");
+        }
+        let dev_network = is_dev_network(&self.rpc_url);
+        self.signer.validate("signer", dev_network, &mut errors);
+        self.faucet.signer.validate("faucet.signer", dev_network, &mut errors);
+        self.device.validate("device", &mut errors);
+        for (name, signer) in &self.signers {
+            signer.validate(&format!("signers.{name}"), dev_network, &mut errors);
+        }
+        self.validate_fleet(&mut errors);
+        if !self.indexer.dsn.starts_with("sqlite:") {
+            errors.push("indexer.dsn", "only sqlite: dsn is supported");
+        }
+        if !self.faucet.server.dsn.starts_with("sqlite:") {
+            errors.push("faucet.server.dsn", "only sqlite: dsn is supported");
+        }
+        if !self.payments.dsn.starts_with("sqlite:") {
+            errors.push("payments.dsn", "only sqlite: dsn is supported");
+        }
+        for (field, period) in [
+            ("payments.access_period", self.payments.access_period),
+            ("payments.pin_period", self.payments.pin_period),
+        ] {
+            if let Some(Period::Blocks(0) | Period::Seconds(0)) = period {
+                errors.push(field, "period must be positive");
+            }
+        }
+        if let Some(dsn) = &self.indexer.payments_dsn {
+            if !dsn.starts_with("sqlite:") {
+                errors.push("indexer.payments_dsn", "only sqlite: dsn is supported");
+            }
+        }
+        for (field, value) in [
+            ("gateway.challenge_ttl", self.gateway.challenge_ttl),
+            ("gateway.session_ttl", self.gateway.session_ttl),
+        ] {
+            if value == 0 {
+                errors.push(field, "ttl must be positive");
+            }
+        }
+        if self.gateway.rbac_refresh == 0 {
+            errors.push("gateway.rbac_refresh", "interval must be positive");
+        }
+        if !self.anchor.dsn.starts_with("sqlite:") {
+            errors.push("anchor.dsn", "only sqlite: dsn is supported");
+        }
+        if self.anchor.interval == 0 {
+            errors.push("anchor.interval", "interval must be positive");
+        }
+        if self.heartbeat.interval == 0 {
+            errors.push("heartbeat.interval", "interval must be positive");
+        }
+        if self.indexer.max_heartbeat_interval == 0 {
+            errors.push("indexer.max_heartbeat_interval", "interval must be positive");
+        }
+        if self.top_up.source == TopUpSource::Server
+            && !self.top_up.url.starts_with("http://")
+            && !self.top_up.url.starts_with("https://")
+        {
+            errors.push("top_up.url", "only http:// and https:// urls are supported");
+        }
+        errors.into_result()
+    }
+
+    /// Returns signer of main or fleet device.
+    pub(crate) fn device_signer(&self, name: &str) -> Result<&Signer, Error> {
+        if name == MAIN_DEVICE_NAME {
+            return Ok(&self.signer);
+        }
+        let fleet_device = self
+            .devices
+            .iter()
+            .find(|device| device.name == name)
+            .ok_or_else(|| format!("device {name} is not found"))?;
+        self.signers
+            .get(&fleet_device.signer)
+            .ok_or_else(|| format!("signer {} is not found", fleet_device.signer).into())
+    }
+
+    /// Validates amounts which can be checked only with network token.
+    pub(crate) fn validate_amounts(&self, token: &Token) -> Result<(), ConfigErrors> {
+        let mut errors = ConfigErrors::default();
+        let mut amounts = vec![
+            ("device.attributes.price_access".to_string(), &self.device.attributes.price_access),
+            ("device.attributes.price_pin".to_string(), &self.device.attributes.price_pin),
+            ("faucet.amount".to_string(), &self.faucet.amount),
+            ("faucet.server.daily_budget".to_string(), &self.faucet.server.daily_budget),
+            ("faucet.server.max_balance".to_string(), &self.faucet.server.max_balance),
+            ("faucet.server.low_funds".to_string(), &self.faucet.server.low_funds),
+        ];
+        for (i, fleet_device) in self.devices.iter().enumerate() {
+            let attributes = &fleet_device.device.attributes;
+            let field = format!("devices[{i}].device.attributes");
+            amounts.push((format!("{field}.price_access"), &attributes.price_access));
+            amounts.push((format!("{field}.price_pin"), &attributes.price_pin));
+        }
+        for (field, amount) in amounts {
+            if let Err(e) = amount.to_planck(token) {
+                errors.push(&field, e);
+            }
+        }
+        errors.into_result()
+    }
+
+    fn validate_fleet(&self, errors: &mut ConfigErrors) {
+        let mut names = HashSet::new();
+        // Device is stored as account attribute, so devices can't share signer.
+        let mut signers = HashSet::new();
+        for (i, fleet_device) in self.devices.iter().enumerate() {
+            let field = format!("devices[{i}]");
+            if fleet_device.name.is_empty() || fleet_device.name == MAIN_DEVICE_NAME {
+                errors.push(&field, format!("name must be set and differ from {MAIN_DEVICE_NAME}"));
+            } else if !names.insert(&fleet_device.name) {
+                errors.push(&field, format!("duplicate device name {}", fleet_device.name));
+            }
+            if !self.signers.contains_key(&fleet_device.signer) {
+                errors.push(&field, format!("signer {} is not found", fleet_device.signer));
+            } else if !signers.insert(&fleet_device.signer) {
+                errors.push(
+                    &field,
+                    format!("signer {} is used by other device", fleet_device.signer),
+                );
+            }
+            fleet_device.device.validate(&format!("{field}.device"), errors);
+        }
+    }
+}
+
+/// All problems found in config, reported at once.
+#[derive(Default)]
+pub(crate) struct ConfigErrors(Vec<String>);
+
+impl ConfigErrors {
+    fn push(&mut self, field: &str, message: impl fmt::Display) {
+        self.0.push(format!("{field}: {message}"));
+    }
+
+    fn into_result(self) -> Result<(), Self> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
         }
     }
 }
 
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid config:")?;
+        for error in &self.0 {
+            write!(f, "\n  - {error}")?;
+        }
+        Ok(())
+    }
+}
+
+// Error is returned from main and printed with Debug, so show the same report.
+impl fmt::Debug for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+fn apply_env(
+    table: &mut toml::Table,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<(), Error> {
+    // Fields missing from config file take their type from default config.
+    let defaults = toml::Table::try_from(Config::default())?;
+    for (key, raw) in vars {
+        let Some(path) = key.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let path: Vec<String> = path.split(ENV_SEPARATOR).map(|v| v.to_lowercase()).collect();
+        let (field, parents) = path.split_last().ok_or_else(|| format!("{key}: empty field"))?;
+        let mut current = &mut *table;
+        for parent in parents {
+            current = current
+                .entry(parent)
+                .or_insert_with(|| toml::Value::Table(Default::default()))
+                .as_table_mut()
+                .ok_or_else(|| format!("{key}: {parent} is not a table"))?;
+        }
+        let default = parents
+            .iter()
+            .try_fold(&defaults, |table, parent| table.get(parent)?.as_table())
+            .and_then(|table| table.get(field));
+        let value = match current.get(field).or(default) {
+            // Keep strings as is even if they look like numbers, e.g. amounts.
+            Some(toml::Value::String(_)) => toml::Value::String(raw),
+            _ => parse_env_value(raw),
+        };
+        current.insert(field.clone(), value);
+    }
+    Ok(())
+}
+
+// Environment variable is parsed as TOML value, e.g. number or boolean,
+// and falls back to string.
+fn parse_env_value(raw: String) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or(toml::Value::String(raw))
+}
+
+fn is_dev_network(rpc_url: &str) -> bool {
+    let host = rpc_url.split("://").nth(1).unwrap_or_default();
+    ["localhost", "127.0.0.1", "[::1]"].iter().any(|local| {
+        host == *local
+            || host.starts_with(&format!("{local}:"))
+            || host.starts_with(&format!("{local}/"))
+    })
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub(crate) enum SignerType {
     Phrase,
     SecretUri,
+    /// Encrypted polkadot-js JSON keystore.
+    Keystore,
+    /// External signer process, value is unix socket path.
+    Remote,
 }
 
+/// Signature scheme of phrase and secret uri signers.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Scheme {
+    #[default]
+    Sr25519,
+    Ed25519,
+    Ecdsa,
+}
+
+/// Signer secret is set with exactly one of inline value, file path or environment variable.
+///
+/// Password is mnemonic password for phrase signer and passphrase for keystore signer,
+/// it is never set inline.
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct Signer {
     pub(crate) typ: SignerType,
-    pub(crate) val: String,
+    #[serde(default)]
+    pub(crate) scheme: Scheme,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) val: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) val_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) val_env: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) password_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) password_env: Option<String>,
+    /// Derivation path for phrase signer, e.g. "//staex/1".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) derivation_path: Option<String>,
 }
 
 impl Default for Signer {
     fn default() -> Self {
         Self {
             typ: SignerType::SecretUri,
-            val: "//Alice".to_string(),
+            scheme: Scheme::default(),
+            val: Some("//Alice".to_string()),
+            val_file: None,
+            val_env: None,
+            password_file: None,
+            password_env: None,
+            derivation_path: None,
         }
     }
 }
 
+impl Signer {
+    pub(crate) fn secret(&self) -> Result<String, Error> {
+        match (&self.val, &self.val_file, &self.val_env) {
+            (Some(val), None, None) => Ok(val.clone()),
+            (None, Some(path), None) => read_file("signer", path),
+            (None, None, Some(name)) => read_env("signer", name),
+            _ => Err("exactly one of val, val_file and val_env must be set".into()),
+        }
+    }
+
+    pub(crate) fn password(&self) -> Result<Option<String>, Error> {
+        match (&self.password_file, &self.password_env) {
+            (None, None) => Ok(None),
+            (Some(path), None) => read_file("password", path).map(Some),
+            (None, Some(name)) => read_env("password", name).map(Some),
+            _ => Err("only one of password_file and password_env can be set".into()),
+        }
+    }
+
+    fn validate(&self, field: &str, dev_network: bool, errors: &mut ConfigErrors) {
+        let secret = match self.secret() {
+            Ok(secret) => secret,
+            Err(e) => return errors.push(field, e),
+        };
+        match self.password() {
+            Ok(None) if matches!(self.typ, SignerType::Keystore) => {
+                errors.push(field, "keystore requires password_file or password_env")
+            }
+            Ok(Some(_)) if matches!(self.typ, SignerType::SecretUri) => {
+                errors.push(field, "secret uri contains password itself, use ///password")
+            }
+            Ok(Some(_)) if matches!(self.typ, SignerType::Remote) => {
+                errors.push(field, "remote signer doesn't use password")
+            }
+            Ok(_) => {}
+            Err(e) => errors.push(field, e),
+        }
+        if self.scheme != Scheme::Sr25519
+            && matches!(self.typ, SignerType::Keystore | SignerType::Remote)
+        {
+            errors.push(field, "scheme is supported only for phrase and secret uri signers");
+        }
+        if let Some(path) = &self.derivation_path {
+            if !matches!(self.typ, SignerType::Phrase) {
+                errors.push(field, "derivation_path is supported only for phrase signer");
+            } else if !path.starts_with('/') || path.contains("///") {
+                errors.push(field, format!("invalid derivation path {path}"));
+            }
+        }
+        if !dev_network && self.is_dev_key(&secret) {
+            errors.push(field, "development keys are allowed only with local rpc_url");
+        }
+    }
+
+    fn is_dev_key(&self, secret: &str) -> bool {
+        let phrase = match self.typ {
+            SignerType::Phrase => secret,
+            // Phrase is everything before derivation path and password.
+            SignerType::SecretUri => secret.split('/').next().unwrap_or_default(),
+            SignerType::Keystore | SignerType::Remote => return false,
+        };
+        let phrase = phrase.trim();
+        phrase.is_empty() || phrase == DEV_PHRASE
+    }
+}
+
+fn read_file(what: &str, path: &str) -> Result<String, Error> {
+    std::fs::read_to_string(path)
+        .map(|val| val.trim_end().to_string())
+        .map_err(|e| format!("failed to read {what} from {path}: {e}").into())
+}
+
+fn read_env(what: &str, name: &str) -> Result<String, Error> {
+    std::env::var(name)
+        .map_err(|e| format!("failed to read {what} from {name} variable: {e}").into())
+}
+
+/// Device with its own signer from signers.
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FleetDevice {
+    pub(crate) name: String,
+    pub(crate) signer: String,
+    pub(crate) device: Device,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct Device {
     pub(crate) sync: bool,
     pub(crate) force: bool,
+    #[serde(default)]
+    pub(crate) encoding: Encoding,
+    /// Account which receives device balance on decommissioning.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) owner: Option<AccountId32>,
+    /// RBAC group id in hex which payers of price_access are assigned to.
+    /// Group must be owned by main signer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) access_group: Option<String>,
+    /// Ed25519 public key of device, e.g. of microcontroller, and its signature of message
+    /// printed by attestation-message command; attributes are not attested if it is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) attestation: Option<device::Attestation>,
+    /// How precisely location is published, exact location from attributes is kept local.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) location_privacy: Option<LocationPrivacy>,
     pub(crate) attributes: Attributes,
 }
 
@@ -58,20 +498,99 @@ impl Default for Device {
         Self {
             sync: true,
             force: false,
+            encoding: Encoding::default(),
+            owner: None,
+            access_group: None,
+            attestation: None,
+            location_privacy: None,
             attributes: Attributes::default(),
         }
     }
 }
 
+impl Device {
+    fn validate(&self, field: &str, errors: &mut ConfigErrors) {
+        if let Some(group) = &self.access_group {
+            if let Err(e) = parse_group(group) {
+                errors.push(&format!("{field}.access_group"), e);
+            }
+        }
+        if self.attributes.private.is_some() && self.access_group.is_none() {
+            errors.push(
+                &format!("{field}.attributes.private"),
+                "requires access_group which buyers are assigned to",
+            );
+        }
+        if let Some(Err(e)) = self.attestation.as_ref().map(device::Attestation::parse) {
+            errors.push(&format!("{field}.attestation"), e);
+        }
+        if let Some(privacy) = &self.location_privacy {
+            privacy.validate(&format!("{field}.location_privacy"), errors);
+        }
+        self.attributes.validate(&format!("{field}.attributes"), errors);
+    }
+}
+
+/// Published location instead of exact one, its accuracy is published as location_accuracy.
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "method", rename_all = "lowercase", deny_unknown_fields)]
+pub(crate) enum LocationPrivacy {
+    /// Center of geohash cell of level from 1 to 12, e.g. level 6 is about 1.2x0.6 km.
+    Geohash { level: u8 },
+    /// Center of square grid cell with side in meters.
+    Grid { size: u32 },
+    /// Point with pseudo-random offset within radius in meters, offset is derived from secret
+    /// seed and stays the same until location or seed changes.
+    Offset { radius: u32, seed: String },
+}
+
+impl LocationPrivacy {
+    fn validate(&self, field: &str, errors: &mut ConfigErrors) {
+        match self {
+            Self::Geohash { level } => {
+                if !(1..=MAX_GEOHASH_LEVEL).contains(level) {
+                    errors.push(
+                        &format!("{field}.level"),
+                        format!("must be from 1 to {MAX_GEOHASH_LEVEL}"),
+                    );
+                }
+            }
+            Self::Grid { size } => {
+                if !(1..=MAX_LOCATION_PRIVACY_SIZE).contains(size) {
+                    errors.push(
+                        &format!("{field}.size"),
+                        format!("must be from 1 to {MAX_LOCATION_PRIVACY_SIZE} meters"),
+                    );
+                }
+            }
+            Self::Offset { radius, seed } => {
+                if !(1..=MAX_LOCATION_PRIVACY_SIZE).contains(radius) {
+                    errors.push(
+                        &format!("{field}.radius"),
+                        format!("must be from 1 to {MAX_LOCATION_PRIVACY_SIZE} meters"),
+                    );
+                }
+                if seed.len() < 16 {
+                    errors.push(&format!("{field}.seed"), "must have at least 16 characters");
+                }
+            }
+        }
+    }
+}
+
 // All fields are required attributes for every device.
 // Only "additional" is additional.
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct Attributes {
     pub(crate) data_type: String,
     pub(crate) location: String,
-    pub(crate) price_access: f64,
-    pub(crate) price_pin: f64,
+    pub(crate) price_access: Amount,
+    pub(crate) price_pin: Amount,
     pub(crate) additional: Option<HashMap<String, toml::Value>>,
+    /// Fields which are sealed to the device and members of device.access_group,
+    /// other accounts see only ciphertext.
+    pub(crate) private: Option<HashMap<String, toml::Value>>,
 }
 
 impl Default for Attributes {
@@ -79,41 +598,155 @@ impl Default for Attributes {
         Self {
             data_type: "cctv-camera".to_string(),
             location: "40.1949288120072,44.55177253802097".to_string(),
-            price_access: 42.03995,
-            price_pin: 445.12222,
+            price_access: "42.03995".parse().expect("valid amount"),
+            price_pin: "445.12222".parse().expect("valid amount"),
             additional: Some(HashMap::from([
                 ("microcontroller".to_string(), "stm32".into()),
                 ("device_age_in_years".to_string(), 2.into()),
             ])),
+            private: None,
+        }
+    }
+}
+
+impl Attributes {
+    /// Validates attributes which don't come from config file, e.g. from onboarding CSV.
+    pub(crate) fn check(&self) -> Result<(), String> {
+        let mut errors = ConfigErrors::default();
+        self.validate("attributes", &mut errors);
+        errors.into_result().map_err(|e| e.0.join("; "))
+    }
+
+    fn validate(&self, field: &str, errors: &mut ConfigErrors) {
+        if self.data_type.is_empty() || self.data_type.len() > 64 {
+            errors.push(&format!("{field}.data_type"), "must be from 1 to 64 characters");
+        }
+        if let Err(e) = device::parse_location(&self.location) {
+            errors.push(&format!("{field}.location"), e);
+        }
+        if let Some(additional) = &self.additional {
+            if additional.len() > MAX_ADDITIONAL_PROPERTIES as usize {
+                errors.push(
+                    &format!("{field}.additional"),
+                    format!("must have at most {MAX_ADDITIONAL_PROPERTIES} fields"),
+                );
+            }
+        }
+        if let Some(private) = &self.private {
+            if private.len() > MAX_ADDITIONAL_PROPERTIES as usize {
+                errors.push(
+                    &format!("{field}.private"),
+                    format!("must have at most {MAX_ADDITIONAL_PROPERTIES} fields"),
+                );
+            }
         }
     }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct Faucet {
     pub(crate) signer: Signer,
-    // We store amount in Planck.
-    pub(crate) amount: u64,
+    pub(crate) amount: Amount,
+    #[serde(default)]
+    pub(crate) server: FaucetServer,
 }
 
 impl Default for Faucet {
     fn default() -> Self {
         Self {
-            signer: Signer {
-                typ: SignerType::SecretUri,
-                val: "//Alice".to_string(),
-            },
-            amount: 999993264201726756, // 0.9
+            signer: Signer::default(),
+            amount: "0.9".parse().expect("valid amount"),
+            server: FaucetServer::default(),
+        }
+    }
+}
+
+/// HTTP faucet which sends faucet amount on request.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FaucetServer {
+    pub(crate) dsn: String,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    /// Use first X-Forwarded-For address as client IP.
+    pub(crate) behind_proxy: bool,
+    /// Seconds before the same address can be funded again.
+    pub(crate) address_cooldown: u64,
+    /// Seconds before the same IP can request funds again.
+    pub(crate) ip_cooldown: u64,
+    /// Total amount which can be sent in 24 hours.
+    pub(crate) daily_budget: Amount,
+    /// Addresses with this balance or more are not funded.
+    pub(crate) max_balance: Amount,
+    /// Faucet balance below this amount is reported as low funds.
+    pub(crate) low_funds: Amount,
+}
+
+impl Default for FaucetServer {
+    fn default() -> Self {
+        Self {
+            dsn: "sqlite:faucet.sqlite".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 4699,
+            behind_proxy: false,
+            address_cooldown: 24 * 60 * 60,
+            ip_cooldown: 60 * 60,
+            daily_budget: "100".parse().expect("valid amount"),
+            max_balance: "0.5".parse().expect("valid amount"),
+            low_funds: "10".parse().expect("valid amount"),
         }
     }
 }
 
+/// Funding of device accounts which can't pay for the next sync.
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TopUp {
+    pub(crate) source: TopUpSource,
+    /// Faucet server endpoint for server source.
+    pub(crate) url: String,
+}
+
+impl Default for TopUp {
+    fn default() -> Self {
+        Self {
+            source: TopUpSource::default(),
+            url: "http://127.0.0.1:4699/fund".to_string(),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TopUpSource {
+    /// Only warn about low balance.
+    #[default]
+    Disabled,
+    /// Send faucet amount from faucet signer.
+    Faucet,
+    /// Request funds from faucet server.
+    Server,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct Indexer {
     pub(crate) from_block: u64,
     pub(crate) dsn: String,
     pub(crate) host: String,
     pub(crate) port: u16,
+    /// Payments database of payment watcher to serve pins from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) payments_dsn: Option<String>,
+    /// Longest heartbeat interval in seconds which is trusted, longer ones are capped to it,
+    /// otherwise device could stay online for years after one heartbeat.
+    #[serde(default = "default_max_heartbeat_interval")]
+    pub(crate) max_heartbeat_interval: u64,
+}
+
+fn default_max_heartbeat_interval() -> u64 {
+    24 * 60 * 60
 }
 
 impl Default for Indexer {
@@ -123,6 +756,322 @@ impl Default for Indexer {
             dsn: "sqlite:staex-iod.sqlite".to_string(),
             host: "127.0.0.1".to_string(),
             port: 4698,
+            payments_dsn: None,
+            max_heartbeat_interval: default_max_heartbeat_interval(),
+        }
+    }
+}
+
+/// Payment watcher which grants access to payers.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Payments {
+    pub(crate) dsn: String,
+    /// Block to start watching from on the first run, last finalized block by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) from_block: Option<u64>,
+    /// How long bought access lasts, e.g. "30 days" or "14400 blocks"; forever by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) access_period: Option<Period>,
+    /// How long pinned data is kept after purchase; pins are not sold if it is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pin_period: Option<Period>,
+    #[serde(default)]
+    pub(crate) server: PaymentsServer,
+}
+
+impl Default for Payments {
+    fn default() -> Self {
+        Self {
+            dsn: "sqlite:payments.sqlite".to_string(),
+            from_block: None,
+            access_period: None,
+            pin_period: None,
+            server: PaymentsServer::default(),
         }
     }
 }
+
+/// HTTP API which shows grants and their remaining time to buyers.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PaymentsServer {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+}
+
+impl Default for PaymentsServer {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 4697,
+        }
+    }
+}
+
+/// Gateway which relays device readings to buyers with RBAC permission.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Gateway {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    /// Name of RBAC permission which subscribers must have.
+    pub(crate) permission: String,
+    /// Seconds to sign challenge.
+    pub(crate) challenge_ttl: u64,
+    /// Seconds session token is valid.
+    pub(crate) session_ttl: u64,
+    /// Seconds between reads of RBAC state, open streams are checked again after every read.
+    pub(crate) rbac_refresh: u64,
+}
+
+impl Default for Gateway {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 4696,
+            permission: "mqtt_access".to_string(),
+            challenge_ttl: 60,
+            session_ttl: 600,
+            rbac_refresh: 30,
+        }
+    }
+}
+
+/// Anchoring of readings received by gateway on chain.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Anchor {
+    pub(crate) enabled: bool,
+    /// Readings, anchored batches and inclusion proofs.
+    pub(crate) dsn: String,
+    /// Seconds between batches.
+    pub(crate) interval: u64,
+}
+
+impl Default for Anchor {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dsn: "sqlite:anchors.sqlite".to_string(),
+            interval: 600,
+        }
+    }
+}
+
+/// Liveness remarks which devices send while provisioner runs.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Heartbeat {
+    pub(crate) enabled: bool,
+    /// Seconds between heartbeats, device is offline in indexer after two missed ones.
+    pub(crate) interval: u64,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 3600,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn env_overrides() {
+        let mut table = toml::Table::try_from(Config::default()).unwrap();
+        apply_env(
+            &mut table,
+            vars(&[
+                ("PROVISIONER_INDEXER__PORT", "5000"),
+                ("PROVISIONER_RPC_URL", "wss://example.com"),
+                ("PROVISIONER_DEVICE__ATTRIBUTES__PRICE_ACCESS", "42"),
+                ("PROVISIONER_DEVICE__FORCE", "true"),
+                ("OTHER_VARIABLE", "1"),
+            ]),
+        )
+        .unwrap();
+        let cfg: Config = toml::Value::Table(table).try_into().unwrap();
+        assert_eq!(cfg.indexer.port, 5000);
+        assert_eq!(cfg.rpc_url, "wss://example.com");
+        assert_eq!(cfg.device.attributes.price_access, "42".parse().unwrap());
+        assert!(cfg.device.force);
+    }
+
+    #[test]
+    fn env_overrides_missing_fields() {
+        let mut table = toml::Table::try_from(Config::default()).unwrap();
+        table["device"]["attributes"].as_table_mut().unwrap().remove("price_access").unwrap();
+        table["faucet"].as_table_mut().unwrap().remove("amount").unwrap();
+        apply_env(
+            &mut table,
+            vars(&[
+                ("PROVISIONER_DEVICE__ATTRIBUTES__PRICE_ACCESS", "2"),
+                ("PROVISIONER_FAUCET__AMOUNT", "0.5"),
+            ]),
+        )
+        .unwrap();
+        let cfg: Config = toml::Value::Table(table).try_into().unwrap();
+        assert_eq!(cfg.device.attributes.price_access, "2".parse().unwrap());
+        assert_eq!(cfg.faucet.amount, "0.5".parse().unwrap());
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let mut table = toml::Table::try_from(Config::default()).unwrap();
+        apply_env(&mut table, vars(&[("PROVISIONER_INDEXER__PROT", "5000")])).unwrap();
+        assert!(toml::Value::Table(table).try_into::<Config>().is_err());
+    }
+
+    // Development keys of default config are allowed only with local node.
+    fn local_config() -> Config {
+        Config {
+            rpc_url: "ws://127.0.0.1:9944".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn validation() {
+        assert!(local_config().validate().is_ok());
+
+        let mut cfg = Config::default();
+        cfg.device.attributes.location = "91,44.55177253802097".to_string();
+        cfg.faucet.signer.val_env = Some("FAUCET_SIGNER".to_string());
+        let errors = cfg.validate().unwrap_err().0;
+        assert_eq!(errors.len(), 3, "{errors:?}");
+        assert!(errors[0].starts_with("signer:"));
+        assert!(errors[1].starts_with("faucet.signer:"));
+        assert!(errors[2].starts_with("device.attributes.location:"));
+
+        let mut cfg = local_config();
+        cfg.top_up.source = TopUpSource::Server;
+        assert!(cfg.validate().is_ok());
+        cfg.top_up.url = "127.0.0.1:4699/fund".to_string();
+        let errors = cfg.validate().unwrap_err().0;
+        assert!(errors[0].starts_with("top_up.url:"), "{errors:?}");
+
+        let mut cfg = local_config();
+        cfg.device.access_group = Some(format!("0x{}", "ab".repeat(32)));
+        assert!(cfg.validate().is_ok());
+        cfg.device.access_group = Some("0xabab".to_string());
+        let errors = cfg.validate().unwrap_err().0;
+        assert!(errors[0].starts_with("device.access_group:"), "{errors:?}");
+
+        let mut cfg = local_config();
+        cfg.device.attributes.private =
+            Some(HashMap::from([("stream_url".to_string(), "rtsp://10.0.0.2".into())]));
+        let errors = cfg.validate().unwrap_err().0;
+        assert!(errors[0].starts_with("device.attributes.private:"), "{errors:?}");
+        cfg.device.access_group = Some(format!("0x{}", "ab".repeat(32)));
+        assert!(cfg.validate().is_ok());
+
+        let mut cfg = local_config();
+        cfg.device.location_privacy = Some(LocationPrivacy::Geohash { level: 6 });
+        assert!(cfg.validate().is_ok());
+        cfg.device.location_privacy = Some(LocationPrivacy::Geohash { level: 13 });
+        let errors = cfg.validate().unwrap_err().0;
+        assert!(errors[0].starts_with("device.location_privacy.level:"), "{errors:?}");
+        cfg.device.location_privacy = Some(LocationPrivacy::Offset {
+            radius: 0,
+            seed: "short".to_string(),
+        });
+        let errors = cfg.validate().unwrap_err().0;
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        let privacy: LocationPrivacy = toml::from_str("method = \"grid\"\nsize = 1000").unwrap();
+        assert_eq!(privacy, LocationPrivacy::Grid { size: 1000 });
+
+        let mut cfg = local_config();
+        cfg.device.attestation = Some(device::Attestation {
+            public_key: format!("0x{}", "ab".repeat(32)),
+            signature: format!("0x{}", "cd".repeat(64)),
+        });
+        assert!(cfg.validate().is_ok());
+        cfg.device.attestation = Some(device::Attestation {
+            public_key: format!("0x{}", "AB".repeat(32)),
+            signature: "0xcd".to_string(),
+        });
+        let errors = cfg.validate().unwrap_err().0;
+        assert!(errors[0].starts_with("device.attestation:"), "{errors:?}");
+
+        let mut cfg = local_config();
+        cfg.payments.access_period = Some(Period::Blocks(0));
+        cfg.payments.pin_period = Some(Period::Seconds(0));
+        cfg.indexer.payments_dsn = Some("payments.sqlite".to_string());
+        let errors = cfg.validate().unwrap_err().0;
+        assert_eq!(errors.len(), 3, "{errors:?}");
+        assert!(errors[0].starts_with("payments.access_period:"), "{errors:?}");
+        assert!(errors[1].starts_with("payments.pin_period:"), "{errors:?}");
+        assert!(errors[2].starts_with("indexer.payments_dsn:"), "{errors:?}");
+
+        let mut cfg = local_config();
+        cfg.indexer.max_heartbeat_interval = 0;
+        let errors = cfg.validate().unwrap_err().0;
+        assert!(errors[0].starts_with("indexer.max_heartbeat_interval:"), "{errors:?}");
+        // Configs written before the limit get the default one.
+        let indexer: Indexer =
+            toml::from_str("from_block = 1\ndsn = \"sqlite:a\"\nhost = \"h\"\nport = 1").unwrap();
+        assert_eq!(indexer.max_heartbeat_interval, 86400);
+    }
+
+    #[test]
+    fn fleet() {
+        let mut cfg: Config = toml::from_str(&format!(
+            r#"{}
+            [signers.sensor-1]
+            typ = "SecretUri"
+            val = "//Bob"
+
+            [[devices]]
+            name = "sensor-1"
+            signer = "sensor-1"
+
+            [devices.device]
+            sync = true
+            force = false
+
+            [devices.device.attributes]
+            data_type = "temperature-sensor"
+            location = "40.1949288120072,44.55177253802097"
+            price_access = "1"
+            price_pin = "2"
+            "#,
+            toml::to_string(&local_config()).unwrap()
+        ))
+        .unwrap();
+        assert!(cfg.validate().is_ok());
+        assert_eq!(cfg.device_signer(MAIN_DEVICE_NAME).unwrap().val.as_deref(), Some("//Alice"));
+        assert_eq!(cfg.device_signer("sensor-1").unwrap().val.as_deref(), Some("//Bob"));
+        assert!(cfg.device_signer("sensor-2").is_err());
+
+        let mut device = cfg.devices[0].clone();
+        device.device.attributes.location = "0,181".to_string();
+        cfg.devices.push(device.clone());
+        device.name = "sensor-2".to_string();
+        device.signer = "sensor-2".to_string();
+        cfg.devices.push(device);
+        let errors = cfg.validate().unwrap_err().0;
+        assert_eq!(errors.len(), 5, "{errors:?}");
+        assert!(errors[0].starts_with("devices[1]: duplicate device name"));
+        assert!(errors[1].starts_with("devices[1]: signer sensor-1 is used"));
+        assert!(errors[2].starts_with("devices[1].device.attributes.location:"));
+        assert!(errors[3].starts_with("devices[2]: signer sensor-2 is not found"));
+        assert!(errors[4].starts_with("devices[2].device.attributes.location:"));
+    }
+
+    #[test]
+    fn dev_network() {
+        assert!(is_dev_network("ws://127.0.0.1:9944"));
+        assert!(is_dev_network("ws://localhost"));
+        assert!(!is_dev_network("ws://localhost.example.com"));
+        assert!(!is_dev_network("wss://rpcpc1-qa.agung.peaq.network"));
+    }
+}
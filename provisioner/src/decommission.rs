@@ -0,0 +1,159 @@
+use std::io::{BufRead, Write};
+
+use log::{error, info};
+use peaq_client::{DIDAttribute, RBACRecord};
+use subxt::{ext::sp_core::H256, utils::AccountId32};
+
+use crate::{amount::Planck, rbac::EntityKind, App, Error};
+
+/// Decommissioning step with transaction hash or error.
+struct Step {
+    description: String,
+    result: Result<H256, String>,
+}
+
+impl App {
+    /// Removes all DID attributes and disables all RBAC entities owned by device,
+    /// then sweeps device balance to its owner.
+    pub(crate) async fn decommission(&self, name: &str, yes: bool) -> Result<(), Error> {
+        let device = self.device(name)?;
+        let peaq_client = &device.peaq_client;
+        let address = peaq_client.address();
+        let owner = device
+            .device
+            .owner
+            .clone()
+            .ok_or_else(|| format!("device {name}: owner account is not set"))?;
+        if owner == address {
+            return Err(format!("device {name}: owner must differ from device account").into());
+        }
+
+        let attributes = peaq_client.get_owned_attributes(&address).await?;
+        let owned = peaq_client.get_owned_entities(&address).await?;
+        // Disabled entities are already revoked.
+        let entities: Vec<(EntityKind, RBACRecord)> = [
+            (EntityKind::Role, owned.roles),
+            (EntityKind::Permission, owned.permissions),
+            (EntityKind::Group, owned.groups),
+        ]
+        .into_iter()
+        .flat_map(|(kind, entities)| entities.into_iter().map(move |entity| (kind, entity)))
+        .filter(|(_, entity)| entity.enabled)
+        .collect();
+        let balance = peaq_client.get_balance(&address).await?;
+
+        eprintln!("Device: {} ({})", name, address);
+        eprintln!("DID attributes to remove: {}", attributes.len());
+        for (did_account, attribute) in &attributes {
+            eprintln!("  {}", describe_attribute(did_account, attribute));
+        }
+        eprintln!("RBAC entities to disable: {}", entities.len());
+        for (kind, entity) in &entities {
+            eprintln!("  {}", describe_entity(*kind, entity));
+        }
+        eprintln!("Balance to sweep: {} to {}", self.token.format(Planck(balance)), owner);
+        if !yes && !confirm(&format!("Decommission device {name}?"))? {
+            eprintln!("Decommissioning is cancelled");
+            return Ok(());
+        }
+
+        let mut steps = Vec::with_capacity(attributes.len() + entities.len() + 1);
+        for (did_account, attribute) in attributes {
+            let description = format!("remove {}", describe_attribute(&did_account, &attribute));
+            info!("{}: {}", name, description);
+            let result =
+                peaq_client.did().remove_account_attribute(did_account, attribute.name).await;
+            steps.push(Step::new(description, result));
+        }
+        for (kind, entity) in entities {
+            let description = format!("disable {}", describe_entity(kind, &entity));
+            info!("{}: {}", name, description);
+            let rbac = peaq_client.rbac();
+            let result = match kind {
+                EntityKind::Role => rbac.disable_role(entity.id).await,
+                EntityKind::Permission => rbac.disable_permission(entity.id).await,
+                EntityKind::Group => rbac.disable_group(entity.id).await,
+            };
+            steps.push(Step::new(description, result));
+        }
+        let description = format!("sweep balance to {owner}");
+        if steps.iter().all(|step| step.result.is_ok()) {
+            info!("{}: {}", name, description);
+            // Device account is not needed anymore, so it is reaped.
+            let result = peaq_client.transfer_all(owner, false).await;
+            steps.push(Step::new(description, result));
+        } else {
+            // Funds stay on device account to pay fees when decommissioning is repeated.
+            steps.push(Step {
+                description,
+                result: Err("skipped because previous steps failed".to_string()),
+            });
+        }
+
+        eprintln!("Report:");
+        let mut failed = 0;
+        for step in &steps {
+            match &step.result {
+                Ok(tx) => eprintln!("  ok: {} in {:?} transaction", step.description, tx),
+                Err(e) => {
+                    failed += 1;
+                    error!("{}: failed to {}: {}", name, step.description, e);
+                    eprintln!("  failed: {}: {}", step.description, e);
+                }
+            }
+        }
+        let balance = peaq_client.get_balance(&address).await?;
+        eprintln!("Device balance: {}", self.token.format(Planck(balance)));
+        if failed > 0 {
+            return Err(format!(
+                "{} of {} decommissioning steps failed; run decommissioning again",
+                failed,
+                steps.len()
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl Step {
+    fn new(description: String, result: Result<H256, Error>) -> Self {
+        Self {
+            description,
+            result: result.map_err(|e| e.to_string()),
+        }
+    }
+}
+
+fn describe_attribute(did_account: &AccountId32, attribute: &DIDAttribute) -> String {
+    format!("attribute {} of {}", String::from_utf8_lossy(&attribute.name), did_account)
+}
+
+fn describe_entity(kind: EntityKind, entity: &RBACRecord) -> String {
+    format!("{} {} (0x{})", kind, String::from_utf8_lossy(&entity.name), hex::encode(entity.id))
+}
+
+pub(crate) fn confirm(prompt: &str) -> Result<bool, Error> {
+    eprint!("{prompt} [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(confirmed(&answer))
+}
+
+fn confirmed(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirmation() {
+        assert!(confirmed("y\n"));
+        assert!(confirmed(" YES "));
+        assert!(!confirmed("\n"));
+        assert!(!confirmed("no"));
+    }
+}
@@ -0,0 +1,731 @@
+use std::{collections::HashMap, fmt, sync::OnceLock};
+
+use jsonschema::{
+    paths::{JSONPointer, JsonPointerNode},
+    ErrorIterator, JSONSchema, Keyword, ValidationError,
+};
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, NumberValidation, ObjectValidation, RootSchema, Schema, SchemaObject},
+    schema_for, JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+use subxt::{
+    ext::sp_core::{ed25519, Pair},
+    utils::AccountId32,
+};
+
+use crate::{
+    amount::{Amount, Planck, Token},
+    Error,
+};
+
+pub(crate) const V1: &str = "v1";
+pub(crate) const V2: &str = "v2";
+
+// Leading byte of compact encoded device. JSON encoded device always starts with '{'.
+const COMPACT_FORMAT: u8 = 0x01;
+
+// Max number of keys in "additional" map, to not pay for huge on-chain attributes.
+pub(crate) const MAX_ADDITIONAL_PROPERTIES: u32 = 16;
+// Prefix of message which device key signs, the message binds attributes to account.
+const ATTESTATION_PREFIX: &str = "staex-ioa-attestation";
+const PUBLIC_KEY_PATTERN: &str = "^0x[0-9a-f]{64}$";
+const SIGNATURE_PATTERN: &str = "^0x[0-9a-f]{128}$";
+const NONCE_PATTERN: &str = "^0x[0-9a-f]{48}$";
+// Content key with authentication tag.
+const SEALED_KEY_PATTERN: &str = "^0x[0-9a-f]{96}$";
+const CIPHERTEXT_PATTERN: &str = "^0x([0-9a-f]{2})+$";
+// Max number of accounts private fields are sealed to, every account adds about 150 bytes.
+pub(crate) const MAX_SEALED_KEYS: u32 = 16;
+// Float prices of v1 are converted to planck of 18 decimals token, so they must fit u128
+// and have at most 18 fractional digits.
+const MAX_V1_PRICE: f64 = 1e20;
+const V1_PRICE_DECIMALS: u32 = 18;
+// Schema keyword for fractional digits, JSON schema multipleOf is unreliable for floats.
+const MAX_DECIMALS_KEYWORD: &str = "maxDecimals";
+// Half of equator, any point is closer.
+const MAX_LOCATION_ACCURACY: u32 = 20_037_509;
+// Latitude in [-90, 90] and longitude in [-180, 180] separated by comma.
+const LOCATION_PATTERN: &str =
+    r"^-?(90(\.0+)?|[1-8]?\d(\.\d+)?)\s*,\s*-?(180(\.0+)?|(1[0-7]\d|[1-9]?\d)(\.\d+)?)$";
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Device {
+    V1(DeviceV1),
+    V2(DeviceV2),
+}
+
+impl Device {
+    pub(crate) fn version(&self) -> &str {
+        match self {
+            Device::V1(_) => V1,
+            Device::V2(_) => V2,
+        }
+    }
+
+    /// Returns access and pin prices in planck.
+    pub(crate) fn prices(&self, token: &Token) -> Result<(Planck, Planck), Error> {
+        // Float is formatted with the shortest representation, e.g. "42.03995",
+        // so it can be converted to planck without float arithmetic.
+        let to_planck = |price: f64| price.to_string().parse::<Amount>()?.to_planck(token);
+        match self {
+            Device::V1(device) => {
+                Ok((to_planck(device.price_access)?, to_planck(device.price_pin)?))
+            }
+            Device::V2(device) => Ok((device.price_access, device.price_pin)),
+        }
+    }
+
+    /// Returns published location and its accuracy in meters, zero for exact location.
+    pub(crate) fn location(&self) -> (&str, u32) {
+        match self {
+            Device::V1(device) => (&device.location, 0),
+            Device::V2(device) => (&device.location, device.location_accuracy.unwrap_or(0)),
+        }
+    }
+}
+
+/// Outdated device with float prices, it is only decoded but never created.
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DeviceV1 {
+    #[schemars(length(min = 1, max = 64))]
+    pub(crate) data_type: String,
+    #[schemars(regex = "LOCATION_PATTERN")]
+    pub(crate) location: String,
+    #[schemars(schema_with = "v1_price_schema")]
+    pub(crate) price_access: f64,
+    #[schemars(schema_with = "v1_price_schema")]
+    pub(crate) price_pin: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "additional_schema")]
+    pub(crate) additional: Option<HashMap<String, toml::Value>>,
+}
+
+/// Device attribute on-chain encoding.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Encoding {
+    /// Plain JSON, human readable.
+    #[default]
+    Json,
+    /// Format byte followed by CBOR encoded device without field names.
+    Compact,
+}
+
+impl Encoding {
+    pub(crate) const ALL: [Encoding; 2] = [Encoding::Json, Encoding::Compact];
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Json => write!(f, "json"),
+            Encoding::Compact => write!(f, "compact"),
+        }
+    }
+}
+
+// Compact representation stores fields as CBOR array, so field names
+// are not repeated in every on-chain attribute. New fields must be
+// appended to the end with default value to be able to decode old devices.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CompactDevice {
+    V1(CompactDeviceV1),
+    V2(CompactDeviceV2),
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactDeviceV1(
+    String,
+    String,
+    f64,
+    f64,
+    #[serde(default)] Option<HashMap<String, toml::Value>>,
+);
+
+#[derive(Serialize, Deserialize)]
+struct CompactDeviceV2(
+    String,
+    String,
+    Planck,
+    Planck,
+    #[serde(default)] Option<HashMap<String, toml::Value>>,
+    // Public key and signature.
+    #[serde(default)] Option<(String, String)>,
+    #[serde(default)] Option<CompactSealed>,
+    #[serde(default)] Option<u32>,
+);
+
+// Ephemeral key, nonce, ciphertext and recipients with their keys.
+#[derive(Serialize, Deserialize)]
+struct CompactSealed(String, String, String, Vec<(String, String)>);
+
+impl From<Device> for CompactDevice {
+    fn from(device: Device) -> Self {
+        match device {
+            Device::V1(d) => CompactDevice::V1(CompactDeviceV1(
+                d.data_type,
+                d.location,
+                d.price_access,
+                d.price_pin,
+                d.additional,
+            )),
+            Device::V2(d) => CompactDevice::V2(CompactDeviceV2(
+                d.data_type,
+                d.location,
+                d.price_access,
+                d.price_pin,
+                d.additional,
+                d.attestation.map(|v| (v.public_key, v.signature)),
+                d.private.map(|v| {
+                    CompactSealed(
+                        v.ephemeral,
+                        v.nonce,
+                        v.ciphertext,
+                        v.keys.into_iter().map(|v| (v.recipient, v.key)).collect(),
+                    )
+                }),
+                d.location_accuracy,
+            )),
+        }
+    }
+}
+
+impl From<CompactDevice> for Device {
+    fn from(device: CompactDevice) -> Self {
+        match device {
+            CompactDevice::V1(d) => Device::V1(DeviceV1 {
+                data_type: d.0,
+                location: d.1,
+                price_access: d.2,
+                price_pin: d.3,
+                additional: d.4,
+            }),
+            CompactDevice::V2(d) => Device::V2(DeviceV2 {
+                data_type: d.0,
+                location: d.1,
+                location_accuracy: d.7,
+                price_access: d.2,
+                price_pin: d.3,
+                additional: d.4,
+                attestation: d.5.map(|(public_key, signature)| Attestation {
+                    public_key,
+                    signature,
+                }),
+                private: d.6.map(|v| Sealed {
+                    ephemeral: v.0,
+                    nonce: v.1,
+                    ciphertext: v.2,
+                    keys: v
+                        .3
+                        .into_iter()
+                        .map(|(recipient, key)| SealedKey { recipient, key })
+                        .collect(),
+                }),
+            }),
+        }
+    }
+}
+
+/// Device with prices in planck.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DeviceV2 {
+    #[schemars(length(min = 1, max = 64))]
+    pub(crate) data_type: String,
+    #[schemars(regex = "LOCATION_PATTERN")]
+    pub(crate) location: String,
+    /// Radius in meters around location which device is within, location is exact without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1, max = "MAX_LOCATION_ACCURACY"))]
+    pub(crate) location_accuracy: Option<u32>,
+    pub(crate) price_access: Planck,
+    pub(crate) price_pin: Planck,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "additional_schema")]
+    pub(crate) additional: Option<HashMap<String, toml::Value>>,
+    /// Fields which only buyers can read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) private: Option<Sealed>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) attestation: Option<Attestation>,
+}
+
+impl DeviceV2 {
+    /// Signs attributes of device of given account with device key like microcontroller does.
+    #[cfg(test)]
+    pub(crate) fn attest(
+        &mut self,
+        address: &AccountId32,
+        key: &ed25519::Pair,
+    ) -> Result<(), Error> {
+        let signature = key.sign(&self.attestation_message(address)?);
+        self.attestation = Some(Attestation {
+            public_key: format!("0x{}", hex::encode(key.public())),
+            signature: format!("0x{}", hex::encode(signature)),
+        });
+        Ok(())
+    }
+
+    /// Returns true if attestation signature matches attributes of device of given account.
+    pub(crate) fn is_attested(&self, address: &AccountId32) -> bool {
+        let Some(attestation) = &self.attestation else {
+            return false;
+        };
+        let (Ok((public_key, signature)), Ok(message)) =
+            (attestation.parse(), self.attestation_message(address))
+        else {
+            return false;
+        };
+        ed25519::Pair::verify(&signature, message, &public_key)
+    }
+
+    /// Returns "staex-ioa-attestation:<address>:<device JSON>" where device has no attestation
+    /// and private fields and JSON has sorted keys and no whitespace.
+    ///
+    /// Private fields are sealed with random keys on every sync, so device key can't sign them.
+    pub(crate) fn attestation_message(&self, address: &AccountId32) -> Result<Vec<u8>, Error> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(object) = value.as_object_mut() {
+            object.remove("attestation");
+            object.remove("private");
+        }
+        let value = serde_json::json!({ V2: value });
+        let mut message = format!("{ATTESTATION_PREFIX}:{address}:").into_bytes();
+        message.extend(serde_json::to_vec(&value)?);
+        Ok(message)
+    }
+}
+
+/// Signature of device attributes by separate device key, e.g. ed25519 key of microcontroller.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Attestation {
+    /// Ed25519 public key in hex.
+    #[schemars(regex = "PUBLIC_KEY_PATTERN")]
+    pub(crate) public_key: String,
+    /// Ed25519 signature in hex.
+    #[schemars(regex = "SIGNATURE_PATTERN")]
+    pub(crate) signature: String,
+}
+
+impl Attestation {
+    /// Returns public key and signature which must be lowercase hex with 0x prefix.
+    pub(crate) fn parse(&self) -> Result<(ed25519::Public, ed25519::Signature), Error> {
+        let public_key = decode_hex::<32>(&self.public_key)
+            .ok_or("public_key must be 32 bytes in lowercase hex with 0x prefix")?;
+        let signature = decode_hex::<64>(&self.signature)
+            .ok_or("signature must be 64 bytes in lowercase hex with 0x prefix")?;
+        Ok((ed25519::Public::from_raw(public_key), ed25519::Signature::from_raw(signature)))
+    }
+}
+
+fn decode_hex<const N: usize>(value: &str) -> Option<[u8; N]> {
+    let value = value.strip_prefix("0x")?;
+    if value.bytes().any(|b| b.is_ascii_uppercase()) {
+        return None;
+    }
+    hex::decode(value).ok()?.try_into().ok()
+}
+
+/// Private fields encrypted with random content key, which is sealed to every recipient.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Sealed {
+    /// Ephemeral sr25519 public key in hex which key exchange is made with.
+    #[schemars(regex = "PUBLIC_KEY_PATTERN")]
+    pub(crate) ephemeral: String,
+    /// XSalsa20-Poly1305 nonce of ciphertext in hex.
+    #[schemars(regex = "NONCE_PATTERN")]
+    pub(crate) nonce: String,
+    /// Fields as JSON encrypted with content key, in hex.
+    #[schemars(regex = "CIPHERTEXT_PATTERN")]
+    pub(crate) ciphertext: String,
+    #[schemars(length(max = "MAX_SEALED_KEYS"))]
+    pub(crate) keys: Vec<SealedKey>,
+}
+
+/// Content key encrypted for one recipient.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SealedKey {
+    /// Address of recipient.
+    #[schemars(length(min = 1, max = 64))]
+    pub(crate) recipient: String,
+    #[schemars(regex = "SEALED_KEY_PATTERN")]
+    pub(crate) key: String,
+}
+
+fn additional_schema(_: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(vec![InstanceType::Object, InstanceType::Null].into()),
+        object: Some(Box::new(ObjectValidation {
+            max_properties: Some(MAX_ADDITIONAL_PROPERTIES),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+fn v1_price_schema(_: &mut SchemaGenerator) -> Schema {
+    let mut schema = SchemaObject {
+        instance_type: Some(InstanceType::Number.into()),
+        number: Some(Box::new(NumberValidation {
+            minimum: Some(0.0),
+            maximum: Some(MAX_V1_PRICE),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    schema.extensions.insert(MAX_DECIMALS_KEYWORD.to_string(), V1_PRICE_DECIMALS.into());
+    schema.into()
+}
+
+/// Checks that number has at most given fractional digits in its shortest representation.
+struct MaxDecimals(usize);
+
+impl MaxDecimals {
+    fn check(&self, value: f64) -> bool {
+        let value = value.to_string();
+        value.split_once('.').is_none_or(|(_, frac)| frac.len() <= self.0)
+    }
+}
+
+impl Keyword for MaxDecimals {
+    fn validate<'instance>(
+        &self,
+        instance: &'instance serde_json::Value,
+        instance_path: &JsonPointerNode,
+    ) -> ErrorIterator<'instance> {
+        if self.is_valid(instance) {
+            return Box::new(std::iter::empty());
+        }
+        Box::new(std::iter::once(ValidationError::custom(
+            JSONPointer::default(),
+            instance_path.into(),
+            instance,
+            format!("{instance} has more than {} fractional digits", self.0),
+        )))
+    }
+
+    fn is_valid(&self, instance: &serde_json::Value) -> bool {
+        instance.as_f64().is_none_or(|value| self.check(value))
+    }
+}
+
+// Error type is defined by jsonschema keyword factory.
+#[allow(clippy::result_large_err)]
+fn max_decimals<'a>(
+    _: &'a serde_json::Map<String, serde_json::Value>,
+    value: &'a serde_json::Value,
+    _: JSONPointer,
+) -> Result<Box<dyn Keyword>, ValidationError<'a>> {
+    Ok(Box::new(MaxDecimals(value.as_u64().unwrap_or_default() as usize)))
+}
+
+/// Parses "latitude,longitude" location and checks coordinates are in range.
+pub(crate) fn parse_location(location: &str) -> Result<(f64, f64), Error> {
+    let (lat, lon) = location
+        .split_once(',')
+        .ok_or_else(|| format!("location must be \"latitude,longitude\": {location}"))?;
+    let lat: f64 = lat.trim().parse().map_err(|_| format!("invalid latitude: {lat}"))?;
+    let lon: f64 = lon.trim().parse().map_err(|_| format!("invalid longitude: {lon}"))?;
+    if !(-90.0..=90.0).contains(&lat) {
+        return Err(format!("latitude {lat} is out of [-90, 90] range").into());
+    }
+    if !(-180.0..=180.0).contains(&lon) {
+        return Err(format!("longitude {lon} is out of [-180, 180] range").into());
+    }
+    Ok((lat, lon))
+}
+
+/// Returns JSON schema for on-chain device attribute of the given version.
+///
+/// Device is serialized with version as a key, so schema describes an object
+/// with single required version field.
+pub(crate) fn schema(version: &str) -> Option<RootSchema> {
+    let mut root = match version {
+        V1 => schema_for!(DeviceV1),
+        V2 => schema_for!(DeviceV2),
+        _ => return None,
+    };
+    let mut inner = std::mem::take(&mut root.schema);
+    let metadata = inner.metadata.take();
+    root.schema = SchemaObject {
+        metadata,
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(ObjectValidation {
+            properties: [(version.to_string(), inner.into())].into_iter().collect(),
+            required: [version.to_string()].into_iter().collect(),
+            additional_properties: Some(Box::new(false.into())),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    Some(root)
+}
+
+fn compiled_schema(version: &str) -> Option<&'static JSONSchema> {
+    static V1_SCHEMA: OnceLock<JSONSchema> = OnceLock::new();
+    static V2_SCHEMA: OnceLock<JSONSchema> = OnceLock::new();
+    let cell = match version {
+        V1 => &V1_SCHEMA,
+        V2 => &V2_SCHEMA,
+        _ => return None,
+    };
+    Some(cell.get_or_init(|| {
+        let schema = serde_json::to_value(schema(version).expect("schema exists for version"))
+            .expect("schema is serializable");
+        JSONSchema::options()
+            .with_keyword(MAX_DECIMALS_KEYWORD, max_decimals)
+            .compile(&schema)
+            .expect("generated schema is valid")
+    }))
+}
+
+/// List of schema violations found in device payload.
+#[derive(Debug)]
+pub(crate) struct SchemaViolations(pub(crate) Vec<String>);
+
+impl fmt::Display for SchemaViolations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "device does not match schema: {}", self.0.join("; "))
+    }
+}
+
+impl std::error::Error for SchemaViolations {}
+
+/// Validates JSON device payload against the schema of its version.
+pub(crate) fn validate(value: &serde_json::Value) -> Result<(), SchemaViolations> {
+    let version = value
+        .as_object()
+        .filter(|object| object.len() == 1)
+        .and_then(|object| object.keys().next())
+        .ok_or_else(|| SchemaViolations(vec!["device must contain exactly one version".into()]))?;
+    let schema = compiled_schema(version)
+        .ok_or_else(|| SchemaViolations(vec![format!("unknown device version: {version}")]))?;
+    if let Err(errors) = schema.validate(value) {
+        return Err(SchemaViolations(
+            errors.map(|e| format!("{}: {}", e.instance_path, e)).collect(),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns encoding of on-chain device.
+pub(crate) fn encoding(data: &[u8]) -> Encoding {
+    match data.first() {
+        Some(&COMPACT_FORMAT) => Encoding::Compact,
+        _ => Encoding::Json,
+    }
+}
+
+/// Decodes device from any supported encoding and validates it against the schema.
+pub(crate) fn decode(data: &[u8]) -> Result<Device, Error> {
+    let value: serde_json::Value = match data.split_first() {
+        Some((&COMPACT_FORMAT, data)) => {
+            let device: Device = ciborium::from_reader::<CompactDevice, _>(data)?.into();
+            serde_json::to_value(device)?
+        }
+        _ => serde_json::from_slice(data)?,
+    };
+    validate(&value)?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Validates device against the schema and encodes it with the given encoding.
+pub(crate) fn encode(device: Device, encoding: Encoding) -> Result<Vec<u8>, Error> {
+    let value = serde_json::to_value(&device)?;
+    validate(&value)?;
+    match encoding {
+        Encoding::Json => Ok(serde_json::to_vec(&value)?),
+        Encoding::Compact => {
+            let mut data = vec![COMPACT_FORMAT];
+            ciborium::into_writer(&CompactDevice::from(device), &mut data)?;
+            Ok(data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::agung;
+
+    #[test]
+    fn valid_device() {
+        let value = json!({"v1": {
+            "data_type": "cctv-camera",
+            "location": "40.1949288120072,44.55177253802097",
+            "price_access": 42.03995,
+            "price_pin": 445.12222,
+            "additional": {"microcontroller": "stm32"},
+        }});
+        validate(&value).unwrap();
+    }
+
+    #[test]
+    fn invalid_device() {
+        let additional: serde_json::Map<String, serde_json::Value> =
+            (0..=MAX_ADDITIONAL_PROPERTIES).map(|i| (i.to_string(), json!(i))).collect();
+        let value = json!({"v1": {
+            "data_type": "cctv-camera",
+            "location": "91.1,44.55177253802097",
+            "price_access": -1,
+            "price_pin": 445.12222,
+            "additional": additional,
+        }});
+        let violations = validate(&value).unwrap_err().0;
+        assert_eq!(violations.len(), 3, "{violations:?}");
+        assert!(violations.iter().any(|v| v.starts_with("/v1/location")));
+        assert!(violations.iter().any(|v| v.starts_with("/v1/price_access")));
+        assert!(violations.iter().any(|v| v.starts_with("/v1/additional")));
+    }
+
+    #[test]
+    fn v1_prices() {
+        let device = |price: f64| {
+            json!({"v1": {
+                "data_type": "cctv-camera",
+                "location": "40.1949288120072,44.55177253802097",
+                "price_access": price,
+                "price_pin": 1,
+            }})
+        };
+        let token = agung();
+        for price in [0.0, 0.0045, 42.03995, 1e-18, 1e20] {
+            let decoded = decode(&serde_json::to_vec(&device(price)).unwrap()).unwrap();
+            assert!(decoded.prices(&token).is_ok(), "{price}");
+        }
+        for price in [1e-19, 0.0012345678901234567, 1e300] {
+            let violations = validate(&device(price)).unwrap_err().0;
+            assert_eq!(violations.len(), 1, "{violations:?}");
+            assert!(violations[0].starts_with("/v1/price_access"), "{violations:?}");
+        }
+    }
+
+    #[test]
+    fn invalid_planck() {
+        let value = json!({"v2": {
+            "data_type": "cctv-camera",
+            "location": "40.1949288120072,44.55177253802097",
+            "price_access": "-1",
+            "price_pin": 445.12222,
+        }});
+        let violations = validate(&value).unwrap_err().0;
+        assert_eq!(violations.len(), 2, "{violations:?}");
+    }
+
+    #[test]
+    fn compact_encoding() {
+        let device = || {
+            Device::V2(DeviceV2 {
+                data_type: "cctv-camera".to_string(),
+                location: "40.1949288120072,44.55177253802097".to_string(),
+                location_accuracy: Some(384),
+                price_access: Planck(42_039_950_000_000_000_000),
+                price_pin: Planck(445_122_220_000_000_000_000),
+                additional: Some(HashMap::from([("microcontroller".to_string(), "stm32".into())])),
+                private: Some(Sealed {
+                    ephemeral: format!("0x{}", "3".repeat(64)),
+                    nonce: format!("0x{}", "4".repeat(48)),
+                    ciphertext: format!("0x{}", "5".repeat(40)),
+                    keys: vec![SealedKey {
+                        recipient: AccountId32([6; 32]).to_string(),
+                        key: format!("0x{}", "7".repeat(96)),
+                    }],
+                }),
+                attestation: Some(Attestation {
+                    public_key: format!("0x{}", "1".repeat(64)),
+                    signature: format!("0x{}", "2".repeat(128)),
+                }),
+            })
+        };
+        let json = encode(device(), Encoding::Json).unwrap();
+        let compact = encode(device(), Encoding::Compact).unwrap();
+        assert!(compact.len() < json.len());
+        let decoded = serde_json::to_value(decode(&compact).unwrap()).unwrap();
+        let expected: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(decoded, expected);
+        assert_eq!(serde_json::to_value(decode(&json).unwrap()).unwrap(), expected);
+        assert_eq!(encoding(&json), Encoding::Json);
+        assert_eq!(encoding(&compact), Encoding::Compact);
+    }
+
+    #[test]
+    fn unknown_version() {
+        assert!(validate(&json!({"v0": {}})).is_err());
+        assert!(schema("v0").is_none());
+    }
+
+    #[test]
+    fn attestation() {
+        let device = || DeviceV2 {
+            data_type: "cctv-camera".to_string(),
+            location: "40.1949288120072,44.55177253802097".to_string(),
+            location_accuracy: None,
+            price_access: Planck(42),
+            price_pin: Planck(445),
+            additional: Some(HashMap::from([("microcontroller".to_string(), "stm32".into())])),
+            private: None,
+            attestation: None,
+        };
+        let address = AccountId32([1; 32]);
+        let key = ed25519::Pair::from_seed(&[2; 32]);
+        assert!(!device().is_attested(&address));
+
+        let mut attested = device();
+        attested.attest(&address, &key).unwrap();
+        assert!(attested.is_attested(&address));
+        assert!(!attested.is_attested(&AccountId32([3; 32])));
+        let value = serde_json::to_value(Device::V2(attested.clone())).unwrap();
+        validate(&value).unwrap();
+
+        // Decoded device is still attested in both encodings.
+        for encoding in Encoding::ALL {
+            let mut copy = device();
+            copy.attestation.clone_from(&attested.attestation);
+            let Device::V2(decoded) = decode(&encode(Device::V2(copy), encoding).unwrap()).unwrap()
+            else {
+                panic!("device must be v2");
+            };
+            assert!(decoded.is_attested(&address), "{encoding}");
+        }
+
+        // Resealed private fields don't change signed message.
+        let mut sealed = attested.clone();
+        sealed.private = Some(Sealed {
+            ephemeral: format!("0x{}", "3".repeat(64)),
+            nonce: format!("0x{}", "4".repeat(48)),
+            ciphertext: format!("0x{}", "5".repeat(40)),
+            keys: Vec::new(),
+        });
+        assert!(sealed.is_attested(&address));
+
+        let mut uppercase = attested.clone();
+        if let Some(attestation) = &mut uppercase.attestation {
+            attestation.signature = attestation.signature.to_uppercase().replacen("0X", "0x", 1);
+        }
+        assert!(!uppercase.is_attested(&address));
+
+        attested.price_access = Planck(1);
+        assert!(!attested.is_attested(&address));
+        let value = json!({"v2": {
+            "data_type": "cctv-camera",
+            "location": "40.1949288120072,44.55177253802097",
+            "price_access": "1",
+            "price_pin": "1",
+            "attestation": {"public_key": "0x01", "signature": "0x02"},
+        }});
+        let violations = validate(&value).unwrap_err().0;
+        assert_eq!(violations.len(), 1, "{violations:?}");
+        assert!(violations[0].starts_with("/v2/attestation"));
+    }
+}
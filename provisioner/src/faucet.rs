@@ -0,0 +1,399 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::SocketAddr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::ConnectInfo,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use log::{error, info, warn};
+use peaq_client::SignerClient;
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+use subxt::utils::AccountId32;
+use tokio::sync::Mutex;
+
+use crate::{
+    amount::{Planck, Token},
+    config::Config,
+    indexer::open_sqlite,
+    signer::AnySigner,
+    Error,
+};
+
+// Daily budget is counted over sliding window of this many seconds.
+const BUDGET_WINDOW: u64 = 24 * 60 * 60;
+
+pub(crate) async fn run(cfg: Config) -> Result<(), Error> {
+    let client = Arc::new(peaq_client::Client::new(&cfg.rpc_url).await?);
+    let token = Token::fetch(&client).await?;
+    cfg.validate_amounts(&token)?;
+    let server = cfg.faucet.server;
+    let faucet = Arc::new(Faucet {
+        peaq_client: SignerClient::with_client(client, AnySigner::from_config(&cfg.faucet.signer)?),
+        amount: cfg.faucet.amount.to_planck(&token)?,
+        address_cooldown: server.address_cooldown,
+        ip_cooldown: server.ip_cooldown,
+        daily_budget: server.daily_budget.to_planck(&token)?,
+        max_balance: server.max_balance.to_planck(&token)?,
+        low_funds: server.low_funds.to_planck(&token)?,
+        behind_proxy: server.behind_proxy,
+        token,
+        database: Mutex::new(Database::new(&server.dsn).await?),
+        requests: Mutex::new(BTreeMap::new()),
+    });
+    let addr = format!("{}:{}", server.host, server.port);
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    info!("faucet {} listens on {addr} for HTTP requests", faucet.peaq_client.address());
+    tokio::spawn(async move {
+        let app = Router::new()
+            .route("/fund", post(fund))
+            .route("/metrics", get(metrics))
+            .layer(Extension(faucet));
+        let app = app.into_make_service_with_connect_info::<SocketAddr>();
+        if let Err(e) = axum::serve(listener, app).await {
+            error!("failed to run faucet api: {e}");
+        }
+    });
+    Ok(())
+}
+
+struct Faucet {
+    peaq_client: SignerClient<AnySigner>,
+    amount: Planck,
+    address_cooldown: u64,
+    ip_cooldown: u64,
+    daily_budget: Planck,
+    max_balance: Planck,
+    low_funds: Planck,
+    behind_proxy: bool,
+    token: Token,
+    database: Mutex<Database>,
+    // Number of requests by result for metrics.
+    requests: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Faucet {
+    async fn fund(&self, address: AccountId32, ip: &str) -> Result<FundResponse, ApiError> {
+        // Requests are handled one by one, so limits can't be bypassed with parallel requests.
+        let mut database = self.database.lock().await;
+        let now = now()?;
+        let last = database.last_address_request(&address.to_string()).await?;
+        if let Some(retry_after) = retry_after(last, self.address_cooldown, now) {
+            return Err(ApiError::cooldown("address was funded recently", retry_after));
+        }
+        let last = database.last_ip_request(ip).await?;
+        if let Some(retry_after) = retry_after(last, self.ip_cooldown, now) {
+            return Err(ApiError::cooldown("too many requests from this IP", retry_after));
+        }
+        let spent = database.spent_since(now.saturating_sub(BUDGET_WINDOW)).await?;
+        if spent.0.saturating_add(self.amount.0) > self.daily_budget.0 {
+            return Err(ApiError::new(
+                "budget",
+                StatusCode::SERVICE_UNAVAILABLE,
+                "daily budget is exhausted",
+            ));
+        }
+        let balance = self.peaq_client.get_balance(&address).await.map_err(ApiError::internal)?;
+        if balance >= self.max_balance.0 {
+            return Err(ApiError::new(
+                "funded",
+                StatusCode::CONFLICT,
+                format!("address already has {}", self.token.format(Planck(balance))),
+            ));
+        }
+        let tx = self
+            .peaq_client
+            .transfer(self.amount.0, address.clone())
+            .await
+            .map_err(ApiError::internal)?;
+        database.save(&address.to_string(), ip, self.amount, &format!("{tx:?}"), now).await?;
+        info!(
+            "sent {} to {} from {} in {:?} transaction",
+            self.token.format(self.amount),
+            address,
+            ip,
+            tx
+        );
+        Ok(FundResponse {
+            address: address.to_string(),
+            amount: self.token.format(self.amount),
+            tx: format!("{tx:?}"),
+        })
+    }
+
+    fn client_ip(&self, peer: SocketAddr, headers: &HeaderMap) -> String {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        match forwarded {
+            Some(ip) if self.behind_proxy => ip,
+            _ => peer.ip().to_string(),
+        }
+    }
+
+    // Metrics are in tokens as Prometheus values are floats anyway.
+    fn tokens(&self, amount: Planck) -> f64 {
+        amount.0 as f64 / 10f64.powi(self.token.decimals as i32)
+    }
+}
+
+struct Database {
+    conn: SqliteConnection,
+}
+
+impl Database {
+    async fn new(dsn: &str) -> Result<Self, Error> {
+        let mut conn = open_sqlite(dsn).await?;
+        let migrator = sqlx::migrate!("./migrations/faucet/");
+        migrator.run_direct(&mut conn).await?;
+        Ok(Self { conn })
+    }
+
+    async fn last_address_request(&mut self, address: &str) -> Result<Option<u64>, Error> {
+        let last: Option<i64> =
+            sqlx::query_scalar("select max(created_at) from requests where address = ?1")
+                .bind(address)
+                .fetch_one(&mut self.conn)
+                .await?;
+        Ok(last.map(|v| v as u64))
+    }
+
+    async fn last_ip_request(&mut self, ip: &str) -> Result<Option<u64>, Error> {
+        let last: Option<i64> =
+            sqlx::query_scalar("select max(created_at) from requests where ip = ?1")
+                .bind(ip)
+                .fetch_one(&mut self.conn)
+                .await?;
+        Ok(last.map(|v| v as u64))
+    }
+
+    async fn spent_since(&mut self, since: u64) -> Result<Planck, Error> {
+        // Planck doesn't fit into sqlite integer, so it is summed here.
+        let amounts: Vec<String> =
+            sqlx::query_scalar("select amount from requests where created_at >= ?1")
+                .bind(since as i64)
+                .fetch_all(&mut self.conn)
+                .await?;
+        let mut spent = Planck(0);
+        for amount in amounts {
+            spent.0 = spent.0.saturating_add(amount.parse::<Planck>()?.0);
+        }
+        Ok(spent)
+    }
+
+    async fn save(
+        &mut self,
+        address: &str,
+        ip: &str,
+        amount: Planck,
+        tx: &str,
+        created_at: u64,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+                insert into requests (address, ip, amount, tx, created_at)
+                values (?1, ?2, ?3, ?4, ?5)
+            "#,
+        )
+        .bind(address)
+        .bind(ip)
+        .bind(amount.to_string())
+        .bind(tx)
+        .bind(created_at as i64)
+        .execute(&mut self.conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Requests funds from faucet server and returns transaction hash.
+pub(crate) async fn request_funds(url: &str, address: &AccountId32) -> Result<String, Error> {
+    let request = FundRequest {
+        address: address.to_string(),
+    };
+    let response = reqwest::Client::new().post(url).json(&request).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let message = match response.json::<ErrorBody>().await {
+            Ok(body) => body.error,
+            Err(_) => status.to_string(),
+        };
+        return Err(format!("faucet server {url} rejected request: {message}").into());
+    }
+    Ok(response.json::<FundResponse>().await?.tx)
+}
+
+/// Returns seconds left until cooldown ends.
+fn retry_after(last: Option<u64>, cooldown: u64, now: u64) -> Option<u64> {
+    let end = last?.saturating_add(cooldown);
+    (end > now).then(|| end - now)
+}
+
+fn now() -> Result<u64, ApiError> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH).map_err(ApiError::internal)?.as_secs())
+}
+
+#[derive(Serialize, Deserialize)]
+struct FundRequest {
+    address: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FundResponse {
+    address: String,
+    amount: String,
+    tx: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ErrorBody {
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+}
+
+struct ApiError {
+    // Result label in requests metric.
+    result: &'static str,
+    status_code: StatusCode,
+    message: String,
+    retry_after: Option<u64>,
+}
+
+impl ApiError {
+    fn new(result: &'static str, status_code: StatusCode, message: impl ToString) -> Self {
+        Self {
+            result,
+            status_code,
+            message: message.to_string(),
+            retry_after: None,
+        }
+    }
+
+    fn cooldown(message: &str, retry_after: u64) -> Self {
+        Self {
+            retry_after: Some(retry_after),
+            ..Self::new("cooldown", StatusCode::TOO_MANY_REQUESTS, message)
+        }
+    }
+
+    fn internal(e: impl ToString) -> Self {
+        Self::new("error", StatusCode::INTERNAL_SERVER_ERROR, e)
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(e: Error) -> Self {
+        Self::internal(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if self.status_code == StatusCode::INTERNAL_SERVER_ERROR {
+            error!("internal server error: {}", self.message);
+        }
+        let mut headers = HeaderMap::new();
+        if let Some(retry_after) = self.retry_after {
+            headers.insert(header::RETRY_AFTER, retry_after.into());
+        }
+        let body = ErrorBody {
+            error: self.message,
+            retry_after: self.retry_after,
+        };
+        (self.status_code, headers, Json(body)).into_response()
+    }
+}
+
+async fn fund(
+    Extension(faucet): Extension<Arc<Faucet>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<FundRequest>,
+) -> Result<Json<FundResponse>, ApiError> {
+    let ip = faucet.client_ip(peer, &headers);
+    let result = match request.address.parse::<AccountId32>() {
+        Ok(address) => faucet.fund(address, &ip).await,
+        Err(e) => Err(ApiError::new("invalid", StatusCode::BAD_REQUEST, e)),
+    };
+    let label = match &result {
+        Ok(_) => "sent",
+        Err(e) => e.result,
+    };
+    *faucet.requests.lock().await.entry(label).or_default() += 1;
+    Ok(Json(result?))
+}
+
+async fn metrics(Extension(faucet): Extension<Arc<Faucet>>) -> Result<Response, ApiError> {
+    let address = faucet.peaq_client.address();
+    let balance =
+        Planck(faucet.peaq_client.get_balance(&address).await.map_err(ApiError::internal)?);
+    let since = now()?.saturating_sub(BUDGET_WINDOW);
+    let spent = faucet.database.lock().await.spent_since(since).await?;
+    let low_funds = balance < faucet.low_funds;
+    if low_funds {
+        warn!("faucet {} has low funds: {}", address, faucet.token.format(balance));
+    }
+
+    let mut body = String::new();
+    let mut gauge = |name: &str, help: &str, value: f64| {
+        let _ = writeln!(body, "# HELP {name} {help}");
+        let _ = writeln!(body, "# TYPE {name} gauge");
+        let _ = writeln!(body, "{name} {value}");
+    };
+    gauge("faucet_balance", "Faucet signer balance in tokens.", faucet.tokens(balance));
+    gauge(
+        "faucet_low_funds",
+        "1 if faucet balance is below low funds threshold.",
+        u8::from(low_funds).into(),
+    );
+    gauge("faucet_spent", "Tokens sent in the last 24 hours.", faucet.tokens(spent));
+    gauge(
+        "faucet_budget",
+        "Tokens which can be sent in 24 hours.",
+        faucet.tokens(faucet.daily_budget),
+    );
+    let _ = writeln!(body, "# HELP faucet_requests_total Fund requests by result.");
+    let _ = writeln!(body, "# TYPE faucet_requests_total counter");
+    for (result, count) in faucet.requests.lock().await.iter() {
+        let _ = writeln!(body, "faucet_requests_total{{result=\"{result}\"}} {count}");
+    }
+    Ok(([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDb;
+
+    #[test]
+    fn cooldown() {
+        assert_eq!(retry_after(None, 60, 100), None);
+        assert_eq!(retry_after(Some(50), 60, 100), Some(10));
+        assert_eq!(retry_after(Some(40), 60, 100), None);
+    }
+
+    #[tokio::test]
+    async fn requests() {
+        let db = TempDb::new("faucet");
+        let mut database = Database::new(&db.dsn()).await.unwrap();
+        assert_eq!(database.last_address_request("a").await.unwrap(), None);
+        database.save("a", "127.0.0.1", Planck(u128::MAX / 4), "0x1", 100).await.unwrap();
+        database.save("b", "127.0.0.1", Planck(u128::MAX / 4), "0x2", 200).await.unwrap();
+        assert_eq!(database.last_address_request("a").await.unwrap(), Some(100));
+        assert_eq!(database.last_ip_request("127.0.0.1").await.unwrap(), Some(200));
+        assert_eq!(database.spent_since(0).await.unwrap(), Planck(u128::MAX / 4 * 2));
+        assert_eq!(database.spent_since(150).await.unwrap(), Planck(u128::MAX / 4));
+    }
+}
@@ -0,0 +1,543 @@
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::Path,
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use crypto_secretbox::aead::{rand_core::RngCore, OsRng};
+use futures::Stream;
+use log::{debug, error, info};
+use peaq_client::{Client, OwnedAssignments, ENTITY_ID_LENGTH};
+use serde::{Deserialize, Serialize};
+use subxt::{
+    ext::sp_core::{sr25519, Pair},
+    utils::AccountId32,
+};
+use tokio::sync::{broadcast, watch, Mutex};
+
+use crate::{anchor::AnchorsPointer, config, onboard::parse_group, App, Error};
+
+/// Prefix of challenge which caller signs to open session.
+const CHALLENGE_PREFIX: &str = "staex-ioa-gateway";
+// Readings which slow subscriber can fall behind before it starts losing them.
+const READINGS_CAPACITY: usize = 64;
+// Maximum size of one reading.
+const MAX_READING_SIZE: usize = 64 * 1024;
+// Challenges are issued to anybody, so unused ones are limited until they expire.
+const MAX_CHALLENGES: usize = 10_000;
+
+type Entity = [u8; ENTITY_ID_LENGTH];
+
+/// Runs gateway which relays readings of fleet devices to buyers with RBAC permission.
+///
+/// Readings are also saved to anchors if they are given.
+pub(crate) async fn run(
+    app: &App,
+    cfg: config::Gateway,
+    anchors: Option<AnchorsPointer>,
+) -> Result<(), Error> {
+    let mut devices = BTreeMap::new();
+    for device in &app.devices {
+        let group = device.device.access_group.as_deref().map(parse_group).transpose()?;
+        let (readings, _) = broadcast::channel(READINGS_CAPACITY);
+        devices.insert(device.peaq_client.address(), GatewayDevice { group, readings });
+    }
+    let client = app.client.clone();
+    let owner = app.main_device().peaq_client.address();
+    let (rbac_sender, rbac) = watch::channel(Arc::new(read_rbac(&client, &owner, &cfg).await?));
+    let gateway = Arc::new(Gateway {
+        devices,
+        challenges: Mutex::new(BTreeMap::new()),
+        sessions: Mutex::new(BTreeMap::new()),
+        rbac,
+        anchors,
+        cfg: cfg.clone(),
+    });
+    // Storage is scanned without locks, subscribers are checked again when new state is sent.
+    let refresh_cfg = cfg.clone();
+    tokio::spawn(async move {
+        let refresh = Duration::from_secs(refresh_cfg.rbac_refresh);
+        loop {
+            tokio::time::sleep(refresh).await;
+            match read_rbac(&client, &owner, &refresh_cfg).await {
+                Ok(state) => {
+                    rbac_sender.send_replace(Arc::new(state));
+                }
+                Err(e) => error!("failed to read RBAC state of {}: {}", owner, e),
+            }
+        }
+    });
+    let addr = format!("{}:{}", cfg.host, cfg.port);
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    info!("gateway listens on {addr} for HTTP requests, {} permission is required", cfg.permission);
+    tokio::spawn(async move {
+        let app = Router::new()
+            .route("/challenges", post(create_challenge))
+            .route("/sessions", post(create_session))
+            .route("/devices/:address/readings", get(subscribe).post(publish))
+            .layer(Extension(gateway));
+        if let Err(e) = axum::serve(listener, app).await {
+            error!("failed to run gateway: {e}");
+        }
+    });
+    Ok(())
+}
+
+struct Gateway {
+    devices: BTreeMap<AccountId32, GatewayDevice>,
+    /// Issued challenges with their address and expiry.
+    challenges: Mutex<BTreeMap<String, (AccountId32, u64)>>,
+    /// Session tokens with their address and expiry.
+    sessions: Mutex<BTreeMap<String, (AccountId32, u64)>>,
+    /// The last RBAC state of owner which assigns buyers to groups.
+    rbac: watch::Receiver<Arc<RbacState>>,
+    anchors: Option<AnchorsPointer>,
+    cfg: config::Gateway,
+}
+
+struct GatewayDevice {
+    /// Access group which subscribers must be in, if device sells access.
+    group: Option<Entity>,
+    readings: broadcast::Sender<String>,
+}
+
+/// RBAC entities and assignments of owner needed to check permissions.
+///
+/// fetch_user_permissions is an extrinsic which costs fee, so storage is read instead.
+struct RbacState {
+    /// Enabled permissions with gateway permission name.
+    permissions: Vec<Entity>,
+    assignments: OwnedAssignments,
+}
+
+impl RbacState {
+    fn has_permission(&self, user: &Entity) -> bool {
+        let groups: Vec<&Entity> = self.groups(user).collect();
+        let roles: Vec<&Entity> = self
+            .assignments
+            .role_users
+            .iter()
+            .filter(|(_, v)| v == user)
+            .chain(self.assignments.role_groups.iter().filter(|(_, v)| groups.contains(&v)))
+            .map(|(role, _)| role)
+            .collect();
+        self.assignments.permission_roles.iter().any(|(permission, role)| {
+            roles.contains(&role) && self.permissions.contains(permission)
+        })
+    }
+
+    fn groups<'a>(&'a self, user: &'a Entity) -> impl Iterator<Item = &'a Entity> {
+        self.assignments.user_groups.iter().filter(move |(v, _)| v == user).map(|(_, group)| group)
+    }
+}
+
+impl Gateway {
+    async fn authenticate(&self, headers: &HeaderMap) -> Result<(AccountId32, u64), ApiError> {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, "bearer token is required"))?;
+        let now = now()?;
+        let mut sessions = self.sessions.lock().await;
+        sessions.retain(|_, (_, expires_at)| *expires_at > now);
+        sessions
+            .get(token)
+            .cloned()
+            .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, "session is expired or unknown"))
+    }
+
+    /// Checks that user has gateway permission and is in access group of device if it has one.
+    fn authorize(
+        &self,
+        state: &RbacState,
+        user: &AccountId32,
+        device: &GatewayDevice,
+    ) -> Result<(), ApiError> {
+        if !state.has_permission(&user.0) {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                format!("{} permission is required", self.cfg.permission),
+            ));
+        }
+        if let Some(group) = &device.group {
+            if !state.groups(&user.0).any(|v| v == group) {
+                return Err(ApiError::new(StatusCode::FORBIDDEN, "device access is not bought"));
+            }
+        }
+        Ok(())
+    }
+
+    fn device(&self, address: &str) -> Result<(AccountId32, &GatewayDevice), ApiError> {
+        let address: AccountId32 =
+            address.parse().map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+        let device = self
+            .devices
+            .get(&address)
+            .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "device is not found"))?;
+        Ok((address, device))
+    }
+}
+
+async fn read_rbac(
+    client: &Client,
+    owner: &AccountId32,
+    cfg: &config::Gateway,
+) -> Result<RbacState, Error> {
+    debug!("reading RBAC state of {}", owner);
+    let entities = client.get_owned_entities(owner).await?;
+    let permissions = entities
+        .permissions
+        .iter()
+        .filter(|v| v.enabled && v.name == cfg.permission.as_bytes())
+        .map(|v| v.id)
+        .collect();
+    let assignments = client.get_owned_assignments(owner).await?;
+    Ok(RbacState {
+        permissions,
+        assignments,
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChallengeRequest {
+    address: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChallengeResponse {
+    challenge: String,
+    expires_at: u64,
+}
+
+async fn create_challenge(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(request): Json<ChallengeRequest>,
+) -> Result<Json<ChallengeResponse>, ApiError> {
+    let address: AccountId32 =
+        request.address.parse().map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    let now = now()?;
+    let expires_at = now + gateway.cfg.challenge_ttl;
+    let mut nonce = [0u8; 16];
+    OsRng.fill_bytes(&mut nonce);
+    let challenge = format!("{CHALLENGE_PREFIX}:{address}:{}:{expires_at}", hex::encode(nonce));
+    let mut challenges = gateway.challenges.lock().await;
+    challenges.retain(|_, (_, expires_at)| *expires_at > now);
+    if challenges.len() >= MAX_CHALLENGES {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "too many challenges, try again later",
+        ));
+    }
+    challenges.insert(challenge.clone(), (address, expires_at));
+    Ok(Json(ChallengeResponse {
+        challenge,
+        expires_at,
+    }))
+}
+
+#[derive(Serialize, Deserialize)]
+struct SessionRequest {
+    challenge: String,
+    /// Sr25519 signature of challenge in hex.
+    signature: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SessionResponse {
+    address: String,
+    token: String,
+    expires_at: u64,
+}
+
+/// Exchanges signed challenge for session token, every challenge can be used once.
+async fn create_session(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(request): Json<SessionRequest>,
+) -> Result<Json<SessionResponse>, ApiError> {
+    let now = now()?;
+    let (address, expires_at) = gateway
+        .challenges
+        .lock()
+        .await
+        .remove(&request.challenge)
+        .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, "challenge is unknown"))?;
+    if expires_at <= now {
+        return Err(ApiError::new(StatusCode::UNAUTHORIZED, "challenge is expired"));
+    }
+    if !verify(&address, &request.challenge, &request.signature) {
+        return Err(ApiError::new(StatusCode::UNAUTHORIZED, "invalid signature"));
+    }
+    let mut token = [0u8; 32];
+    OsRng.fill_bytes(&mut token);
+    let token = hex::encode(token);
+    let expires_at = now + gateway.cfg.session_ttl;
+    gateway.sessions.lock().await.insert(token.clone(), (address.clone(), expires_at));
+    info!("opened session of {}", address);
+    Ok(Json(SessionResponse {
+        address: address.to_string(),
+        token,
+        expires_at,
+    }))
+}
+
+/// Checks sr25519 signature of challenge as raw bytes or wrapped in <Bytes> like polkadot.js does.
+fn verify(address: &AccountId32, challenge: &str, signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature.trim_start_matches("0x")) else {
+        return false;
+    };
+    let Ok(signature) = <[u8; 64]>::try_from(signature) else {
+        return false;
+    };
+    let signature = sr25519::Signature::from_raw(signature);
+    let public = sr25519::Public::from_raw(address.0);
+    sr25519::Pair::verify(&signature, challenge, &public)
+        || sr25519::Pair::verify(&signature, format!("<Bytes>{challenge}</Bytes>"), &public)
+}
+
+/// Streams readings of device as server-sent events until session expires or access is lost.
+async fn subscribe(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Path(address): Path<String>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let (user, expires_at) = gateway.authenticate(&headers).await?;
+    let (address, device) = gateway.device(&address)?;
+    let mut rbac = gateway.rbac.clone();
+    let state = rbac.borrow_and_update().clone();
+    gateway.authorize(&state, &user, device)?;
+    info!("{} subscribed to readings of {}", user, address);
+    let deadline =
+        tokio::time::Instant::now() + Duration::from_secs(expires_at.saturating_sub(now()?));
+    let subscription = Subscription {
+        readings: device.readings.subscribe(),
+        rbac,
+        revoked: false,
+    };
+    let stream = futures::stream::unfold(subscription, move |mut subscription| {
+        let (gateway, user, address) = (gateway.clone(), user.clone(), address.clone());
+        async move {
+            if subscription.revoked {
+                return None;
+            }
+            let device = &gateway.devices[&address];
+            loop {
+                let event = tokio::select! {
+                    reading = subscription.readings.recv() => match reading {
+                        Ok(reading) => Event::default().data(reading),
+                        // Subscriber is told how many readings it missed.
+                        Err(broadcast::error::RecvError::Lagged(missed)) => {
+                            Event::default().event("lagged").data(missed.to_string())
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    },
+                    changed = subscription.rbac.changed() => {
+                        if changed.is_err() {
+                            return None;
+                        }
+                        let state = subscription.rbac.borrow_and_update().clone();
+                        let Err(e) = gateway.authorize(&state, &user, device) else {
+                            continue;
+                        };
+                        // Subscriber is told why stream ends.
+                        info!("closed stream of {} to {}: {}", address, user, e.message);
+                        subscription.revoked = true;
+                        Event::default().event("revoked").data(e.message)
+                    },
+                    _ = tokio::time::sleep_until(deadline) => return None,
+                };
+                return Some((Ok(event), subscription));
+            }
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Readings of one subscriber and RBAC state to check its access when it changes.
+struct Subscription {
+    readings: broadcast::Receiver<String>,
+    rbac: watch::Receiver<Arc<RbacState>>,
+    revoked: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PublishResponse {
+    /// Id of saved reading to get its proof once it is anchored.
+    id: i64,
+}
+
+/// Accepts reading from device itself and relays it to subscribers.
+async fn publish(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Path(address): Path<String>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response, ApiError> {
+    let (user, _) = gateway.authenticate(&headers).await?;
+    let (address, device) = gateway.device(&address)?;
+    if user != address {
+        return Err(ApiError::new(StatusCode::FORBIDDEN, "only device can publish its readings"));
+    }
+    if body.len() > MAX_READING_SIZE || body.contains('\n') {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("reading must be one line of at most {MAX_READING_SIZE} bytes"),
+        ));
+    }
+    let id = match &gateway.anchors {
+        Some(anchors) => Some(anchors.lock().await.add_reading(&address.to_string(), &body).await?),
+        None => None,
+    };
+    // Error means that nobody is subscribed.
+    let _ = device.readings.send(body);
+    Ok(match id {
+        Some(id) => Json(PublishResponse { id }).into_response(),
+        None => StatusCode::NO_CONTENT.into_response(),
+    })
+}
+
+fn now() -> Result<u64, ApiError> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH).map_err(ApiError::internal)?.as_secs())
+}
+
+#[derive(Serialize, Deserialize)]
+struct ErrorBody {
+    error: String,
+}
+
+struct ApiError {
+    status_code: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status_code: StatusCode, message: impl ToString) -> Self {
+        Self {
+            status_code,
+            message: message.to_string(),
+        }
+    }
+
+    fn internal(e: impl ToString) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, e)
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(e: Error) -> Self {
+        Self::internal(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if self.status_code == StatusCode::INTERNAL_SERVER_ERROR {
+            error!("internal server error: {}", self.message);
+        }
+        (
+            self.status_code,
+            Json(ErrorBody {
+                error: self.message,
+            }),
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permissions() {
+        let (user, other) = ([1; 32], [2; 32]);
+        let (group, role, direct_role, permission, other_permission) =
+            ([10; 32], [20; 32], [21; 32], [30; 32], [31; 32]);
+        let state = |assignments| RbacState {
+            permissions: vec![permission],
+            assignments,
+        };
+        let via_group = state(OwnedAssignments {
+            permission_roles: vec![(permission, role), (other_permission, direct_role)],
+            role_groups: vec![(role, group)],
+            user_groups: vec![(user, group)],
+            ..Default::default()
+        });
+        assert!(via_group.has_permission(&user));
+        assert!(!via_group.has_permission(&other));
+        assert_eq!(via_group.groups(&user).collect::<Vec<_>>(), [&group]);
+
+        let direct = state(OwnedAssignments {
+            permission_roles: vec![(permission, direct_role)],
+            role_users: vec![(direct_role, user)],
+            ..Default::default()
+        });
+        assert!(direct.has_permission(&user));
+
+        // Role has other permission only.
+        let wrong = state(OwnedAssignments {
+            permission_roles: vec![(other_permission, role)],
+            role_users: vec![(role, user)],
+            ..Default::default()
+        });
+        assert!(!wrong.has_permission(&user));
+    }
+
+    #[tokio::test]
+    async fn challenge_limit() {
+        let (_, rbac) = watch::channel(Arc::new(RbacState {
+            permissions: Vec::new(),
+            assignments: OwnedAssignments::default(),
+        }));
+        let gateway = Arc::new(Gateway {
+            devices: BTreeMap::new(),
+            challenges: Mutex::new(BTreeMap::new()),
+            sessions: Mutex::new(BTreeMap::new()),
+            rbac,
+            anchors: None,
+            cfg: config::Gateway::default(),
+        });
+        let request = || {
+            Json(ChallengeRequest {
+                address: AccountId32([1; 32]).to_string(),
+            })
+        };
+        assert!(create_challenge(Extension(gateway.clone()), request()).await.is_ok());
+        let expires_at = now().ok().unwrap() + 60;
+        gateway.challenges.lock().await.extend(
+            (0..MAX_CHALLENGES).map(|i| (i.to_string(), (AccountId32([1; 32]), expires_at))),
+        );
+        let Err(e) = create_challenge(Extension(gateway.clone()), request()).await else {
+            panic!("challenge is created over limit");
+        };
+        assert_eq!(e.status_code, StatusCode::SERVICE_UNAVAILABLE);
+        // Expired challenges don't count.
+        gateway.challenges.lock().await.values_mut().for_each(|(_, v)| *v = 0);
+        assert!(create_challenge(Extension(gateway.clone()), request()).await.is_ok());
+    }
+
+    #[test]
+    fn signature() {
+        let (pair, _) = sr25519::Pair::generate();
+        let address = AccountId32(pair.public().0);
+        let challenge = format!("{CHALLENGE_PREFIX}:{address}:00:1");
+        let raw = hex::encode(pair.sign(challenge.as_bytes()).0);
+        assert!(verify(&address, &challenge, &raw));
+        let wrapped = hex::encode(pair.sign(format!("<Bytes>{challenge}</Bytes>").as_bytes()).0);
+        assert!(verify(&address, &challenge, &format!("0x{wrapped}")));
+        assert!(!verify(&address, &format!("{challenge}0"), &raw));
+        assert!(!verify(&AccountId32([1; 32]), &challenge, &raw));
+        assert!(!verify(&address, &challenge, "0x00"));
+    }
+}
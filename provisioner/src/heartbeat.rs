@@ -0,0 +1,87 @@
+use std::{fmt, str::from_utf8, time::Duration};
+
+use log::{info, warn};
+
+use crate::{App, DeviceClient};
+
+/// Prefix of system remark which device sends to show that it is alive.
+pub(crate) const HEARTBEAT_PREFIX: &str = "staex-ioa-heartbeat";
+
+/// Remark which device sends periodically, sender is the device.
+#[derive(PartialEq, Eq, Debug)]
+pub(crate) struct HeartbeatRemark {
+    /// Seconds until the next heartbeat, indexer uses it to decide whether device is online.
+    pub(crate) interval: u64,
+}
+
+impl HeartbeatRemark {
+    /// Returns None if remark is not heartbeat or its interval doesn't fit database.
+    pub(crate) fn parse(remark: &[u8]) -> Option<Self> {
+        let remark = from_utf8(remark).ok()?;
+        let mut parts = remark.split(':');
+        if parts.next()? != HEARTBEAT_PREFIX {
+            return None;
+        }
+        let heartbeat = Self {
+            interval: parts.next()?.parse().ok().filter(|v| *v > 0 && *v <= i64::MAX as u64)?,
+        };
+        parts.next().is_none().then_some(heartbeat)
+    }
+}
+
+impl fmt::Display for HeartbeatRemark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{HEARTBEAT_PREFIX}:{}", self.interval)
+    }
+}
+
+impl App {
+    /// Sends heartbeat remark from every given device on interval, the first one right away.
+    ///
+    /// Failed heartbeat is only logged, the next one is sent on the next tick.
+    /// Returns right away if no device is given.
+    pub(crate) async fn send_heartbeats(&self, devices: &[&DeviceClient]) {
+        let remark = HeartbeatRemark {
+            interval: self.heartbeat.interval,
+        }
+        .to_string();
+        if devices.is_empty() {
+            warn!("no device is synced, heartbeats are not sent");
+            return;
+        }
+        let mut ticker = tokio::time::interval(Duration::from_secs(self.heartbeat.interval));
+        loop {
+            ticker.tick().await;
+            for device in devices {
+                match device.peaq_client.remark_with_event(remark.clone().into_bytes()).await {
+                    Ok((_, tx)) => info!("{}: sent heartbeat in {:?} transaction", device.name, tx),
+                    Err(e) => warn!("{}: failed to send heartbeat: {}", device.name, e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeat_remark() {
+        let remark = HeartbeatRemark { interval: 3600 };
+        assert_eq!(remark.to_string(), "staex-ioa-heartbeat:3600");
+        assert_eq!(HeartbeatRemark::parse(remark.to_string().as_bytes()), Some(remark));
+        let max = format!("staex-ioa-heartbeat:{}", i64::MAX);
+        assert!(HeartbeatRemark::parse(max.as_bytes()).is_some());
+        for remark in [
+            "staex-ioa-anchor:3600",
+            "staex-ioa-heartbeat",
+            "staex-ioa-heartbeat:0",
+            "staex-ioa-heartbeat:-1",
+            "staex-ioa-heartbeat:9223372036854775808",
+            "staex-ioa-heartbeat:3600:1",
+        ] {
+            assert_eq!(HeartbeatRemark::parse(remark.as_bytes()), None, "{remark}");
+        }
+    }
+}
@@ -1,39 +1,64 @@
 use std::{
+    collections::HashSet,
     fs::OpenOptions,
     io::ErrorKind,
+    str::from_utf8,
     sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use axum::{
-    extract::FromRequestParts,
+    extract::{FromRequestParts, Path},
     http::{request::Parts, StatusCode},
-    response::{IntoResponse, Response},
-    routing::get,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
     Extension, Json, Router,
 };
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 use peaq_client::{
-    peaq_gen::api::peaq_did::events::{AttributeAdded, AttributeRemoved, AttributeUpdated},
+    peaq_gen::api::{
+        peaq_did::events::{AttributeAdded, AttributeRemoved, AttributeUpdated},
+        system::{calls::types::RemarkWithEvent, events::Remarked},
+    },
     Client,
 };
 use serde::{Deserialize, Serialize};
 use sqlx::{Connection, QueryBuilder, SqliteConnection};
 use subxt::{
-    events::{EventDetails, StaticEvent},
-    PolkadotConfig,
+    blocks::Block,
+    events::{EventDetails, Phase, StaticEvent},
+    ext::{codec::Decode, sp_core::twox_128},
+    utils::AccountId32,
+    OnlineClient, PolkadotConfig,
 };
 use tokio::sync::Mutex;
 
 use crate::{
+    amount::{Amount, Planck, Token},
+    anchor::{decode_hash, encode_hash, leaf_hash, proof_root, AnchorRemark, Sibling},
     config::{self, Config},
-    Device, Error, DEVICE_ATTRIBUTE_NAME, V1,
+    device::{self, Device, V1, V2},
+    heartbeat::HeartbeatRemark,
+    location::{self, METERS_PER_DEGREE},
+    payments::{PinLedger, PinResponse},
+    Error, DEVICE_ATTRIBUTE_NAME, SUPERSEDED_BY_ATTRIBUTE_NAME,
 };
 
+// Superseded-by links are followed at most this many times to stop on cycles.
+const MAX_SUPERSEDED_DEPTH: usize = 16;
+/// Uptime is computed from heartbeats of this period.
+const UPTIME_WINDOW: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
 pub(crate) async fn run(cfg: Config) -> Result<(), Error> {
-    let database = Arc::new(Mutex::new(Database::new(&cfg.indexer).await?));
+    let peaq_client = Arc::new(peaq_client::Client::new(&cfg.rpc_url).await?);
+    let token = Token::fetch(&peaq_client).await?;
+    let database = Arc::new(Mutex::new(Database::new(&cfg.indexer, token.clone()).await?));
+    let pins = match &cfg.indexer.payments_dsn {
+        Some(dsn) => Some(Arc::new(Mutex::new(PinLedger::open(dsn).await?))),
+        None => None,
+    };
 
-    let indexer = Indexer::new(&cfg.clone(), database.clone()).await?;
+    let indexer = Indexer::new(peaq_client.clone(), database.clone());
     tokio::spawn(async move {
         if let Err(e) = indexer.run(cfg.indexer.from_block).await {
             error!("failed to run indexer: {e}");
@@ -41,7 +66,7 @@ pub(crate) async fn run(cfg: Config) -> Result<(), Error> {
     });
 
     tokio::spawn(async move {
-        if let Err(e) = run_api(&cfg.indexer, database).await {
+        if let Err(e) = run_api(&cfg.indexer, database, pins, peaq_client, token).await {
             error!("failed to run api: {e}")
         }
     });
@@ -50,36 +75,46 @@ pub(crate) async fn run(cfg: Config) -> Result<(), Error> {
 }
 
 struct Indexer {
-    peaq_client: Client,
+    peaq_client: Arc<Client>,
     database: DatabasePointer,
 }
 
 impl Indexer {
-    async fn new(cfg: &Config, database: DatabasePointer) -> Result<Self, Error> {
-        let peaq_client = peaq_client::Client::new(&cfg.rpc_url).await?;
-        Ok(Self {
+    fn new(peaq_client: Arc<Client>, database: DatabasePointer) -> Self {
+        Self {
             peaq_client,
             database,
-        })
+        }
     }
 
     async fn run(&self, from_block: u64) -> Result<(), Error> {
         let mut current_block_index: u64 = from_block;
         loop {
             trace!("get events in {} block", current_block_index);
-            let events = self.peaq_client.get_events_in_block(current_block_index).await?;
-            let events = match events {
-                Some(events) => events,
+            let block = self.peaq_client.get_block(current_block_index).await?;
+            let block = match block {
+                Some(block) => block,
                 None => {
                     trace!("indexer synced all blocks; waiting for new");
                     tokio::time::sleep(Duration::from_secs(3)).await;
                     continue;
                 }
             };
-            for event in events.iter() {
+            // Remark itself is not in event, so it is read from extrinsic after all events.
+            let mut remarks = Vec::new();
+            for event in block.events().await?.iter() {
                 let event = event?;
+                if let (Phase::ApplyExtrinsic(index), Some(remarked)) =
+                    (event.phase(), event.as_event::<Remarked>()?)
+                {
+                    remarks.push((index, remarked));
+                    continue;
+                }
                 self.process_event(event).await?;
             }
+            if !remarks.is_empty() {
+                self.process_remarks(&block, current_block_index, remarks).await?;
+            }
             // Go to the next block.
             current_block_index += 1;
         }
@@ -101,12 +136,15 @@ impl Indexer {
         let event = event
             .as_event::<AttributeAdded>()?
             .ok_or_else::<Error, _>(|| "event is not AttributeAdded".into())?;
+        if event.2.eq(SUPERSEDED_BY_ATTRIBUTE_NAME.as_bytes()) {
+            return self.supersede(&event.0, &event.1, &event.3).await;
+        }
         if event.2.ne(DEVICE_ATTRIBUTE_NAME.as_bytes()) {
             return Ok(());
         }
         let address = &event.1.to_string();
         debug!("added event received for {}", address);
-        self.database.lock().await.save(address, event.3).await
+        self.save(address, event.3).await
     }
 
     async fn process_updated_event(
@@ -116,12 +154,15 @@ impl Indexer {
         let event = event
             .as_event::<AttributeUpdated>()?
             .ok_or_else::<Error, _>(|| "event is not AttributeUpdated".into())?;
+        if event.2.eq(SUPERSEDED_BY_ATTRIBUTE_NAME.as_bytes()) {
+            return self.supersede(&event.0, &event.1, &event.3).await;
+        }
         if event.2.ne(DEVICE_ATTRIBUTE_NAME.as_bytes()) {
             return Ok(());
         }
         let address = &event.1.to_string();
         debug!("updated event received for {}", address);
-        self.database.lock().await.save(address, event.3).await
+        self.save(address, event.3).await
     }
 
     async fn process_removed_event(
@@ -131,6 +172,11 @@ impl Indexer {
         let event = event
             .as_event::<AttributeRemoved>()?
             .ok_or_else::<Error, _>(|| "event is not AttributeRemoved".into())?;
+        if event.2.eq(SUPERSEDED_BY_ATTRIBUTE_NAME.as_bytes()) {
+            let address = &event.1.to_string();
+            debug!("superseded-by removed event received for {}", address);
+            return self.database.lock().await.delete_superseded(address).await;
+        }
         if event.2.ne(DEVICE_ATTRIBUTE_NAME.as_bytes()) {
             return Ok(());
         }
@@ -138,6 +184,88 @@ impl Indexer {
         debug!("remove event received for {}", address);
         self.database.lock().await.delete(address).await
     }
+
+    /// Saves anchors of device readings and heartbeats which devices sent for themselves.
+    async fn process_remarks(
+        &self,
+        block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+        number: u64,
+        remarks: Vec<(u32, Remarked)>,
+    ) -> Result<(), Error> {
+        let extrinsics = block.extrinsics().await?;
+        // Block time is read only for blocks with heartbeats.
+        let mut time = None;
+        for (index, remarked) in remarks {
+            let Some(extrinsic) = extrinsics.iter().nth(index as usize).transpose()? else {
+                continue;
+            };
+            let Some(call) = extrinsic.as_extrinsic::<RemarkWithEvent>()? else {
+                continue;
+            };
+            if let Some(heartbeat) = HeartbeatRemark::parse(&call.remark) {
+                let block_time = match time {
+                    Some(time) => time,
+                    None => block_time(block).await?,
+                };
+                time = Some(block_time);
+                let device = remarked.sender.to_string();
+                let mut database = self.database.lock().await;
+                if database.save_heartbeat(&device, number, block_time, heartbeat.interval).await? {
+                    trace!("heartbeat of {} in {} block", device, number);
+                } else {
+                    debug!("skip heartbeat of unknown device {} in {} block", device, number);
+                }
+                continue;
+            }
+            let Some(anchor) = AnchorRemark::parse(&call.remark) else {
+                continue;
+            };
+            // Otherwise anybody could anchor data of somebody else's device.
+            if anchor.device != remarked.sender {
+                warn!("skip anchor of {} sent by {}", anchor.device, remarked.sender);
+                continue;
+            }
+            debug!("batch {} of {} is anchored in {} block", anchor.batch, anchor.device, number);
+            let block_hash = format!("{:?}", block.hash());
+            self.database.lock().await.save_anchor(&anchor, number, &block_hash).await?;
+        }
+        Ok(())
+    }
+
+    async fn save(&self, address: &str, data: Vec<u8>) -> Result<(), Error> {
+        let device = match device::decode(&data) {
+            Ok(device) => device,
+            Err(e) => {
+                // Somebody put invalid device on-chain, it should not stop indexing.
+                warn!("skip invalid device for {}: {}", address, e);
+                return Ok(());
+            }
+        };
+        self.database.lock().await.save(address, device).await
+    }
+
+    async fn supersede(
+        &self,
+        owner: &AccountId32,
+        did_account: &AccountId32,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let address = did_account.to_string();
+        // Otherwise anybody could redirect somebody else's device.
+        if owner != did_account {
+            warn!("skip superseded-by of {} created by {}", address, owner);
+            return Ok(());
+        }
+        let superseded_by = match from_utf8(data).map(str::parse::<AccountId32>) {
+            Ok(Ok(superseded_by)) => superseded_by.to_string(),
+            _ => {
+                warn!("skip invalid superseded-by for {}", address);
+                return Ok(());
+            }
+        };
+        debug!("{} is superseded by {}", address, superseded_by);
+        self.database.lock().await.save_superseded(&address, &superseded_by).await
+    }
 }
 
 #[derive(sqlx::FromRow)]
@@ -146,58 +274,135 @@ struct DatabaseDevice {
     version: String,
     data: Vec<u8>,
     updated_at: i64,
+    price_access: Option<String>,
+    price_pin: Option<String>,
+    attested: bool,
+    // Liveness is computed in query, plain device rows don't have it.
+    #[sqlx(default)]
+    last_seen: Option<i64>,
+    #[sqlx(default)]
+    online: bool,
+    #[sqlx(default)]
+    uptime: Option<f64>,
+}
+
+#[derive(sqlx::FromRow)]
+struct AnchorRow {
+    batch: i64,
+    size: i64,
+    block: i64,
+    block_hash: String,
 }
 
 type DatabasePointer = Arc<Mutex<Database>>;
+type PinsPointer = Arc<Mutex<PinLedger>>;
 
 struct Database {
     conn: SqliteConnection,
+    token: Token,
+    max_heartbeat_interval: u64,
 }
 
 impl Database {
-    async fn new(cfg: &config::Indexer) -> Result<Self, Error> {
-        // Create file if not exists to be able to open and migrate.
-        let file_name = cfg.dsn.split(':').collect::<Vec<&str>>()[1];
-        if let Err(e) =
-            OpenOptions::new().read(true).write(true).create(true).create_new(true).open(file_name)
-        {
-            match e.kind() {
-                ErrorKind::AlreadyExists => (),
-                _ => return Err(e.into()),
-            }
-        }
-
-        let mut conn = SqliteConnection::connect(&cfg.dsn).await?;
-        conn.ping().await?;
-
+    async fn new(cfg: &config::Indexer, token: Token) -> Result<Self, Error> {
+        let mut conn = open_sqlite(&cfg.dsn).await?;
         let migrator = sqlx::migrate!("./migrations/");
         migrator.run_direct(&mut conn).await?;
 
-        Ok(Self { conn })
+        let mut database = Self {
+            conn,
+            token,
+            max_heartbeat_interval: cfg.max_heartbeat_interval,
+        };
+        database.fill_prices().await?;
+        Ok(database)
     }
 
-    async fn save(&mut self, address: &str, data: Vec<u8>) -> Result<(), Error> {
-        let device: Device = serde_json::from_slice(&data)?;
+    // Devices saved before planck prices were introduced don't have price columns.
+    async fn fill_prices(&mut self) -> Result<(), Error> {
+        let devices: Vec<DatabaseDevice> =
+            sqlx::query_as("select * from devices where price_access is null or price_pin is null")
+                .fetch_all(&mut self.conn)
+                .await?;
+        for device in devices {
+            let data = format!(r#"{{"{}":{}}}"#, device.version, from_utf8(&device.data)?);
+            let prices = device::decode(data.as_bytes()).and_then(|v| v.prices(&self.token));
+            let (price_access, price_pin) = match prices {
+                Ok(prices) => prices,
+                Err(e) => {
+                    warn!("failed to fill prices for {}: {}", device.address, e);
+                    continue;
+                }
+            };
+            sqlx::query("update devices set price_access = ?2, price_pin = ?3 where address = ?1")
+                .bind(&device.address)
+                .bind(price_access.to_padded())
+                .bind(price_pin.to_padded())
+                .execute(&mut self.conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn save(&mut self, address: &str, device: Device) -> Result<(), Error> {
+        // Prices which can't be converted to planck of this token don't stop indexing.
+        let (price_access, price_pin) = match device.prices(&self.token) {
+            Ok(prices) => prices,
+            Err(e) => {
+                warn!("skip device {}: {}", address, e);
+                return Ok(());
+            }
+        };
+        let attested = match &device {
+            Device::V1(_) => false,
+            Device::V2(device) => device.is_attested(&address.parse()?),
+        };
+        let (location, accuracy) = device.location();
+        let (latitude, longitude) = device::parse_location(location)?;
         let data: Vec<u8> = match &device {
             Device::V1(device) => serde_json::to_vec(&device)?,
+            Device::V2(device) => serde_json::to_vec(&device)?,
         };
         sqlx::query(
             r#"
-                insert into devices (address, version, data, updated_at) values (?1, ?2, ?3, ?4) 
-                on conflict(address) do update set data = ?3, updated_at = ?4
+                insert into devices (
+                  address, version, data, updated_at, price_access, price_pin, attested,
+                  latitude, longitude, location_accuracy
+                )
+                values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                on conflict(address) do update
+                set version = ?2, data = ?3, updated_at = ?4, price_access = ?5, price_pin = ?6,
+                  attested = ?7, latitude = ?8, longitude = ?9, location_accuracy = ?10
             "#,
         )
         .bind(address)
         .bind(device.version())
         .bind(data)
         .bind(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64)
+        .bind(price_access.to_padded())
+        .bind(price_pin.to_padded())
+        .bind(attested)
+        .bind(latitude)
+        .bind(longitude)
+        .bind(f64::from(accuracy))
         .execute(&mut self.conn)
         .await?;
         Ok(())
     }
 
-    async fn query(&mut self, params: GetDevicesParams) -> Result<Vec<DatabaseDevice>, Error> {
-        let mut query: QueryBuilder<sqlx::Sqlite> = Self::prepare_query::<sqlx::Sqlite>(&params)?;
+    async fn query(
+        &mut self,
+        params: GetDevicesParams,
+    ) -> Result<Vec<DatabaseDevice>, ErrorResponse> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        // Query is built from request parameters only, so its errors are client errors.
+        let mut query: QueryBuilder<sqlx::Sqlite> =
+            Self::prepare_query::<sqlx::Sqlite>(&params, &self.token, now).map_err(|e| {
+                ErrorResponse {
+                    status_code: StatusCode::BAD_REQUEST,
+                    message: e.to_string(),
+                }
+            })?;
         trace!("sql query: {}", query.sql());
         let query = query.build_query_as::<DatabaseDevice>();
         let devices = query.fetch_all(&mut self.conn).await?;
@@ -206,41 +411,258 @@ impl Database {
 
     fn prepare_query<'a, DB: sqlx::Database>(
         params: &'a GetDevicesParams,
+        token: &Token,
+        now: u64,
     ) -> Result<QueryBuilder<'a, DB>, Error>
     where
         std::string::String: sqlx::Encode<'a, DB>,
         std::string::String: sqlx::Type<DB>,
         u32: sqlx::Encode<'a, DB>,
         u32: sqlx::Type<DB>,
+        i64: sqlx::Encode<'a, DB>,
+        i64: sqlx::Type<DB>,
         f64: sqlx::Encode<'a, DB>,
         f64: sqlx::Type<DB>,
     {
-        let mut query: QueryBuilder<DB> = QueryBuilder::new("select * from devices");
+        let sort = Self::sort_column(params.sort.as_deref().unwrap_or("updated_at"))?;
+        let order = match params.order.as_deref().unwrap_or("desc") {
+            "asc" => "asc",
+            "desc" => "desc",
+            order => return Err(format!("order must be asc or desc: {order}").into()),
+        };
+        // Device is online until it misses two heartbeats. Every heartbeat covers its interval,
+        // so uptime is covered part of time from the first heartbeat in uptime window
+        // until the end of interval of the latest one.
+        let now = now as i64;
+        let mut query: QueryBuilder<DB> = QueryBuilder::new(
+            "select * from (select devices.*, liveness.last_seen, \
+             coalesce(liveness.last_seen >= ",
+        );
+        query
+            .push_bind(now)
+            .push(
+                " - 2 * liveness.interval, 0) as online, \
+                 (select min(100.0, 100.0 * sum(interval) / (",
+            )
+            .push_bind(now)
+            .push(
+                " - min(time) + max(interval))) from heartbeats \
+                 where heartbeats.device = devices.address and time >= ",
+            )
+            .push_bind(now - UPTIME_WINDOW.as_secs() as i64)
+            .push(
+                ") as uptime from devices \
+                 left join liveness on liveness.device = devices.address)",
+            );
         if let Some(address) = &params.address {
             query.push(" where address = ");
             query.push_bind(address);
             return Ok(query);
         }
+        let near = params.near.as_deref().map(device::parse_location).transpose()?;
+        if !params.radius.is_finite() || params.radius < 0.0 {
+            return Err("radius must be non-negative number of meters".into());
+        }
         let filters_len = params.filters.len();
-        if filters_len != 0 {
+        if filters_len != 0 || near.is_some() {
             query.push(" where ");
             for (i, filter) in params.filters.iter().enumerate() {
                 Self::is_filter_allowed(filter)?;
                 if i != 0 {
                     query.push(" AND ");
                 }
-                query.push(format!(
-                    "json_extract(data, '$.{}') {} ",
-                    filter.field, filter.condition
-                ));
-                push_bind(&mut query, &filter.value);
+                match filter.field.as_str() {
+                    // Prices are compared as zero padded planck to not lose precision.
+                    "price_access" | "price_pin" => {
+                        query.push(format!("{} {} ", filter.field, filter.condition));
+                        let price = filter
+                            .value
+                            .parse::<Amount>()
+                            .map_err(|e| {
+                                format!(
+                                    "{} must be an amount: {}: {}",
+                                    filter.field, filter.value, e
+                                )
+                            })?
+                            .to_planck(token)?;
+                        query.push_bind(price.to_padded());
+                    }
+                    // Signature is checked on save, so filter uses stored flag.
+                    "attested" | "online" => {
+                        query.push(format!("{} {} ", filter.field, filter.condition));
+                        let value: bool = filter.value.parse().map_err(|_| {
+                            format!("{} must be true or false: {}", filter.field, filter.value)
+                        })?;
+                        query.push_bind(value as u32);
+                    }
+                    "last_seen" | "uptime" => {
+                        query.push(format!("{} {} ", filter.field, filter.condition));
+                        let value: f64 = filter.value.parse().map_err(|_| {
+                            format!("{} must be a number: {}", filter.field, filter.value)
+                        })?;
+                        query.push_bind(value);
+                    }
+                    _ => {
+                        query.push(format!(
+                            "json_extract(data, '$.{}') {} ",
+                            filter.field, filter.condition
+                        ));
+                        match filter.value.parse::<f64>() {
+                            Ok(value) => query.push_bind(value),
+                            Err(_) => query.push_bind(filter.value.clone()),
+                        };
+                    }
+                }
+            }
+            // Device matches if circle of its location accuracy intersects search circle.
+            // Equirectangular distance is precise enough for radius up to hundreds of kilometers.
+            if let Some((lat, lon)) = near {
+                if filters_len != 0 {
+                    query.push(" AND ");
+                }
+                let (lat_scale, lon_scale) = (METERS_PER_DEGREE, location::lon_scale(lat));
+                query
+                    .push("((latitude - ")
+                    .push_bind(lat)
+                    .push(") * ")
+                    .push_bind(lat_scale)
+                    .push(") * ((latitude - ")
+                    .push_bind(lat)
+                    .push(") * ")
+                    .push_bind(lat_scale)
+                    .push(") + ((longitude - ")
+                    .push_bind(lon)
+                    .push(") * ")
+                    .push_bind(lon_scale)
+                    .push(") * ((longitude - ")
+                    .push_bind(lon)
+                    .push(") * ")
+                    .push_bind(lon_scale)
+                    .push(") <= (location_accuracy + ")
+                    .push_bind(params.radius)
+                    .push(") * (location_accuracy + ")
+                    .push_bind(params.radius)
+                    .push(")");
             }
         }
-        query.push(" order by updated_at desc");
+        // Devices without heartbeats go last in both orders.
+        query.push(format!(" order by {sort} is null, {sort} {order}"));
         query.push(" limit ").push_bind(params.limit).push(" offset ").push_bind(params.offset);
         Ok(query)
     }
 
+    // Sort field can't be bound too, so it is picked from allowed ones.
+    fn sort_column(field: &str) -> Result<&'static str, Error> {
+        Ok(match field {
+            "updated_at" => "updated_at",
+            "last_seen" => "last_seen",
+            "uptime" => "uptime",
+            "price_access" => "price_access",
+            "price_pin" => "price_pin",
+            _ => return Err(format!("{field} field is not supported for sorting").into()),
+        })
+    }
+
+    /// Saves heartbeat and prunes heartbeats of device which are out of uptime window.
+    ///
+    /// Returns false if device is unknown, otherwise anybody could fill database with remarks.
+    /// Interval is capped, since sender chooses it.
+    async fn save_heartbeat(
+        &mut self,
+        device: &str,
+        block: u64,
+        time: u64,
+        interval: u64,
+    ) -> Result<bool, Error> {
+        let interval = interval.min(self.max_heartbeat_interval);
+        let mut tx = self.conn.begin().await?;
+        let known: bool =
+            sqlx::query_scalar("select exists(select 1 from devices where address = ?1)")
+                .bind(device)
+                .fetch_one(&mut *tx)
+                .await?;
+        if !known {
+            return Ok(false);
+        }
+        sqlx::query(
+            r#"
+                insert into heartbeats (device, block, time, interval)
+                values (?1, ?2, ?3, ?4)
+                on conflict(device, block) do nothing
+            "#,
+        )
+        .bind(device)
+        .bind(block as i64)
+        .bind(time as i64)
+        .bind(interval as i64)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"
+                insert into liveness (device, last_seen, interval)
+                values (?1, ?2, ?3)
+                on conflict(device) do update
+                set last_seen = ?2, interval = ?3
+                where last_seen <= ?2
+            "#,
+        )
+        .bind(device)
+        .bind(time as i64)
+        .bind(interval as i64)
+        .execute(&mut *tx)
+        .await?;
+        // Window ends at the latest heartbeat, so replayed old blocks don't keep old heartbeats.
+        sqlx::query(
+            r#"
+                delete from heartbeats
+                where device = ?1
+                  and time < (select last_seen from liveness where device = ?1) - ?2
+            "#,
+        )
+        .bind(device)
+        .bind(UPTIME_WINDOW.as_secs() as i64)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Saves anchor, the first anchor of the same root is kept.
+    async fn save_anchor(
+        &mut self,
+        anchor: &AnchorRemark,
+        block: u64,
+        block_hash: &str,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+                insert into anchors (device, batch, root, size, block, block_hash)
+                values (?1, ?2, ?3, ?4, ?5, ?6)
+                on conflict(device, root) do nothing
+            "#,
+        )
+        .bind(anchor.device.to_string())
+        .bind(anchor.batch as i64)
+        .bind(encode_hash(&anchor.root))
+        .bind(anchor.size as i64)
+        .bind(block as i64)
+        .bind(block_hash)
+        .execute(&mut self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn anchor(&mut self, device: &str, root: &str) -> Result<Option<AnchorRow>, Error> {
+        let anchor = sqlx::query_as(
+            "select batch, size, block, block_hash from anchors where device = ?1 and root = ?2",
+        )
+        .bind(device)
+        .bind(root)
+        .fetch_optional(&mut self.conn)
+        .await?;
+        Ok(anchor)
+    }
+
     async fn delete(&mut self, address: &str) -> Result<(), Error> {
         sqlx::query("delete from devices where address = ?1")
             .bind(address)
@@ -249,6 +671,69 @@ impl Database {
         Ok(())
     }
 
+    async fn save_superseded(&mut self, address: &str, superseded_by: &str) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+                insert into superseded (address, superseded_by, updated_at)
+                values (?1, ?2, ?3)
+                on conflict(address) do update
+                set superseded_by = ?2, updated_at = ?3
+            "#,
+        )
+        .bind(address)
+        .bind(superseded_by)
+        .bind(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64)
+        .execute(&mut self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_superseded(&mut self, address: &str) -> Result<(), Error> {
+        sqlx::query("delete from superseded where address = ?1")
+            .bind(address)
+            .execute(&mut self.conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Follows superseded-by links and returns current address of device.
+    async fn current_address(&mut self, address: &str) -> Result<String, Error> {
+        let mut current = address.to_string();
+        let mut visited = HashSet::new();
+        while visited.len() < MAX_SUPERSEDED_DEPTH && visited.insert(current.clone()) {
+            let superseded_by: Option<String> =
+                sqlx::query_scalar("select superseded_by from superseded where address = ?1")
+                    .bind(&current)
+                    .fetch_optional(&mut self.conn)
+                    .await?;
+            match superseded_by {
+                Some(superseded_by) => current = superseded_by,
+                None => break,
+            }
+        }
+        Ok(current)
+    }
+
+    /// Returns all addresses which were superseded by this address, directly or not.
+    async fn previous_addresses(&mut self, address: &str) -> Result<Vec<String>, Error> {
+        // Union without "all" stops recursion on cycles.
+        let addresses = sqlx::query_scalar(
+            r#"
+                with recursive previous(address) as (
+                    select address from superseded where superseded_by = ?1
+                    union
+                    select superseded.address from superseded
+                    join previous on superseded.superseded_by = previous.address
+                )
+                select address from previous where address != ?1
+            "#,
+        )
+        .bind(address)
+        .fetch_all(&mut self.conn)
+        .await?;
+        Ok(addresses)
+    }
+
     // I didn't find a way to properly bind JSON field name and condition to sql query,
     // so it is required to manually check for allowed fields and conditions.
     // For value we don't need this check as we can bind it.
@@ -259,7 +744,17 @@ impl Database {
     }
 
     fn is_field_allowed(field: &str) -> Result<(), Error> {
-        if matches!(field, "data_type" | "location" | "price_access" | "price_pin") {
+        if matches!(
+            field,
+            "data_type"
+                | "location"
+                | "price_access"
+                | "price_pin"
+                | "attested"
+                | "online"
+                | "last_seen"
+                | "uptime"
+        ) {
             return Ok(());
         }
         Err("received untrusted filter".into())
@@ -273,19 +768,34 @@ impl Database {
     }
 }
 
-fn push_bind<'a, DB: sqlx::Database>(query: &mut QueryBuilder<'a, DB>, value: &'a Value)
-where
-    std::string::String: sqlx::Encode<'a, DB>,
-    std::string::String: sqlx::Type<DB>,
-    f64: sqlx::Encode<'a, DB>,
-    f64: sqlx::Type<DB>,
-{
-    match value {
-        Value::String(string) => query.push_bind(string),
-        Value::F64(f64) => query.push_bind(f64),
-    };
+/// Returns block time in unix seconds from timestamp pallet storage.
+async fn block_time(
+    block: &Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+) -> Result<u64, Error> {
+    // Generated metadata has no timestamp pallet, so its storage is read by raw key.
+    let mut key = twox_128(b"Timestamp").to_vec();
+    key.extend(twox_128(b"Now"));
+    let now = block.storage().fetch_raw(key).await?.ok_or("block has no timestamp")?;
+    let millis = u64::decode(&mut now.as_slice())?;
+    Ok(millis / 1000)
+}
+
+/// Opens sqlite database and creates its file if it doesn't exist.
+pub(crate) async fn open_sqlite(dsn: &str) -> Result<SqliteConnection, Error> {
+    // Create file if not exists to be able to open and migrate.
+    let file_name = dsn.split(':').collect::<Vec<&str>>()[1];
+    if let Err(e) = OpenOptions::new().read(true).write(true).create_new(true).open(file_name) {
+        match e.kind() {
+            ErrorKind::AlreadyExists => (),
+            _ => return Err(e.into()),
+        }
+    }
+    let mut conn = SqliteConnection::connect(dsn).await?;
+    conn.ping().await?;
+    Ok(conn)
 }
 
+#[derive(Debug)]
 struct ErrorResponse {
     status_code: StatusCode,
     message: String,
@@ -313,10 +823,24 @@ impl IntoResponse for ErrorResponse {
     }
 }
 
-async fn run_api(cfg: &config::Indexer, database: DatabasePointer) -> Result<(), Error> {
+async fn run_api(
+    cfg: &config::Indexer,
+    database: DatabasePointer,
+    pins: Option<PinsPointer>,
+    peaq_client: Arc<Client>,
+    token: Token,
+) -> Result<(), Error> {
     let app = Router::new()
         .route("/devices", get(get_devices))
+        .route("/devices/:address", get(get_device))
+        .route("/devices/:address/pins", get(get_device_pins))
+        .route("/accounts/:address/balance", get(get_balance))
+        .route("/schemas/device/:version", get(get_device_schema))
+        .route("/proofs/verify", post(verify_proof))
         .layer(Extension(database))
+        .layer(Extension(pins))
+        .layer(Extension(peaq_client))
+        .layer(Extension(token))
         .fallback(fallback);
     let addr = format!("{}:{}", cfg.host, cfg.port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
@@ -353,6 +877,15 @@ struct GetDevicesParams {
     address: Option<String>,
     #[serde(default)]
     filters: Vec<Filter>,
+    /// Location "latitude,longitude" which devices are searched around.
+    near: Option<String>,
+    /// Search radius in meters around near location.
+    #[serde(default)]
+    radius: f64,
+    /// Field to sort by, updated_at by default.
+    sort: Option<String>,
+    /// "asc" or "desc", desc by default.
+    order: Option<String>,
     #[serde(default)]
     limit: u32,
     #[serde(default)]
@@ -364,6 +897,10 @@ impl Default for GetDevicesParams {
         Self {
             address: None,
             filters: vec![],
+            near: None,
+            radius: 0.0,
+            sort: None,
+            order: None,
             limit: 10,
             offset: 0,
         }
@@ -374,25 +911,8 @@ impl Default for GetDevicesParams {
 struct Filter {
     field: String,
     condition: String, // "=", "<", ">"
-    value: Value,
-}
-
-enum Value {
-    String(String),
-    F64(f64),
-}
-
-impl<'de> Deserialize<'de> for Value {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let value = String::deserialize(deserializer)?;
-        match value.parse::<f64>() {
-            Ok(v) => Ok(Value::F64(v)),
-            _ => Ok(Value::String(value)),
-        }
-    }
+    // Number, string or token amount for prices, e.g. "0.9 AGUNG".
+    value: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -400,7 +920,31 @@ struct DeviceResponse {
     address: String,
     version: String,
     device: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prices: Option<Prices>,
+    /// Whether attributes are signed by device key of attestation.
+    #[serde(default)]
+    attested: bool,
+    /// Block time of the last heartbeat in unix seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_seen: Option<u64>,
+    /// Whether device sent heartbeat within two of its intervals.
+    #[serde(default)]
+    online: bool,
+    /// Percent of time device was alive in the last 7 days or since its first heartbeat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uptime: Option<f64>,
     updated_at: u64,
+    /// Addresses of device before key rotations.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    previous_addresses: Vec<String>,
+}
+
+/// Human readable device prices.
+#[derive(Serialize, Deserialize)]
+struct Prices {
+    access: String,
+    pin: String,
 }
 
 async fn get_devices(
@@ -412,34 +956,500 @@ async fn get_devices(
             return Err(format!("{} field is not supporting for filtering", filter.field).into());
         }
     }
-    let internal_devices = database.lock().await.query(params).await?;
+    let mut database = database.lock().await;
+    let internal_devices = database.query(params).await?;
     let mut external_devices: Vec<DeviceResponse> = Vec::with_capacity(internal_devices.len());
     for internal_device in &internal_devices {
-        let device: serde_json::Value = {
-            match internal_device.version.as_str() {
-                V1 => {
-                    let device: serde_json::Value = serde_json::from_slice(&internal_device.data)?;
-                    device
-                }
-                _ => {
-                    return Err(format!(
-                        "unknown version to convert internal device to external :{}",
-                        internal_device.version
-                    )
-                    .into())
-                }
-            }
-        };
-        external_devices.push(DeviceResponse {
-            address: internal_device.address.clone(),
-            version: internal_device.version.clone(),
-            device,
-            updated_at: internal_device.updated_at as u64,
-        })
+        external_devices.push(device_response(&mut database, internal_device).await?);
     }
     Ok((StatusCode::OK, Json(external_devices)))
 }
 
+/// Returns single device; addresses of rotated devices redirect to their current address.
+async fn get_device(
+    Extension(database): Extension<DatabasePointer>,
+    Path(address): Path<String>,
+) -> Result<Response, ErrorResponse> {
+    let mut database = database.lock().await;
+    let current_address = database.current_address(&address).await?;
+    if current_address != address {
+        return Ok(Redirect::permanent(&format!("/devices/{current_address}")).into_response());
+    }
+    let params = GetDevicesParams {
+        address: Some(address),
+        ..Default::default()
+    };
+    let internal_devices = database.query(params).await?;
+    let internal_device = internal_devices.first().ok_or_else(|| ErrorResponse {
+        status_code: StatusCode::NOT_FOUND,
+        message: String::new(),
+    })?;
+    let device = device_response(&mut database, internal_device).await?;
+    Ok((StatusCode::OK, Json(device)).into_response())
+}
+
+async fn device_response(
+    database: &mut Database,
+    internal_device: &DatabaseDevice,
+) -> Result<DeviceResponse, ErrorResponse> {
+    let device: serde_json::Value = {
+        match internal_device.version.as_str() {
+            V1 | V2 => {
+                let device: serde_json::Value = serde_json::from_slice(&internal_device.data)?;
+                device
+            }
+            _ => {
+                return Err(format!(
+                    "unknown version to convert internal device to external :{}",
+                    internal_device.version
+                )
+                .into())
+            }
+        }
+    };
+    let prices = match (&internal_device.price_access, &internal_device.price_pin) {
+        (Some(access), Some(pin)) => Some(Prices {
+            access: database.token.format(access.parse()?),
+            pin: database.token.format(pin.parse()?),
+        }),
+        _ => None,
+    };
+    Ok(DeviceResponse {
+        address: internal_device.address.clone(),
+        version: internal_device.version.clone(),
+        device,
+        prices,
+        attested: internal_device.attested,
+        last_seen: internal_device.last_seen.map(|v| v as u64),
+        online: internal_device.online,
+        uptime: internal_device.uptime,
+        updated_at: internal_device.updated_at as u64,
+        previous_addresses: database.previous_addresses(&internal_device.address).await?,
+    })
+}
+
+#[derive(Deserialize, Default)]
+struct GetPinsParams {
+    buyer: Option<String>,
+}
+
+/// Returns ranges of device data which are pinned and not expired yet.
+async fn get_device_pins(
+    Extension(pins): Extension<Option<PinsPointer>>,
+    Extension(peaq_client): Extension<Arc<Client>>,
+    Path(address): Path<String>,
+    QueryArray(params): QueryArray<GetPinsParams>,
+) -> Result<impl IntoResponse, ErrorResponse> {
+    let pins = pins.ok_or_else(|| ErrorResponse {
+        status_code: StatusCode::NOT_FOUND,
+        message: "pins are not served, set indexer.payments_dsn".to_string(),
+    })?;
+    let finalized = peaq_client.get_finalized_block_number().await?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let pins: Vec<PinResponse> =
+        pins.lock().await.pins(&address, params.buyer.as_deref(), finalized, now).await?;
+    Ok((StatusCode::OK, Json(pins)))
+}
+
+#[derive(Serialize, Deserialize)]
+struct BalanceResponse {
+    address: String,
+    planck: Planck,
+    /// Human readable balance.
+    balance: String,
+}
+
+async fn get_balance(
+    Extension(peaq_client): Extension<Arc<Client>>,
+    Extension(token): Extension<Token>,
+    Path(address): Path<String>,
+) -> Result<impl IntoResponse, ErrorResponse> {
+    let account_id: AccountId32 = address.parse().map_err(|e| ErrorResponse {
+        status_code: StatusCode::BAD_REQUEST,
+        message: format!("invalid address: {e}"),
+    })?;
+    let balance = Planck(peaq_client.get_balance(&account_id).await?);
+    Ok((
+        StatusCode::OK,
+        Json(BalanceResponse {
+            address: account_id.to_string(),
+            planck: balance,
+            balance: token.format(balance),
+        }),
+    ))
+}
+
+async fn get_device_schema(
+    Path(version): Path<String>,
+) -> Result<impl IntoResponse, ErrorResponse> {
+    let schema = device::schema(&version).ok_or_else(|| ErrorResponse {
+        status_code: StatusCode::NOT_FOUND,
+        message: format!("unknown device version: {version}"),
+    })?;
+    Ok((StatusCode::OK, Json(schema)))
+}
+
+/// Reading with its Merkle inclusion proof, e.g. output of `provisioner anchor proof`.
+#[derive(Serialize, Deserialize)]
+struct VerifyRequest {
+    device: String,
+    data: String,
+    received_at: u64,
+    proof: Vec<Sibling>,
+    /// Expected root, it is computed from proof if not set.
+    root: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VerifyResponse {
+    /// Whether proof leads to root anchored by device.
+    valid: bool,
+    root: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    batch: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block_hash: Option<String>,
+}
+
+/// Checks that reading is part of batch which device anchored on chain.
+async fn verify_proof(
+    Extension(database): Extension<DatabasePointer>,
+    Json(request): Json<VerifyRequest>,
+) -> Result<impl IntoResponse, ErrorResponse> {
+    let bad_request = |message: String| ErrorResponse {
+        status_code: StatusCode::BAD_REQUEST,
+        message,
+    };
+    let device: AccountId32 =
+        request.device.parse().map_err(|e| bad_request(format!("invalid device: {e}")))?;
+    let leaf = leaf_hash(request.received_at, &request.data);
+    let root = proof_root(&leaf, &request.proof)
+        .map_err(|e| bad_request(format!("invalid proof: {e}")))?;
+    let expected = match &request.root {
+        Some(root) => decode_hash(root).map_err(|e| bad_request(format!("invalid root: {e}")))?,
+        None => root,
+    };
+    let matches = expected == root;
+    let root = encode_hash(&root);
+    let anchor = match matches {
+        true => database.lock().await.anchor(&device.to_string(), &root).await?,
+        false => None,
+    };
+    Ok((
+        StatusCode::OK,
+        Json(VerifyResponse {
+            valid: anchor.is_some(),
+            root,
+            batch: anchor.as_ref().map(|v| v.batch as u64),
+            size: anchor.as_ref().map(|v| v.size as u64),
+            block: anchor.as_ref().map(|v| v.block as u64),
+            block_hash: anchor.map(|v| v.block_hash),
+        }),
+    ))
+}
+
 async fn fallback() -> impl IntoResponse {
     StatusCode::NOT_FOUND
 }
+
+#[cfg(test)]
+mod tests {
+    use subxt::ext::sp_core::{ed25519, Pair};
+
+    use super::*;
+    use crate::testing::{agung, TempDb};
+
+    #[tokio::test]
+    async fn superseded_devices() {
+        let db = TempDb::new("indexer");
+        let cfg = config::Indexer {
+            dsn: db.dsn(),
+            ..Default::default()
+        };
+        let mut database = Database::new(&cfg, agung()).await.unwrap();
+        database.save_superseded("a", "b").await.unwrap();
+        database.save_superseded("b", "c").await.unwrap();
+        assert_eq!(database.current_address("a").await.unwrap(), "c");
+        assert_eq!(database.current_address("c").await.unwrap(), "c");
+        let mut previous = database.previous_addresses("c").await.unwrap();
+        previous.sort();
+        assert_eq!(previous, ["a", "b"]);
+
+        // Cycles don't hang.
+        database.save_superseded("c", "a").await.unwrap();
+        database.current_address("a").await.unwrap();
+        database.previous_addresses("a").await.unwrap();
+
+        database.delete_superseded("c").await.unwrap();
+        assert!(database.previous_addresses("a").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn anchors() {
+        let db = TempDb::new("indexer-anchors");
+        let cfg = config::Indexer {
+            dsn: db.dsn(),
+            ..Default::default()
+        };
+        let mut database = Database::new(&cfg, agung()).await.unwrap();
+        let anchor = AnchorRemark {
+            device: AccountId32([1; 32]),
+            batch: 1,
+            root: [2; 32],
+            size: 3,
+        };
+        database.save_anchor(&anchor, 10, "0x10").await.unwrap();
+        // Root anchored again doesn't move to later block.
+        database.save_anchor(&anchor, 20, "0x20").await.unwrap();
+        let device = anchor.device.to_string();
+        let row = database.anchor(&device, &encode_hash(&[2; 32])).await.unwrap().unwrap();
+        assert_eq!((row.batch, row.size, row.block, row.block_hash.as_str()), (1, 3, 10, "0x10"));
+        assert!(database.anchor(&device, &encode_hash(&[3; 32])).await.unwrap().is_none());
+        let other = AccountId32([4; 32]).to_string();
+        assert!(database.anchor(&other, &encode_hash(&[2; 32])).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn attested_devices() {
+        let db = TempDb::new("indexer-attested");
+        let cfg = config::Indexer {
+            dsn: db.dsn(),
+            ..Default::default()
+        };
+        let mut database = Database::new(&cfg, agung()).await.unwrap();
+        let device = || device::DeviceV2 {
+            data_type: "cctv-camera".to_string(),
+            location: "40.1949288120072,44.55177253802097".to_string(),
+            location_accuracy: None,
+            price_access: Planck(42),
+            price_pin: Planck(445),
+            additional: None,
+            private: None,
+            attestation: None,
+        };
+        let (attested, plain) = (AccountId32([1; 32]), AccountId32([2; 32]));
+        let key = ed25519::Pair::from_seed(&[3; 32]);
+        let mut attested_device = device();
+        attested_device.attest(&attested, &key).unwrap();
+        database.save(&attested.to_string(), Device::V2(attested_device)).await.unwrap();
+        // Signature of other account doesn't attest device.
+        let mut copied = device();
+        copied.attest(&attested, &key).unwrap();
+        database.save(&plain.to_string(), Device::V2(copied)).await.unwrap();
+
+        let query = |value: &str| GetDevicesParams {
+            filters: vec![Filter {
+                field: "attested".to_string(),
+                condition: "=".to_string(),
+                value: value.to_string(),
+            }],
+            ..Default::default()
+        };
+        let devices = database.query(query("true")).await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].address, attested.to_string());
+        assert!(devices[0].attested);
+        // Devices from initial migration are not attested too.
+        let devices = database.query(query("false")).await.unwrap();
+        assert!(devices.iter().all(|d| !d.attested));
+        assert!(devices.iter().any(|d| d.address == plain.to_string()));
+        let error = database.query(query("yes")).await.err().unwrap();
+        assert_eq!(error.status_code, StatusCode::BAD_REQUEST);
+        let mut params = query("true");
+        params.filters[0].field = "price_access".to_string();
+        params.filters[0].value = "cheap".to_string();
+        let error = database.query(params).await.err().unwrap();
+        assert_eq!(error.status_code, StatusCode::BAD_REQUEST);
+        assert!(error.message.starts_with("price_access must be an amount: cheap"));
+    }
+
+    #[tokio::test]
+    async fn geo_search() {
+        let db = TempDb::new("indexer-geo");
+        let cfg = config::Indexer {
+            dsn: db.dsn(),
+            ..Default::default()
+        };
+        let mut database = Database::new(&cfg, agung()).await.unwrap();
+        // Published about 1.1 km north of search point with 2 km accuracy.
+        let (coarse, exact) = (AccountId32([1; 32]), AccountId32([2; 32]));
+        let device = |location: &str, location_accuracy| device::DeviceV2 {
+            data_type: "cctv-camera".to_string(),
+            location: location.to_string(),
+            location_accuracy,
+            price_access: Planck(42),
+            price_pin: Planck(445),
+            additional: None,
+            private: None,
+            attestation: None,
+        };
+        let coarse_device = device("52.53,13.4", Some(2000));
+        database.save(&coarse.to_string(), Device::V2(coarse_device)).await.unwrap();
+        database.save(&exact.to_string(), Device::V2(device("52.53,13.4", None))).await.unwrap();
+
+        let query = |near: &str, radius| GetDevicesParams {
+            near: Some(near.to_string()),
+            radius,
+            ..Default::default()
+        };
+        let addresses = |devices: Vec<DatabaseDevice>| -> HashSet<String> {
+            devices.into_iter().map(|d| d.address).collect()
+        };
+        let devices = database.query(query("52.52,13.4", 500.0)).await.unwrap();
+        assert_eq!(addresses(devices), HashSet::from([coarse.to_string()]));
+        let devices = database.query(query("52.52,13.4", 1200.0)).await.unwrap();
+        assert_eq!(addresses(devices), HashSet::from([coarse.to_string(), exact.to_string()]));
+        let devices = database.query(query("52.5,13.4", 1000.0)).await.unwrap();
+        assert!(devices.is_empty());
+        // Device from initial migration is found by its location too.
+        let devices = database.query(query("52.569,13.355", 100.0)).await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert!(database.query(query("52.52", 100.0)).await.is_err());
+        assert!(database.query(query("52.52,13.4", -1.0)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn invalid_prices() {
+        let db = TempDb::new("indexer-prices");
+        let cfg = config::Indexer {
+            dsn: db.dsn(),
+            ..Default::default()
+        };
+        let mut database = Database::new(&cfg, agung()).await.unwrap();
+        // Prices of v1 device which is decoded without schema can't be converted to planck.
+        let address = AccountId32([1; 32]).to_string();
+        let device = Device::V1(device::DeviceV1 {
+            data_type: "cctv-camera".to_string(),
+            location: "40.1949288120072,44.55177253802097".to_string(),
+            price_access: 1e-19,
+            price_pin: 1e300,
+            additional: None,
+        });
+        database.save(&address, device).await.unwrap();
+        let query = "select count(*) from devices where address = ?1";
+        let count: i64 =
+            sqlx::query_scalar(query).bind(&address).fetch_one(&mut database.conn).await.unwrap();
+        assert_eq!(count, 0);
+
+        // Device saved before prices were introduced doesn't stop indexer from starting.
+        sqlx::query("insert into devices (address, version, data, updated_at) values (?1, ?2, ?3, 0)")
+            .bind(&address)
+            .bind(V1)
+            .bind(
+                br#"{"data_type":"cctv-camera","location":"40.19,44.55","price_access":1e300,"price_pin":1}"#
+                    .to_vec(),
+            )
+            .execute(&mut database.conn)
+            .await
+            .unwrap();
+        let mut database = Database::new(&cfg, agung()).await.unwrap();
+        let count: i64 =
+            sqlx::query_scalar(query).bind(&address).fetch_one(&mut database.conn).await.unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn liveness() {
+        let db = TempDb::new("indexer-liveness");
+        let cfg = config::Indexer {
+            dsn: db.dsn(),
+            max_heartbeat_interval: 3600,
+            ..Default::default()
+        };
+        let mut database = Database::new(&cfg, agung()).await.unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let hour = 3600;
+        let (alive, dead) = (AccountId32([1; 32]).to_string(), AccountId32([2; 32]).to_string());
+        let device = || device::DeviceV2 {
+            data_type: "cctv-camera".to_string(),
+            location: "40.1949288120072,44.55177253802097".to_string(),
+            location_accuracy: None,
+            price_access: Planck(42),
+            price_pin: Planck(445),
+            additional: None,
+            private: None,
+            attestation: None,
+        };
+        // Heartbeats of unknown devices are not saved.
+        assert!(!database.save_heartbeat(&alive, 1, now, hour).await.unwrap());
+        database.save(&alive, Device::V2(device())).await.unwrap();
+        database.save(&dead, Device::V2(device())).await.unwrap();
+        // Alive device sent heartbeats for the last 10 hours, but missed one.
+        for i in (0..10).filter(|i| *i != 4) {
+            assert!(database.save_heartbeat(&alive, 100 - i, now - i * hour, hour).await.unwrap());
+        }
+        // Dead device sent heartbeats for 2 hours 10 days ago, they are out of uptime window.
+        for i in 0..3 {
+            database
+                .save_heartbeat(&dead, 10 - i, now - 240 * hour - i * hour, hour)
+                .await
+                .unwrap();
+        }
+        // Dead device sent one more heartbeat 4 hours ago and died, it claimed to send the next
+        // one in a year, but interval is capped to one hour.
+        database.save_heartbeat(&dead, 50, now - 4 * hour, 365 * 24 * hour).await.unwrap();
+        // Replayed older heartbeat doesn't move last seen back.
+        database.save_heartbeat(&dead, 9, now - 241 * hour, hour).await.unwrap();
+        let heartbeats: i64 =
+            sqlx::query_scalar("select count(*) from heartbeats where device = ?1")
+                .bind(&dead)
+                .fetch_one(&mut database.conn)
+                .await
+                .unwrap();
+        assert_eq!(heartbeats, 1);
+
+        let devices = database.query(GetDevicesParams::default()).await.unwrap();
+        let alive_device = devices.iter().find(|d| d.address == alive).unwrap();
+        assert_eq!(alive_device.last_seen, Some(now as i64));
+        assert!(alive_device.online);
+        // 9 heartbeats of 1 hour cover 9 of 10 hours since the first one.
+        assert_eq!(alive_device.uptime, Some(90.0));
+        let dead_device = devices.iter().find(|d| d.address == dead).unwrap();
+        assert_eq!(dead_device.last_seen, Some((now - 4 * hour) as i64));
+        assert!(!dead_device.online);
+        assert_eq!(dead_device.uptime, Some(20.0));
+        // Devices from initial migration never sent heartbeats.
+        assert!(devices
+            .iter()
+            .filter(|d| d.address != alive && d.address != dead)
+            .all(|d| { d.last_seen.is_none() && !d.online && d.uptime.is_none() }));
+
+        let query = |field: &str, condition: &str, value: &str| GetDevicesParams {
+            filters: vec![Filter {
+                field: field.to_string(),
+                condition: condition.to_string(),
+                value: value.to_string(),
+            }],
+            ..Default::default()
+        };
+        let devices = database.query(query("online", "=", "true")).await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].address, alive);
+        let devices = database.query(query("uptime", ">", "50")).await.unwrap();
+        assert_eq!(devices.len(), 1);
+        let last_seen = (now - 2 * hour).to_string();
+        let devices = database.query(query("last_seen", "<", &last_seen)).await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].address, dead);
+        assert!(database.query(query("uptime", ">", "high")).await.is_err());
+
+        let sorted = |sort: &str, order: &str| GetDevicesParams {
+            sort: Some(sort.to_string()),
+            order: Some(order.to_string()),
+            limit: 100,
+            ..Default::default()
+        };
+        // Devices without heartbeats go last in both orders.
+        let devices = database.query(sorted("uptime", "asc")).await.unwrap();
+        assert_eq!((devices[0].address.as_str(), devices[1].address.as_str()), (&*dead, &*alive));
+        assert!(devices[2].uptime.is_none());
+        let devices = database.query(sorted("last_seen", "desc")).await.unwrap();
+        assert_eq!((devices[0].address.as_str(), devices[1].address.as_str()), (&*alive, &*dead));
+        assert!(database.query(sorted("data", "asc")).await.is_err());
+        assert!(database.query(sorted("uptime", "up")).await.is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crypto_secretbox::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    XSalsa20Poly1305,
+};
+use schnorrkel::SecretKey;
+use scrypt::{scrypt, Params};
+use subxt::{ext::sp_core::sr25519, utils::AccountId32};
+
+use crate::Error;
+
+// Keystore layout is compatible with polkadot-js keyring JSON version 3.
+const VERSION: &str = "3";
+const CONTENT: [&str; 2] = ["pkcs8", "sr25519"];
+const ENCRYPTION: [&str; 2] = ["scrypt", "xsalsa20-poly1305"];
+
+const PKCS8_HEADER: [u8; 16] = [48, 83, 2, 1, 1, 48, 5, 6, 3, 43, 101, 112, 4, 34, 4, 32];
+const PKCS8_DIVIDER: [u8; 5] = [161, 35, 3, 33, 0];
+const SECRET_LENGTH: usize = 64;
+const PUBLIC_LENGTH: usize = 32;
+
+const SALT_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 24;
+// Salt followed by N, p and r scrypt parameters as little endian u32.
+const SCRYPT_LENGTH: usize = SALT_LENGTH + 3 * 4;
+// Same parameters as polkadot-js uses.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+// Limits N * r * p read from untrusted keystore to 8 times of polkadot-js parameters,
+// scrypt memory is 128 * N * r bytes.
+const MAX_SCRYPT_COST: u64 = 8 * (1 << SCRYPT_LOG_N) * SCRYPT_R as u64 * SCRYPT_P as u64;
+
+/// Encrypted sr25519 keypair in polkadot-js JSON keystore format.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct Keystore {
+    pub(crate) encoded: String,
+    pub(crate) encoding: KeystoreEncoding,
+    pub(crate) address: String,
+    #[serde(default)]
+    pub(crate) meta: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct KeystoreEncoding {
+    pub(crate) content: Vec<String>,
+    #[serde(rename = "type")]
+    pub(crate) typ: Vec<String>,
+    pub(crate) version: String,
+}
+
+impl Keystore {
+    pub(crate) fn encrypt(
+        keypair: &sr25519::Pair,
+        passphrase: &str,
+        name: &str,
+    ) -> Result<Self, Error> {
+        Self::encrypt_with(keypair, passphrase, name, SCRYPT_LOG_N)
+    }
+
+    fn encrypt_with(
+        keypair: &sr25519::Pair,
+        passphrase: &str,
+        name: &str,
+        log_n: u8,
+    ) -> Result<Self, Error> {
+        let keypair: &schnorrkel::Keypair = keypair.as_ref();
+        let mut pkcs8 = Vec::with_capacity(
+            PKCS8_HEADER.len() + SECRET_LENGTH + PKCS8_DIVIDER.len() + PUBLIC_LENGTH,
+        );
+        pkcs8.extend_from_slice(&PKCS8_HEADER);
+        pkcs8.extend_from_slice(&keypair.secret.to_ed25519_bytes());
+        pkcs8.extend_from_slice(&PKCS8_DIVIDER);
+        pkcs8.extend_from_slice(&keypair.public.to_bytes());
+
+        let mut salt = [0u8; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt, log_n, SCRYPT_R, SCRYPT_P)?;
+        let nonce = XSalsa20Poly1305::generate_nonce(&mut OsRng);
+        let encrypted = XSalsa20Poly1305::new(&key.into())
+            .encrypt(&nonce, pkcs8.as_slice())
+            .map_err(|_| "failed to encrypt keystore")?;
+
+        let mut encoded = Vec::with_capacity(SCRYPT_LENGTH + NONCE_LENGTH + encrypted.len());
+        encoded.extend_from_slice(&salt);
+        encoded.extend_from_slice(&(1u32 << log_n).to_le_bytes());
+        encoded.extend_from_slice(&SCRYPT_P.to_le_bytes());
+        encoded.extend_from_slice(&SCRYPT_R.to_le_bytes());
+        encoded.extend_from_slice(&nonce);
+        encoded.extend_from_slice(&encrypted);
+
+        let mut meta = serde_json::Map::new();
+        meta.insert("name".to_string(), name.into());
+        let created = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        meta.insert("whenCreated".to_string(), created.into());
+        Ok(Self {
+            encoded: STANDARD.encode(encoded),
+            encoding: KeystoreEncoding {
+                content: CONTENT.iter().map(ToString::to_string).collect(),
+                typ: ENCRYPTION.iter().map(ToString::to_string).collect(),
+                version: VERSION.to_string(),
+            },
+            address: AccountId32(keypair.public.to_bytes()).to_string(),
+            meta,
+        })
+    }
+
+    pub(crate) fn decrypt(&self, passphrase: &str) -> Result<sr25519::Pair, Error> {
+        if self.encoding.version != VERSION
+            || self.encoding.content != CONTENT
+            || self.encoding.typ != ENCRYPTION
+        {
+            return Err(format!(
+                "unsupported keystore encoding: version {}, content {:?}, type {:?}",
+                self.encoding.version, self.encoding.content, self.encoding.typ
+            )
+            .into());
+        }
+        let encoded = STANDARD.decode(&self.encoded)?;
+        if encoded.len() < SCRYPT_LENGTH + NONCE_LENGTH {
+            return Err("keystore encoded data is too short".into());
+        }
+        let (params, encoded) = encoded.split_at(SCRYPT_LENGTH);
+        let (salt, params) = params.split_at(SALT_LENGTH);
+        let param = |i: usize| u32::from_le_bytes(params[i * 4..(i + 1) * 4].try_into().unwrap());
+        let (n, p, r) = (param(0), param(1), param(2));
+        if !n.is_power_of_two() {
+            return Err(format!("invalid keystore scrypt parameter N {n}").into());
+        }
+        if n as u64 * r as u64 * p as u64 > MAX_SCRYPT_COST {
+            return Err(
+                format!("keystore scrypt parameters N {n}, p {p}, r {r} are too large").into()
+            );
+        }
+        let key = derive_key(passphrase, salt, n.trailing_zeros() as u8, r, p)?;
+        let (nonce, encrypted) = encoded.split_at(NONCE_LENGTH);
+        let pkcs8 = XSalsa20Poly1305::new(&key.into())
+            .decrypt(nonce.into(), encrypted)
+            .map_err(|_| "failed to decrypt keystore; check passphrase")?;
+
+        let divider_offset = PKCS8_HEADER.len() + SECRET_LENGTH;
+        let public_offset = divider_offset + PKCS8_DIVIDER.len();
+        if pkcs8.len() != public_offset + PUBLIC_LENGTH
+            || pkcs8[..PKCS8_HEADER.len()] != PKCS8_HEADER
+            || pkcs8[divider_offset..public_offset] != PKCS8_DIVIDER
+        {
+            return Err("invalid keystore pkcs8 data".into());
+        }
+        let secret = SecretKey::from_ed25519_bytes(&pkcs8[PKCS8_HEADER.len()..divider_offset])
+            .map_err(|e| format!("invalid keystore secret key: {e}"))?;
+        let keypair = secret.to_keypair();
+        if keypair.public.to_bytes()[..] != pkcs8[public_offset..] {
+            return Err("keystore public key doesn't match secret key".into());
+        }
+        Ok(keypair.into())
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> Result<[u8; 32], Error> {
+    // polkadot-js derives 64 bytes and uses the first half as encryption key.
+    let mut output = [0u8; 64];
+    let params = Params::new(log_n, r, p, output.len())
+        .map_err(|e| format!("invalid keystore scrypt parameters: {e}"))?;
+    scrypt(passphrase.as_bytes(), salt, &params, &mut output)
+        .map_err(|e| format!("failed to derive keystore key: {e}"))?;
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&output[..32]);
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use subxt::ext::sp_core::Pair;
+
+    use super::*;
+
+    #[test]
+    fn keystore_roundtrip() {
+        let (keypair, _) = sr25519::Pair::generate();
+        let keystore = Keystore::encrypt_with(&keypair, "passphrase", "device", 10).unwrap();
+        assert_eq!(keystore.address, AccountId32(keypair.public().0).to_string());
+
+        let json = serde_json::to_string(&keystore).unwrap();
+        let keystore: Keystore = serde_json::from_str(&json).unwrap();
+        let decrypted = keystore.decrypt("passphrase").unwrap();
+        assert_eq!(decrypted.public(), keypair.public());
+        assert!(keystore.decrypt("wrong").is_err());
+    }
+
+    // Keystore of //Alice with passphrase "alice-passphrase" and polkadot-js scrypt parameters.
+    const ALICE_KEYSTORE: &str = r#"{
+        "encoded": "Y0ea1poJCyWCd+yPum+ZQZov+ySJgVEGV8lEzNEUjpcAgAAAAQAAAAgAAAB4N3tSV1e0lEJ/iQFPl9eZKPOTjRTrUeJe/sqEwPbAF3VPkdEGmaaWchguAddvMhAbj4rx0TohxeTIHQTycnTdfry57hKHFMIqW3QICHyVi/zmyZ5gmAMcr9837mDHzfJCmkkepygUhe+Ex4DelqRSCC0oWrk5jVHKvM3YAs7kXup5HGTzoRWBkCHQ661fApv+3xx53H4zqA58+cPH",
+        "encoding": {"content": ["pkcs8", "sr25519"], "type": ["scrypt", "xsalsa20-poly1305"], "version": "3"},
+        "address": "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY",
+        "meta": {"genesisHash": "", "name": "alice", "whenCreated": 1700000000000}
+    }"#;
+
+    #[test]
+    fn polkadot_js_keystore() {
+        let keystore: Keystore = serde_json::from_str(ALICE_KEYSTORE).unwrap();
+        let decrypted = keystore.decrypt("alice-passphrase").unwrap();
+        let alice = sr25519::Pair::from_string("//Alice", None).unwrap();
+        assert_eq!(decrypted.public(), alice.public());
+        assert_eq!(AccountId32(decrypted.public().0).to_string(), keystore.address);
+    }
+
+    #[test]
+    fn scrypt_limit() {
+        let mut keystore: Keystore = serde_json::from_str(ALICE_KEYSTORE).unwrap();
+        let mut encoded = STANDARD.decode(&keystore.encoded).unwrap();
+        encoded[SALT_LENGTH..SALT_LENGTH + 4].copy_from_slice(&(1u32 << 30).to_le_bytes());
+        keystore.encoded = STANDARD.encode(encoded);
+        let e = keystore.decrypt("alice-passphrase").err().unwrap();
+        assert!(e.to_string().contains("too large"), "{e}");
+    }
+}
@@ -0,0 +1,158 @@
+use subxt::{ext::sp_core::blake2_256, utils::AccountId32};
+
+use crate::{config::LocationPrivacy, device, Error};
+
+/// Meters in one degree of latitude and of longitude on equator.
+pub(crate) const METERS_PER_DEGREE: f64 = 111_320.0;
+pub(crate) const MAX_GEOHASH_LEVEL: u8 = 12;
+// Published degrees have about one meter precision, it is finer than any accuracy.
+const DECIMALS: usize = 5;
+const OFFSET_CONTEXT: &[u8] = b"staex-ioa-location";
+
+/// Returns location which is published instead of exact one and its accuracy in meters,
+/// i.e. radius around published location which exact location is within.
+pub(crate) fn publish(
+    location: &str,
+    privacy: &LocationPrivacy,
+    address: &AccountId32,
+) -> Result<(String, u32), Error> {
+    let (lat, lon) = device::parse_location(location)?;
+    let (lat, lon, accuracy) = match privacy {
+        LocationPrivacy::Geohash { level } => {
+            // Geohash interleaves bits starting with longitude.
+            let bits = 5 * u32::from(*level);
+            let lat_cell = 180.0 / f64::from(1u32 << (bits / 2));
+            let lon_cell = 360.0 / f64::from(1u32 << bits.div_ceil(2));
+            cell_center(lat, lon, lat_cell, lon_cell)
+        }
+        LocationPrivacy::Grid { size } => {
+            let lat_cell = f64::from(*size) / METERS_PER_DEGREE;
+            // Cells are square at their own latitude, so longitude of snapped latitude is used.
+            let lat = snap(lat, -90.0, lat_cell);
+            let lon_cell = (lat_cell / lat.to_radians().cos()).min(360.0);
+            cell_center(lat, lon, lat_cell, lon_cell)
+        }
+        LocationPrivacy::Offset { radius, seed } => {
+            // Offset is stable, otherwise average of published locations reveals exact one.
+            // Seed is secret, otherwise exact location can be found by trying every location.
+            let mut buf = OFFSET_CONTEXT.to_vec();
+            buf.extend(seed.as_bytes());
+            buf.extend(address.0);
+            buf.extend(location.as_bytes());
+            let hash = blake2_256(&buf);
+            let angle = unit(&hash[..8]) * std::f64::consts::TAU;
+            // Square root makes points uniform over the circle area.
+            let distance = f64::from(*radius) * unit(&hash[8..16]).sqrt();
+            let lat = (lat + distance * angle.cos() / METERS_PER_DEGREE).clamp(-90.0, 90.0);
+            let lon = lon + distance * angle.sin() / lon_scale(lat);
+            (lat, wrap_longitude(lon), *radius)
+        }
+    };
+    Ok((format!("{lat:.DECIMALS$},{lon:.DECIMALS$}"), accuracy))
+}
+
+/// Returns meters in one degree of longitude on given latitude.
+pub(crate) fn lon_scale(lat: f64) -> f64 {
+    // Near poles degree of longitude is tiny, it is limited to not divide by zero.
+    (METERS_PER_DEGREE * lat.to_radians().cos()).max(1.0)
+}
+
+// Returns center of cell which location is in and distance from it to the farthest corner.
+fn cell_center(lat: f64, lon: f64, lat_cell: f64, lon_cell: f64) -> (f64, f64, u32) {
+    let lat = snap(lat, -90.0, lat_cell);
+    let lon = snap(lon, -180.0, lon_cell);
+    // Cell is the widest on its edge closest to equator.
+    let edge = (lat.abs() - lat_cell / 2.0).max(0.0);
+    let height = lat_cell * METERS_PER_DEGREE;
+    let width = lon_cell * METERS_PER_DEGREE * edge.to_radians().cos();
+    let accuracy = (height.hypot(width) / 2.0).ceil() as u32;
+    (lat, lon, accuracy)
+}
+
+// Returns center of cell of given size starting at min which value is in.
+fn snap(value: f64, min: f64, cell: f64) -> f64 {
+    let max = -min;
+    // The last cell can be partial, its center is kept in range.
+    let index = ((value - min) / cell).floor().min(((max - min) / cell).ceil() - 1.0);
+    (min + (index + 0.5) * cell).min(max)
+}
+
+// Offset near poles can go around the globe many times.
+fn wrap_longitude(lon: f64) -> f64 {
+    (lon + 180.0).rem_euclid(360.0) - 180.0
+}
+
+// Maps 8 bytes to [0, 1).
+fn unit(bytes: &[u8]) -> f64 {
+    let value = u64::from_le_bytes(bytes.try_into().expect("8 bytes"));
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Equirectangular distance in meters, like in indexer geo search.
+    fn distance(a: &str, b: &str) -> f64 {
+        let (a, b) = (device::parse_location(a).unwrap(), device::parse_location(b).unwrap());
+        let lat = (a.0 - b.0) * METERS_PER_DEGREE;
+        let lon = (a.1 - b.1) * lon_scale(a.0);
+        lat.hypot(lon)
+    }
+
+    #[test]
+    fn publish_location() {
+        let exact = "40.1949288120072,44.55177253802097";
+        let address = AccountId32([1; 32]);
+        let privacies = [
+            LocationPrivacy::Geohash { level: 6 },
+            LocationPrivacy::Geohash { level: 1 },
+            LocationPrivacy::Grid { size: 1000 },
+            LocationPrivacy::Offset {
+                radius: 500,
+                seed: "secret".to_string(),
+            },
+        ];
+        for privacy in &privacies {
+            let (location, accuracy) = publish(exact, privacy, &address).unwrap();
+            assert!(device::parse_location(&location).is_ok(), "{location}");
+            assert!(distance(exact, &location) <= f64::from(accuracy), "{privacy:?}");
+            // Published location is stable, so it is not re-synced and can't be averaged.
+            assert_eq!(publish(exact, privacy, &address).unwrap(), (location, accuracy));
+        }
+
+        // Geohash "szpstv" of level 6 is about 0.9x0.6 km on this latitude.
+        let (location, accuracy) = publish(exact, &privacies[0], &address).unwrap();
+        assert_eq!(location, "40.19623,44.55505");
+        assert_eq!(accuracy, 559);
+        // Nearby location is in the same cell.
+        let nearby = publish("40.1955,44.554", &privacies[0], &address).unwrap();
+        assert_eq!(nearby.0, location);
+
+        let (location, _) = publish(exact, &privacies[3], &address).unwrap();
+        let other = LocationPrivacy::Offset {
+            radius: 500,
+            seed: "other".to_string(),
+        };
+        assert_ne!(publish(exact, &other, &address).unwrap().0, location);
+        assert_ne!(publish(exact, &privacies[3], &AccountId32([2; 32])).unwrap().0, location);
+    }
+
+    #[test]
+    fn publish_edges() {
+        let address = AccountId32([1; 32]);
+        for exact in ["90,180", "-90,-180", "89.99999,179.99999", "0,0"] {
+            for privacy in [
+                LocationPrivacy::Geohash { level: 3 },
+                LocationPrivacy::Grid { size: 100_000 },
+                LocationPrivacy::Offset {
+                    radius: 10_000,
+                    seed: "secret".to_string(),
+                },
+            ] {
+                let (location, _) = publish(exact, &privacy, &address).unwrap();
+                assert!(device::parse_location(&location).is_ok(), "{exact} {privacy:?}");
+            }
+        }
+    }
+}
@@ -1,29 +1,61 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use clap::{Parser, Subcommand};
 use config::Faucet;
+use futures::future::join_all;
 use log::{error, info, warn, Level, LevelFilter};
-use peaq_client::{generate_account, peaq_gen::api::peaq_did::events::AttributeRead};
-use serde::{Deserialize, Serialize};
+use peaq_client::{
+    generate_account,
+    peaq_gen::{self, api::peaq_did::events::AttributeRead},
+    OwnedAssignments, SignerClient, TrySigner, ENTITY_ID_LENGTH,
+};
 use subxt::{
     config::Header,
     events::{EventDetails, StaticEvent},
-    tx::Signer,
+    ext::sp_core::{sr25519, H256},
     utils::AccountId32,
     PolkadotConfig,
 };
-use subxt_signer::{
-    bip39::{self},
-    sr25519::Keypair,
-    SecretUri,
-};
+use tokio::sync::{Mutex, OnceCell};
 
-use crate::config::Config;
+use crate::{
+    amount::{Planck, Token},
+    anchor::Anchors,
+    config::{Config, TopUpSource},
+    device::{Attestation, Device, DeviceV2, Encoding},
+    keystore::Keystore,
+    signer::AnySigner,
+};
 
+mod account;
+mod amount;
+mod anchor;
+mod buy;
 mod config;
+mod decommission;
+mod device;
+mod faucet;
+mod gateway;
+mod heartbeat;
 mod indexer;
+mod keystore;
+mod location;
+mod onboard;
+mod payments;
+mod rbac;
+mod rotate;
+mod seal;
+mod signer;
+#[cfg(test)]
+mod testing;
 
 pub(crate) const DEVICE_ATTRIBUTE_NAME: &str = "staex-ioa-device";
+/// Attribute of rotated device account with address of its new account.
+pub(crate) const SUPERSEDED_BY_ATTRIBUTE_NAME: &str = "staex-ioa-superseded-by";
 
 pub(crate) type Error = Box<dyn std::error::Error>;
 
@@ -33,34 +65,36 @@ enum SyncState {
     NotCreated,
 }
 
-pub(crate) const V1: &str = "v1";
-
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub(crate) enum Device {
-    V1(DeviceV1),
+enum SyncResult {
+    Disabled,
+    UpToDate,
+    Created(H256),
+    Updated(H256),
 }
 
-impl Device {
-    pub(crate) fn version(&self) -> &str {
+impl SyncResult {
+    fn tx(&self) -> Option<H256> {
         match self {
-            Device::V1(_) => V1,
+            Self::Created(tx) | Self::Updated(tx) => Some(*tx),
+            Self::Disabled | Self::UpToDate => None,
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
-pub(crate) struct DeviceV1 {
-    data_type: String,
-    location: String,
-    price_access: f64,
-    price_pin: f64,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    additional: Option<HashMap<String, toml::Value>>,
+impl std::fmt::Display for SyncResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Disabled => write!(f, "sync is disabled"),
+            Self::UpToDate => write!(f, "up to date"),
+            Self::Created(tx) => write!(f, "created in {tx:?} transaction"),
+            Self::Updated(tx) => write!(f, "updated in {tx:?} transaction"),
+        }
+    }
 }
 
 enum ReadResult {
-    Ok(Device),
+    // Device with private fields is large.
+    Ok(Box<Device>, Encoding),
     DecodeError,
 }
 
@@ -81,184 +115,833 @@ struct Cli {
 enum Commands {
     /// Show default config for provisioner.
     Config {},
-    /// Run provisioner.
+    /// Run provisioner, it exits after sync unless heartbeats are enabled.
     Run {},
+    /// Show on-chain device size and fee for every encoding without submitting it.
+    Plan {},
+    /// Print message which device key signs to attest attributes, it changes with attributes.
+    AttestationMessage {
+        /// Device which attributes are attested, main device by default.
+        #[arg(long, default_value = config::MAIN_DEVICE_NAME)]
+        device: String,
+    },
     /// Run indexer.
     Indexer {},
+    /// Show and manage accounts.
+    Account {
+        /// Print JSON to stdout.
+        #[arg(long, global = true)]
+        json: bool,
+        #[clap(subcommand)]
+        command: account::AccountCommand,
+    },
+    /// Show proofs of readings anchored by gateway.
+    Anchor {
+        #[clap(subcommand)]
+        command: anchor::AnchorCommand,
+    },
+    /// Reconcile on-chain RBAC with policy file.
+    Rbac {
+        #[clap(subcommand)]
+        command: rbac::RbacCommand,
+    },
     /// Create new account.
-    NewAccount {},
+    NewAccount {
+        /// Write account to encrypted keystore file instead of printing its phrase.
+        #[arg(long)]
+        keystore: Option<String>,
+        /// Environment variable with keystore passphrase.
+        #[arg(long, requires = "keystore", conflicts_with = "password_file")]
+        password_env: Option<String>,
+        /// File with keystore passphrase.
+        #[arg(long, requires = "keystore")]
+        password_file: Option<String>,
+    },
+    /// Create, fund and sync devices from CSV file.
+    Onboard {
+        /// CSV file with name, data_type, location, price_access, price_pin and optional
+        /// additional (JSON object) columns.
+        devices: PathBuf,
+        /// Results CSV file, onboarding is resumed from it [default: <DEVICES>.results.csv].
+        #[arg(long)]
+        results: Option<PathBuf>,
+        /// Directory for device keystores.
+        #[arg(long, default_value = "keystores")]
+        keystore_dir: PathBuf,
+        /// Environment variable with keystores passphrase.
+        #[arg(long, conflicts_with = "password_file")]
+        password_env: Option<String>,
+        /// File with keystores passphrase.
+        #[arg(long)]
+        password_file: Option<String>,
+        /// RBAC group id in hex to assign devices to; group must be owned by main signer.
+        #[arg(long)]
+        group: Option<String>,
+    },
     /// Remove on-chain device.
     SelfRemove {},
+    /// Move device to new account and link old account to it.
+    RotateKey {
+        /// Device name, main device by default.
+        #[arg(long, default_value = config::MAIN_DEVICE_NAME)]
+        device: String,
+        /// Keystore file for new account, it is created if it doesn't exist.
+        #[arg(long)]
+        keystore: PathBuf,
+        /// Environment variable with keystore passphrase.
+        #[arg(long, conflicts_with = "password_file")]
+        password_env: Option<String>,
+        /// File with keystore passphrase.
+        #[arg(long)]
+        password_file: Option<String>,
+    },
+    /// Remove all DID attributes, disable RBAC entities and sweep balance of device to its owner.
+    Decommission {
+        /// Device name, main device by default.
+        #[arg(long, default_value = config::MAIN_DEVICE_NAME)]
+        device: String,
+        /// Don't ask for confirmation.
+        #[arg(long)]
+        yes: bool,
+    },
     /// Faucet account.
     Faucet {
         /// Address to send tokens to.
         address: AccountId32,
     },
+    /// Run faucet HTTP server with cooldowns and daily budget.
+    FaucetServer {},
+    /// Watch payments to devices and grant access to payers.
+    Payments {},
+    /// Run gateway which streams device readings to buyers with RBAC permission.
+    Gateway {},
+    /// Pay for device access or data pin and wait until it is granted.
+    #[command(group(clap::ArgGroup::new("kind").required(true).args(["access", "pin"])))]
+    Buy {
+        /// Address of device to buy from.
+        device_address: AccountId32,
+        /// Buy access to device data.
+        #[arg(long)]
+        access: bool,
+        /// Buy pinning of device data in time range of unix seconds, e.g. 1712000000-1712086400.
+        #[arg(long, value_name = "FROM-TO")]
+        pin: Option<String>,
+        /// Indexer URL to look up device, on-chain attribute is used by default.
+        #[arg(long)]
+        indexer: Option<String>,
+        /// Payments API URL of seller to learn grant expiry and wait for renewal, e.g. http://127.0.0.1:4697.
+        #[arg(long)]
+        payments: Option<String>,
+        /// Purchase reference [default: random].
+        #[arg(long)]
+        reference: Option<String>,
+        /// Device which account pays, main device by default.
+        #[arg(long, default_value = config::MAIN_DEVICE_NAME)]
+        device: String,
+        /// Directory for purchase receipts.
+        #[arg(long, default_value = "receipts")]
+        receipts_dir: PathBuf,
+        /// Seconds to wait for access to be granted.
+        #[arg(long, default_value_t = 300)]
+        timeout: u64,
+        /// Don't ask for confirmation.
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Decrypt private fields of device which are sealed to account of local device.
+    Reveal {
+        /// Address of device with private fields.
+        device_address: AccountId32,
+        /// Indexer URL to look up device, on-chain attribute is used by default.
+        #[arg(long)]
+        indexer: Option<String>,
+        /// Device which account fields are sealed to, main device by default.
+        #[arg(long, default_value = config::MAIN_DEVICE_NAME)]
+        device: String,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let cli = Cli::parse();
-    let cfg: config::Config = || -> Result<config::Config, Error> {
-        let buf = match std::fs::read_to_string(cli.config) {
-            Ok(buf) => buf,
-            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
-                return Ok(config::Config::default())
-            }
-            Err(e) => return Err(e.into()),
-        };
-        let cfg: config::Config = toml::from_str(&buf)?;
-        Ok(cfg)
-    }()?;
+    // Default config is shown without config file, as it may not exist yet.
+    let cfg = match cli.command {
+        Commands::Config {} => Config::default(),
+        _ => Config::load(&cli.config)?,
+    };
     env_logger::builder()
         .filter(None, LevelFilter::Off)
         .filter_module("provisioner", cfg.log_level.parse::<Level>()?.to_level_filter())
         .init();
     match cli.command {
         Commands::Config {} => {
-            eprint!("{}", toml::to_string_pretty(&config::Config::default())?);
+            eprint!("{}", toml::to_string_pretty(&cfg)?);
         }
         Commands::Run {} => {
             let app: App = App::new(cfg).await?;
             tokio::spawn(async move {
-                if let Err(e) = app.run().await {
-                    error!("failed to run application: {e}")
+                // Error is not Send, so it is dropped before heartbeats.
+                let synced = match app.run().await {
+                    (synced, Ok(())) => synced,
+                    (synced, Err(e)) => {
+                        error!("failed to run application: {e}");
+                        synced
+                    }
+                };
+                // Device which failed to sync may be not created yet or have outdated attributes,
+                // so only synced devices are shown alive.
+                if app.heartbeat.enabled {
+                    app.send_heartbeats(&synced).await;
                 }
             })
             .await?;
         }
+        Commands::Plan {} => {
+            let app: App = App::new(cfg).await?;
+            app.plan().await?;
+        }
+        Commands::AttestationMessage { device } => {
+            let app: App = App::new(cfg).await?;
+            app.attestation_message(&device).await?;
+        }
         Commands::Indexer {} => {
             indexer::run(cfg).await?;
             tokio::signal::ctrl_c().await?;
         }
+        Commands::Onboard {
+            devices,
+            results,
+            keystore_dir,
+            password_env,
+            password_file,
+            group,
+        } => {
+            let password = config::Signer {
+                password_env,
+                password_file,
+                ..Default::default()
+            }
+            .password()?
+            .ok_or("keystores require --password-env or --password-file")?;
+            let opts = onboard::Onboard {
+                results: results.unwrap_or_else(|| devices.with_extension("results.csv")),
+                devices,
+                keystore_dir,
+                password,
+                group: group.as_deref().map(onboard::parse_group).transpose()?,
+            };
+            let app: App = App::new(cfg).await?;
+            app.onboard(&opts).await?;
+        }
         Commands::SelfRemove {} => {
             let app: App = App::new(cfg).await?;
             app.self_remove().await?;
         }
-        Commands::NewAccount {} => {
-            let (phrase, _, account_id) = generate_account()?;
-            eprintln!("Phrase: {}", phrase);
+        Commands::RotateKey {
+            device,
+            keystore,
+            password_env,
+            password_file,
+        } => {
+            let password = config::Signer {
+                password_env,
+                password_file,
+                ..Default::default()
+            }
+            .password()?
+            .ok_or("keystore requires --password-env or --password-file")?;
+            let opts = rotate::RotateKey {
+                device,
+                keystore,
+                password,
+            };
+            let app: App = App::new(cfg).await?;
+            app.rotate_key(&opts).await?;
+        }
+        Commands::Decommission { device, yes } => {
+            let app: App = App::new(cfg).await?;
+            app.decommission(&device, yes).await?;
+        }
+        Commands::Account { json, command } => {
+            account::run(cfg, command, json).await?;
+        }
+        Commands::Rbac { command } => {
+            rbac::run(cfg, command).await?;
+        }
+        Commands::NewAccount {
+            keystore,
+            password_env,
+            password_file,
+        } => {
+            let (phrase, keypair, account_id) = generate_account()?;
+            match keystore {
+                Some(path) => {
+                    let password = config::Signer {
+                        password_env,
+                        password_file,
+                        ..Default::default()
+                    }
+                    .password()?
+                    .ok_or("keystore requires --password-env or --password-file")?;
+                    // Name is shown by wallets which import keystore.
+                    let name = std::path::Path::new(&path)
+                        .file_stem()
+                        .map(|v| v.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    write_keystore(&path, &Keystore::encrypt(&keypair, &password, &name)?)?;
+                    eprintln!("Keystore: {}", path);
+                }
+                None => eprintln!("Phrase: {}", phrase),
+            }
             eprintln!("Address: {}", account_id);
         }
         Commands::Faucet { address } => {
             let app: App = App::new(cfg).await?;
             app.faucet(address).await?;
         }
+        Commands::FaucetServer {} => {
+            faucet::run(cfg).await?;
+            tokio::signal::ctrl_c().await?;
+        }
+        Commands::Payments {} => {
+            let app: App = App::new(cfg).await?;
+            tokio::select! {
+                result = app.watch_payments() => result?,
+                result = tokio::signal::ctrl_c() => result?,
+            }
+        }
+        Commands::Gateway {} => {
+            let gateway_cfg = cfg.gateway.clone();
+            let app: App = App::new(cfg).await?;
+            if !app.anchor.enabled {
+                gateway::run(&app, gateway_cfg, None).await?;
+                tokio::signal::ctrl_c().await?;
+                return Ok(());
+            }
+            let anchors = Arc::new(Mutex::new(Anchors::new(&app.anchor.dsn).await?));
+            gateway::run(&app, gateway_cfg, Some(anchors.clone())).await?;
+            tokio::select! {
+                result = app.anchor_readings(anchors) => result?,
+                result = tokio::signal::ctrl_c() => result?,
+            }
+        }
+        Commands::Anchor { command } => {
+            let app: App = App::new(cfg).await?;
+            app.anchor(command).await?;
+        }
+        Commands::Buy {
+            device_address,
+            access: _,
+            pin,
+            indexer,
+            payments,
+            reference,
+            device,
+            receipts_dir,
+            timeout,
+            yes,
+        } => {
+            let range = pin.as_deref().map(str::parse::<payments::PinRange>).transpose()?;
+            let opts = buy::Buy {
+                device_address,
+                kind: match range {
+                    Some(_) => payments::PurchaseKind::Pin,
+                    None => payments::PurchaseKind::Access,
+                },
+                range,
+                indexer,
+                payments,
+                reference,
+                device,
+                receipts_dir,
+                timeout: Duration::from_secs(timeout),
+                yes,
+            };
+            let app: App = App::new(cfg).await?;
+            app.buy(&opts).await?;
+        }
+        Commands::Reveal {
+            device_address,
+            indexer,
+            device,
+        } => {
+            let app: App = App::new(cfg).await?;
+            app.reveal(&device_address, indexer.as_deref(), &device).await?;
+        }
     };
     Ok(())
 }
 
 struct App {
-    peaq_client: peaq_client::SignerClient,
+    client: Arc<peaq_client::Client>,
+    // Main device goes first, fleet devices follow it.
+    devices: Vec<DeviceClient>,
+    /// Names of fleet devices which signers failed to be created, with errors.
+    failed_devices: Vec<(String, String)>,
     faucet: Faucet,
+    top_up: config::TopUp,
+    payments: config::Payments,
+    anchor: config::Anchor,
+    heartbeat: config::Heartbeat,
+    token: Token,
+}
+
+/// Device with client which signs transactions from device account.
+struct DeviceClient {
+    name: String,
+    peaq_client: peaq_client::SignerClient<AnySigner>,
     device: config::Device,
 }
 
 impl App {
     async fn new(cfg: Config) -> Result<Self, Error> {
-        let keypair = get_keypair(&cfg.signer)?;
-        let peaq_client = peaq_client::SignerClient::new(&cfg.rpc_url, keypair).await?;
+        let client = Arc::new(peaq_client::Client::new(&cfg.rpc_url).await?);
+        let token = Token::fetch(&client).await?;
+        cfg.validate_amounts(&token)?;
+        let mut devices = vec![DeviceClient {
+            name: config::MAIN_DEVICE_NAME.to_string(),
+            peaq_client: SignerClient::with_client(
+                client.clone(),
+                AnySigner::from_config(&cfg.signer)?,
+            ),
+            device: cfg.device,
+        }];
+        // Main device is required by every command, but fleet device with broken signer,
+        // e.g. unreachable remote signer, doesn't stop others.
+        let mut failed_devices = Vec::new();
+        for fleet_device in cfg.devices {
+            let signer = cfg
+                .signers
+                .get(&fleet_device.signer)
+                .ok_or_else(|| format!("signer {} is not found", fleet_device.signer).into())
+                .and_then(AnySigner::from_config);
+            match signer {
+                Ok(signer) => devices.push(DeviceClient {
+                    name: fleet_device.name,
+                    peaq_client: SignerClient::with_client(client.clone(), signer),
+                    device: fleet_device.device,
+                }),
+                Err(e) => {
+                    warn!("device {}: failed to create signer: {}", fleet_device.name, e);
+                    failed_devices.push((fleet_device.name, e.to_string()));
+                }
+            }
+        }
         Ok(Self {
-            peaq_client,
+            client,
+            devices,
+            failed_devices,
             faucet: cfg.faucet,
-            device: cfg.device,
+            top_up: cfg.top_up,
+            payments: cfg.payments,
+            anchor: cfg.anchor,
+            heartbeat: cfg.heartbeat,
+            token,
         })
     }
 
-    async fn run(&self) -> Result<(), Error> {
-        self.sync().await
+    fn main_device(&self) -> &DeviceClient {
+        &self.devices[0]
     }
 
-    async fn sync(&self) -> Result<(), Error> {
-        if !self.device.sync {
-            return Ok(());
+    fn device(&self, name: &str) -> Result<&DeviceClient, Error> {
+        if let Some((_, e)) = self.failed_devices.iter().find(|(failed, _)| failed == name) {
+            return Err(format!("device {name}: failed to create signer: {e}").into());
         }
-        let last_block = self.peaq_client.get_last_block().await?;
+        self.devices
+            .iter()
+            .find(|device| device.name == name)
+            .ok_or_else(|| format!("device {name} is not found").into())
+    }
+
+    /// Syncs all devices and returns ones which are synced, error tells how many failed.
+    async fn run(&self) -> (Vec<&DeviceClient>, Result<(), Error>) {
+        // Devices are synced concurrently and failed device doesn't stop others.
+        // They share assignments of access groups, so RBAC storage is scanned once per run.
+        let groups = AccessGroups::default();
+        let results = join_all(self.devices.iter().map(|device| {
+            let groups = &groups;
+            async move { self.sync(device, groups).await.map_err(|e| e.to_string()) }
+        }))
+        .await;
+        let mut failed = self.failed_devices.len();
+        for (name, e) in &self.failed_devices {
+            error!("device {}: failed to create signer: {}", name, e);
+        }
+        let mut synced = Vec::new();
+        for (device, result) in self.devices.iter().zip(results) {
+            let address = device.peaq_client.address();
+            match result {
+                Ok(status) => {
+                    info!("device {} ({}): {}", device.name, address, status);
+                    if !matches!(status, SyncResult::Disabled) {
+                        synced.push(device);
+                    }
+                }
+                Err(e) => {
+                    failed += 1;
+                    error!("device {} ({}): failed to sync: {}", device.name, address, e);
+                }
+            }
+        }
+        let total = self.devices.len() + self.failed_devices.len();
+        if failed > 0 {
+            let e = format!("{} of {} devices failed to sync", failed, total);
+            return (synced, Err(e.into()));
+        }
+        (synced, Ok(()))
+    }
+
+    async fn sync(
+        &self,
+        device: &DeviceClient,
+        groups: &AccessGroups,
+    ) -> Result<SyncResult, Error> {
+        if !device.device.sync {
+            return Ok(SyncResult::Disabled);
+        }
+        let name = &device.name;
+        let peaq_client = &device.peaq_client;
+        // Sync is still tried, transaction error explains the problem better.
+        if let Err(e) = self.check_balance(device, groups).await {
+            warn!("{}: failed to check balance: {}", name, e);
+        }
+        let last_block = peaq_client.get_last_block().await?;
         info!(
-            "starting to get on-chain device information starting from {} block",
+            "{}: starting to get on-chain device information starting from {} block",
+            name,
             last_block.block.header.number()
         );
-        let read_result: Option<ReadResult> = self
-            .peaq_client
+        let read_result: Option<ReadResult> = peaq_client
             .did()
             .read_attribute::<ReadResult, _>(DEVICE_ATTRIBUTE_NAME, Some(filter))
             .await?;
-        let sync_state = get_sync_state(read_result, &self.device);
+        let expected = self.expected_device(device, groups).await?;
+        let encoding = device.device.encoding;
+        let sync_state = get_sync_state(read_result, &expected, encoding);
         match sync_state {
             SyncState::Ok => {
-                info!("on-chain device is up to date");
-                if self.device.force {
-                    warn!("force sync is enabled; starting to sync it");
-                    let value = self.prepare_device()?;
-                    self.peaq_client.did().update_attribute(DEVICE_ATTRIBUTE_NAME, value).await?;
-                    info!("successfully updated on-chain device");
+                info!("{}: on-chain device is up to date", name);
+                if device.device.force {
+                    warn!("{}: force sync is enabled; starting to sync it", name);
+                    let value = expected.encode(encoding)?;
+                    let tx =
+                        peaq_client.did().update_attribute(DEVICE_ATTRIBUTE_NAME, value).await?;
+                    info!("{}: successfully updated on-chain device", name);
+                    return Ok(SyncResult::Updated(tx));
                 }
+                Ok(SyncResult::UpToDate)
             }
             SyncState::Outdated => {
-                info!("on-chain device is outdated; starting to sync it");
-                let value = self.prepare_device()?;
-                self.peaq_client.did().update_attribute(DEVICE_ATTRIBUTE_NAME, value).await?;
-                info!("successfully updated on-chain device");
+                info!("{}: on-chain device is outdated; starting to sync it", name);
+                let value = expected.encode(encoding)?;
+                let tx = peaq_client.did().update_attribute(DEVICE_ATTRIBUTE_NAME, value).await?;
+                info!("{}: successfully updated on-chain device", name);
+                Ok(SyncResult::Updated(tx))
             }
             SyncState::NotCreated => {
-                info!("on-chain device is not created");
-                let value = self.prepare_device()?;
-                self.peaq_client.did().add_attribute(DEVICE_ATTRIBUTE_NAME, value).await?;
-                info!("successfully created on-chain device");
+                info!("{}: on-chain device is not created", name);
+                let value = expected.encode(encoding)?;
+                let tx = peaq_client.did().add_attribute(DEVICE_ATTRIBUTE_NAME, value).await?;
+                info!("{}: successfully created on-chain device", name);
+                Ok(SyncResult::Created(tx))
             }
         }
+    }
+
+    /// Warns if device can't pay for the next sync and requests top-up if it is configured.
+    async fn check_balance(
+        &self,
+        device: &DeviceClient,
+        groups: &AccessGroups,
+    ) -> Result<(), Error> {
+        let peaq_client = &device.peaq_client;
+        let address = peaq_client.address();
+        let did = peaq_gen::api::tx().peaq_did();
+        let name = DEVICE_ATTRIBUTE_NAME.as_bytes().to_vec();
+        // Sync always reads attribute and writes it in the worst case.
+        let read = did.read_attribute(address.clone(), name.clone());
+        let value = self.expected_device(device, groups).await?.encode(device.device.encoding)?;
+        let write = did.update_attribute(address.clone(), name, value, None);
+        let read_fee = peaq_client.estimate_fee(&read).await?;
+        let write_fee = peaq_client.estimate_fee(&write).await?;
+        let cost = read_fee + write_fee;
+        let balance = peaq_client.get_balance(&address).await?;
+        if balance >= cost {
+            return Ok(());
+        }
+        warn!(
+            "{}: balance {} of {} is below estimated cost {} of the next sync",
+            device.name,
+            self.token.format(Planck(balance)),
+            address,
+            self.token.format(Planck(cost))
+        );
+        let tx = match self.top_up.source {
+            TopUpSource::Disabled => return Ok(()),
+            TopUpSource::Faucet => {
+                let faucet_client = SignerClient::with_client(
+                    self.client.clone(),
+                    AnySigner::from_config(&self.faucet.signer)?,
+                );
+                let amount = self.faucet.amount.to_planck(&self.token)?;
+                format!("{:?}", faucet_client.transfer(amount.0, address).await?)
+            }
+            TopUpSource::Server => faucet::request_funds(&self.top_up.url, &address).await?,
+        };
+        info!("{}: requested top-up in {} transaction", device.name, tx);
         Ok(())
     }
 
     async fn faucet(&self, account_id: AccountId32) -> Result<(), Error> {
-        let signer = get_keypair(&self.faucet.signer)?;
-        let faucet_account_id: AccountId32 =
-            <subxt_signer::sr25519::Keypair as Signer<PolkadotConfig>>::account_id(&signer);
-        let balance = self.peaq_client.get_balance(&faucet_account_id).await?;
-        info!("faucet balance: {}: {}", faucet_account_id, balance,);
+        let peaq_client = &self.main_device().peaq_client;
+        let signer = AnySigner::from_config(&self.faucet.signer)?;
+        let faucet_account_id = signer.account_id();
+        let amount = self.faucet.amount.to_planck(&self.token)?;
+        let balance = peaq_client.get_balance(&faucet_account_id).await?;
+        info!("faucet balance: {}: {}", faucet_account_id, self.token.format(Planck(balance)));
 
-        let balance = self.peaq_client.get_balance(&account_id).await?;
-        info!("address balance before: {}", balance);
+        let balance = peaq_client.get_balance(&account_id).await?;
+        info!("address balance before: {}", self.token.format(Planck(balance)));
 
-        self.peaq_client.transfer(self.faucet.amount as u128, account_id.clone()).await?;
+        info!("sending {} to {}", self.token.format(amount), account_id);
+        peaq_client.transfer(amount.0, account_id.clone()).await?;
 
-        let balance = self.peaq_client.get_balance(&account_id).await?;
-        info!("address balance after: {}", balance);
+        let balance = peaq_client.get_balance(&account_id).await?;
+        info!("address balance after: {}", self.token.format(Planck(balance)));
         Ok(())
     }
 
     async fn self_remove(&self) -> Result<(), Error> {
         info!("starting to do self-remove");
-        self.peaq_client.did().remove_attribute(DEVICE_ATTRIBUTE_NAME).await
+        let tx =
+            self.main_device().peaq_client.did().remove_attribute(DEVICE_ATTRIBUTE_NAME).await?;
+        info!("successfully removed on-chain device in {:?} transaction", tx);
+        Ok(())
+    }
+
+    async fn plan(&self) -> Result<(), Error> {
+        let main_device = self.main_device();
+        let expected = self.expected_device(main_device, &AccessGroups::default()).await?;
+        for encoding in Encoding::ALL {
+            let value = expected.encode(encoding)?;
+            let size = value.len();
+            let call = peaq_gen::api::tx().peaq_did().add_attribute(
+                main_device.peaq_client.address(),
+                DEVICE_ATTRIBUTE_NAME.as_bytes().to_vec(),
+                value,
+                None,
+            );
+            let fee = main_device.peaq_client.estimate_fee(&call).await?;
+            let configured = if encoding == main_device.device.encoding {
+                " (configured)"
+            } else {
+                ""
+            };
+            eprintln!("Encoding: {}{}", encoding, configured);
+            eprintln!("Size: {} bytes", size);
+            eprintln!("Fee: {}", self.token.format(Planck(fee)));
+        }
+        Ok(())
     }
 
-    fn prepare_device(&self) -> Result<Vec<u8>, Error> {
-        let device = Device::V1(DeviceV1 {
-            data_type: self.device.attributes.data_type.clone(),
-            location: self.device.attributes.location.clone(),
-            price_pin: self.device.attributes.price_pin,
-            price_access: self.device.attributes.price_access,
-            additional: self.device.attributes.additional.clone(),
+    async fn attestation_message(&self, name: &str) -> Result<(), Error> {
+        let expected = self.expected_device(self.device(name)?, &AccessGroups::default()).await?;
+        let message = expected.device.attestation_message(&expected.address)?;
+        println!("{}", String::from_utf8(message)?);
+        Ok(())
+    }
+
+    async fn expected_device(
+        &self,
+        device: &DeviceClient,
+        groups: &AccessGroups,
+    ) -> Result<ExpectedDevice, Error> {
+        let attributes = &device.device.attributes;
+        let private = match &attributes.private {
+            Some(fields) => Some(ExpectedPrivate {
+                fields: fields.clone(),
+                recipients: self.private_recipients(device, groups).await?,
+                key: device.peaq_client.signer().sr25519().cloned(),
+            }),
+            None => None,
+        };
+        let address = device.peaq_client.address();
+        let (location, location_accuracy) = match &device.device.location_privacy {
+            Some(privacy) => {
+                let (location, accuracy) =
+                    location::publish(&attributes.location, privacy, &address)?;
+                (location, Some(accuracy))
+            }
+            None => (attributes.location.clone(), None),
+        };
+        Ok(ExpectedDevice {
+            device: DeviceV2 {
+                data_type: attributes.data_type.clone(),
+                location,
+                location_accuracy,
+                price_access: attributes.price_access.to_planck(&self.token)?,
+                price_pin: attributes.price_pin.to_planck(&self.token)?,
+                additional: attributes.additional.clone(),
+                private: None,
+                attestation: None,
+            },
+            address,
+            attestation: device.device.attestation.clone(),
+            private,
+        })
+    }
+
+    /// Returns device itself and members of its access group which private fields are sealed to.
+    async fn private_recipients(
+        &self,
+        device: &DeviceClient,
+        groups: &AccessGroups,
+    ) -> Result<Vec<AccountId32>, Error> {
+        let mut recipients = vec![device.peaq_client.address()];
+        let group = device.device.access_group.as_deref().map(onboard::parse_group).transpose()?;
+        if let Some(group) = group {
+            recipients.extend(groups.members(self, &group).await?);
+        }
+        recipients.retain(|account| {
+            let sealable = seal::can_seal_to(account);
+            if !sealable {
+                warn!(
+                    "{}: can't seal private fields to non-sr25519 account {}",
+                    device.name, account
+                );
+            }
+            sealable
         });
-        let value = serde_json::to_vec(&device)?;
-        Ok(value)
+        recipients.sort_by_key(ToString::to_string);
+        recipients.dedup();
+        Ok(recipients)
     }
 }
 
-fn get_keypair(cfg: &config::Signer) -> Result<Keypair, Error> {
-    match cfg.typ {
-        config::SignerType::Phrase => {
-            Ok(Keypair::from_phrase(&bip39::Mnemonic::from_str(&cfg.val)?, None)?)
+/// Assignments of groups owned by main signer, read at most once and shared by devices
+/// synced together, since reading them scans RBAC storage.
+#[derive(Default)]
+struct AccessGroups(OnceCell<OwnedAssignments>);
+
+impl AccessGroups {
+    async fn members(
+        &self,
+        app: &App,
+        group: &[u8; ENTITY_ID_LENGTH],
+    ) -> Result<Vec<AccountId32>, Error> {
+        let assignments = self
+            .0
+            .get_or_try_init(|| async {
+                let owner = app.main_device().peaq_client.address();
+                app.client.get_owned_assignments(&owner).await
+            })
+            .await?;
+        Ok(assignments
+            .user_groups
+            .iter()
+            .filter(|(_, v)| v == group)
+            .map(|(user, _)| AccountId32(*user))
+            .collect())
+    }
+}
+
+/// Device which is expected on chain.
+///
+/// Private fields are sealed with random keys, so they are compared by their meaning
+/// rather than by value.
+struct ExpectedDevice {
+    device: DeviceV2,
+    address: AccountId32,
+    /// Signature made by device key outside of provisioner.
+    attestation: Option<Attestation>,
+    private: Option<ExpectedPrivate>,
+}
+
+struct ExpectedPrivate {
+    fields: seal::Fields,
+    recipients: Vec<AccountId32>,
+    // Only local sr25519 device signer can open on-chain fields to compare them.
+    key: Option<sr25519::Pair>,
+}
+
+impl ExpectedDevice {
+    /// Seals private fields and attests device, value differs on every call.
+    fn encode(&self, encoding: Encoding) -> Result<Vec<u8>, Error> {
+        let mut device = self.device.clone();
+        if let Some(private) = &self.private {
+            device.private = Some(seal::seal(&private.fields, &private.recipients)?);
+        }
+        if let Some(attestation) = &self.attestation {
+            device.attestation = Some(attestation.clone());
+            if !device.is_attested(&self.address) {
+                return Err("attestation signature doesn't match attributes, \
+                     sign output of attestation-message command with device key again"
+                    .into());
+            }
+        }
+        device::encode(Device::V2(device), encoding)
+    }
+
+    fn is_attested(&self, device: &DeviceV2) -> bool {
+        device.attestation == self.attestation
+    }
+
+    fn is_sealed(&self, device: &DeviceV2) -> bool {
+        match (&device.private, &self.private) {
+            (None, None) => true,
+            (Some(sealed), Some(private)) => {
+                let mut recipients = seal::recipients(sealed);
+                recipients.sort();
+                let expected: Vec<String> =
+                    private.recipients.iter().map(ToString::to_string).collect();
+                recipients == expected
+                    && private.key.as_ref().is_none_or(|key| {
+                        seal::open(sealed, key).is_ok_and(|fields| fields == private.fields)
+                    })
+            }
+            _ => false,
         }
-        config::SignerType::SecretUri => Ok(Keypair::from_uri(&SecretUri::from_str(&cfg.val)?)?),
     }
 }
 
+/// Decrypts keystore or creates it with new account if it doesn't exist.
+fn open_or_create_keystore(
+    path: &Path,
+    password: &str,
+    name: &str,
+) -> Result<sr25519::Pair, Error> {
+    if path.exists() {
+        let keystore: Keystore = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        return keystore.decrypt(password);
+    }
+    let (_, keypair, _) = generate_account()?;
+    write_keystore(&path.to_string_lossy(), &Keystore::encrypt(&keypair, password, name)?)?;
+    Ok(keypair)
+}
+
+fn write_keystore(path: &str, keystore: &Keystore) -> Result<(), Error> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    // Keystore is never overwritten and is readable only by owner.
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| format!("failed to create keystore {path}: {e}"))?;
+    file.write_all(serde_json::to_string_pretty(keystore)?.as_bytes())?;
+    Ok(())
+}
+
 fn filter(event: EventDetails<PolkadotConfig>) -> Option<ReadResult> {
     if event.variant_name() == AttributeRead::EVENT {
         if let Ok(Some(evt)) = event.as_event::<AttributeRead>() {
-            match serde_json::from_slice(&evt.0.value) {
-                Ok(device) => return Some(ReadResult::Ok(device)),
+            match device::decode(&evt.0.value) {
+                Ok(device) => {
+                    let encoding = device::encoding(&evt.0.value);
+                    return Some(ReadResult::Ok(Box::new(device), encoding));
+                }
                 Err(e) => {
                     // Looks like we have outdated format.
                     warn!("failed to decode on-chain attribute: {}", e);
@@ -270,19 +953,30 @@ fn filter(event: EventDetails<PolkadotConfig>) -> Option<ReadResult> {
     None
 }
 
-fn get_sync_state(read_result: Option<ReadResult>, expected: &config::Device) -> SyncState {
+fn get_sync_state(
+    read_result: Option<ReadResult>,
+    expected: &ExpectedDevice,
+    encoding: Encoding,
+) -> SyncState {
     if read_result.is_none() {
         return SyncState::NotCreated;
     }
     let read_result = read_result.unwrap();
     match read_result {
         ReadResult::DecodeError => SyncState::Outdated,
-        ReadResult::Ok(device) => match device {
-            Device::V1(device) => {
-                if device.data_type != expected.attributes.data_type
-                    || device.location != expected.attributes.location
-                    || device.price_access != expected.attributes.price_access
-                    || device.price_pin != expected.attributes.price_pin
+        // Device is re-encoded when configured encoding changes.
+        ReadResult::Ok(_, actual) if actual != encoding => SyncState::Outdated,
+        ReadResult::Ok(device, _) => match *device {
+            // Float prices can't be compared, so device must be re-created with planck prices.
+            Device::V1(_) => SyncState::Outdated,
+            Device::V2(device) => {
+                if device.data_type != expected.device.data_type
+                    || device.location != expected.device.location
+                    || device.location_accuracy != expected.device.location_accuracy
+                    || device.price_access != expected.device.price_access
+                    || device.price_pin != expected.device.price_pin
+                    || !expected.is_attested(&device)
+                    || !expected.is_sealed(&device)
                 {
                     SyncState::Outdated
                 } else {
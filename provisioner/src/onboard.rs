@@ -0,0 +1,312 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use log::{error, info};
+use peaq_client::{PairSigner, SignerClient, TrySigner, ENTITY_ID_LENGTH};
+use subxt::ext::sp_core::H256;
+
+use crate::{
+    amount::Planck,
+    config::{self, Attributes},
+    open_or_create_keystore,
+    signer::AnySigner,
+    AccessGroups, App, DeviceClient, Error,
+};
+
+/// Onboarding options from command line.
+pub(crate) struct Onboard {
+    pub(crate) devices: PathBuf,
+    pub(crate) results: PathBuf,
+    pub(crate) keystore_dir: PathBuf,
+    pub(crate) password: String,
+    pub(crate) group: Option<[u8; ENTITY_ID_LENGTH]>,
+}
+
+/// Device row from onboarding CSV.
+#[derive(serde::Deserialize)]
+struct DeviceRow {
+    name: String,
+    data_type: String,
+    location: String,
+    price_access: String,
+    price_pin: String,
+    // JSON object with additional fields.
+    #[serde(default)]
+    additional: Option<String>,
+}
+
+/// Results row, filled step by step so onboarding can be resumed from it.
+#[derive(serde::Serialize, serde::Deserialize, Default, Clone)]
+struct ResultRow {
+    name: String,
+    address: Option<String>,
+    keystore: Option<String>,
+    fund_tx: Option<String>,
+    attribute_tx: Option<String>,
+    group_tx: Option<String>,
+    error: Option<String>,
+}
+
+impl App {
+    /// Creates, funds and syncs every device from CSV file.
+    ///
+    /// Keystores and results of previous run are reused, so only failed steps are repeated.
+    pub(crate) async fn onboard(&self, opts: &Onboard) -> Result<(), Error> {
+        let rows = read_devices(&opts.devices)?;
+        let mut previous: HashMap<String, ResultRow> = if opts.results.exists() {
+            read_csv::<ResultRow>(&opts.results)?
+                .into_iter()
+                .map(|row| (row.name.clone(), row))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        std::fs::create_dir_all(&opts.keystore_dir)?;
+        let faucet_client = SignerClient::with_client(
+            self.client.clone(),
+            AnySigner::from_config(&self.faucet.signer)?,
+        );
+        let mut results = Vec::with_capacity(rows.len());
+        let mut failed = 0;
+        for row in rows {
+            let mut result = previous.remove(&row.name).unwrap_or_else(|| ResultRow {
+                name: row.name.clone(),
+                ..Default::default()
+            });
+            result.error = None;
+            if let Err(e) = self.onboard_device(opts, &faucet_client, &row, &mut result).await {
+                error!("{}: failed to onboard device: {}", row.name, e);
+                result.error = Some(e.to_string());
+                failed += 1;
+            } else {
+                info!("{}: device is onboarded", row.name);
+            }
+            results.push(result);
+            // Results are saved after every device to not lose progress.
+            write_results(&opts.results, &results)?;
+        }
+        if failed > 0 {
+            return Err(format!(
+                "{} of {} devices failed to onboard; see {} and run onboarding again",
+                failed,
+                results.len(),
+                opts.results.display()
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    async fn onboard_device(
+        &self,
+        opts: &Onboard,
+        faucet_client: &SignerClient<AnySigner>,
+        row: &DeviceRow,
+        result: &mut ResultRow,
+    ) -> Result<(), Error> {
+        let keystore_path = opts.keystore_dir.join(format!("{}.json", row.name));
+        let keypair = open_or_create_keystore(&keystore_path, &opts.password, &row.name)?;
+        let signer = AnySigner::Sr25519(Box::new(PairSigner::new(keypair)));
+        let address = signer.account_id();
+        result.address = Some(address.to_string());
+        result.keystore = Some(keystore_path.to_string_lossy().to_string());
+
+        let amount = self.faucet.amount.to_planck(&self.token)?;
+        let balance = self.client.get_balance(&address).await?;
+        if needs_funding(result, balance, amount) {
+            info!("{}: sending {} to {}", row.name, self.token.format(amount), address);
+            let tx = faucet_client.transfer(amount.0, address.clone()).await?;
+            result.fund_tx = Some(format_tx(tx));
+        } else {
+            info!("{}: account has {}", row.name, self.token.format(Planck(balance)));
+        }
+
+        let device = DeviceClient {
+            name: row.name.clone(),
+            peaq_client: SignerClient::with_client(self.client.clone(), signer),
+            device: config::Device {
+                sync: true,
+                force: false,
+                encoding: self.main_device().device.encoding,
+                owner: None,
+                access_group: None,
+                attestation: None,
+                location_privacy: self.main_device().device.location_privacy.clone(),
+                attributes: row.attributes()?,
+            },
+        };
+        // Onboarded device has no private fields, so assignments are never read.
+        let sync_result = self.sync(&device, &AccessGroups::default()).await?;
+        info!("{}: on-chain device is {}", row.name, sync_result);
+        if let Some(tx) = sync_result.tx() {
+            result.attribute_tx = Some(format_tx(tx));
+        }
+
+        if let (Some(group), None) = (opts.group, &result.group_tx) {
+            let tx = self
+                .main_device()
+                .peaq_client
+                .rbac()
+                .assign_user_to_group(address.0, group)
+                .await?;
+            result.group_tx = Some(format_tx(tx));
+        }
+        Ok(())
+    }
+}
+
+impl DeviceRow {
+    fn attributes(&self) -> Result<Attributes, Error> {
+        let additional = match self.additional.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(additional) => {
+                let additional: serde_json::Map<String, serde_json::Value> =
+                    serde_json::from_str(additional)
+                        .map_err(|e| format!("additional must be JSON object: {e}"))?;
+                let additional = additional
+                    .into_iter()
+                    .map(|(k, v)| Ok((k, toml::Value::try_from(v)?)))
+                    .collect::<Result<_, toml::ser::Error>>()?;
+                Some(additional)
+            }
+        };
+        let attributes = Attributes {
+            data_type: self.data_type.clone(),
+            location: self.location.clone(),
+            price_access: self.price_access.parse()?,
+            price_pin: self.price_pin.parse()?,
+            additional,
+            private: None,
+        };
+        attributes.check()?;
+        Ok(attributes)
+    }
+}
+
+/// Returns true if account of device is not funded yet.
+///
+/// Device spends funds on fees, so funded account is recognized by recorded transaction,
+/// while balance is checked only if transaction hash was lost.
+fn needs_funding(result: &ResultRow, balance: u128, amount: Planck) -> bool {
+    result.fund_tx.is_none() && balance < amount.0
+}
+
+/// Parses group id as 32 bytes in hex.
+pub(crate) fn parse_group(value: &str) -> Result<[u8; ENTITY_ID_LENGTH], Error> {
+    let bytes = hex::decode(value.trim_start_matches("0x"))?;
+    bytes.try_into().map_err(|_| format!("group id must be {ENTITY_ID_LENGTH} bytes").into())
+}
+
+fn read_devices(path: &Path) -> Result<Vec<DeviceRow>, Error> {
+    let rows: Vec<DeviceRow> = read_csv(path)?;
+    let mut names = HashSet::new();
+    for (i, row) in rows.iter().enumerate() {
+        if !is_valid_name(&row.name) {
+            return Err(format!("row {}: invalid device name {:?}", i + 1, row.name).into());
+        }
+        if !names.insert(&row.name) {
+            return Err(format!("row {}: duplicate device name {}", i + 1, row.name).into());
+        }
+        row.attributes().map_err(|e| format!("row {} ({}): {e}", i + 1, row.name))?;
+    }
+    Ok(rows)
+}
+
+/// Checks that name can be used as keystore file name.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+fn read_csv<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Vec<T>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    let mut rows = Vec::new();
+    for row in reader.deserialize() {
+        rows.push(row.map_err(|e| format!("failed to read {}: {e}", path.display()))?);
+    }
+    Ok(rows)
+}
+
+fn write_results(path: &Path, results: &[ResultRow]) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for result in results {
+        writer.serialize(result)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn format_tx(tx: H256) -> String {
+    format!("{tx:?}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_rows() {
+        let path = std::env::temp_dir().join(format!("onboard-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "name,data_type,location,price_access,price_pin,additional\n\
+             sensor-1,temperature-sensor,\"40.19,44.55\",1 AGUNG,0.5,\n\
+             sensor-2,cctv-camera,\"40.19,44.55\",1,2,\"{\"\"resolution\"\": 1080}\"\n",
+        )
+        .unwrap();
+        let rows = read_devices(&path).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].attributes().unwrap().additional.is_none());
+        let additional = rows[1].attributes().unwrap().additional.unwrap();
+        assert_eq!(additional["resolution"], toml::Value::Integer(1080));
+
+        std::fs::write(
+            &path,
+            "name,data_type,location,price_access,price_pin\n\
+             sensor-1,temperature-sensor,\"91,44.55\",1,2\n",
+        )
+        .unwrap();
+        assert!(read_devices(&path).is_err());
+
+        std::fs::write(
+            &path,
+            "name,data_type,location,price_access,price_pin\n\
+             ../sensor,temperature-sensor,\"40.19,44.55\",1,2\n",
+        )
+        .unwrap();
+        assert!(read_devices(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn resumed_funding() {
+        let path = std::env::temp_dir().join(format!("onboard-{}.results.csv", std::process::id()));
+        let funded = ResultRow {
+            name: "sensor-1".to_string(),
+            fund_tx: Some(format_tx(H256::repeat_byte(1))),
+            ..Default::default()
+        };
+        let new = ResultRow {
+            name: "sensor-2".to_string(),
+            ..Default::default()
+        };
+        write_results(&path, &[funded, new]).unwrap();
+        let results: Vec<ResultRow> = read_csv(&path).unwrap();
+        let amount = Planck(100);
+        // Funded device spent part of funds on fees, but it is not funded again.
+        assert!(!needs_funding(&results[0], 10, amount));
+        assert!(needs_funding(&results[1], 10, amount));
+        assert!(!needs_funding(&results[1], 100, amount));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn group_id() {
+        assert_eq!(parse_group(&format!("0x{}", "ab".repeat(32))).unwrap(), [0xab; 32]);
+        assert!(parse_group("0xabab").is_err());
+    }
+}
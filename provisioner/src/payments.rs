@@ -0,0 +1,1505 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Json, Router,
+};
+use log::{error, info, trace, warn};
+use peaq_client::{
+    peaq_gen::api::{
+        balances::events::Transfer,
+        system::calls::types::{Remark, RemarkWithEvent},
+    },
+    Client, ENTITY_ID_LENGTH,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::{Connection, SqliteConnection};
+use subxt::{
+    events::Phase,
+    ext::{codec::Decode, sp_core::H256},
+    utils::{AccountId32, MultiAddress},
+};
+use tokio::sync::Mutex;
+
+use crate::{
+    amount::Planck, config, indexer::open_sqlite, onboard::parse_group, AccessGroups, App, Error,
+};
+
+/// Prefix of system remark which buyer sends right before payment to describe purchase.
+pub(crate) const PURCHASE_MEMO_PREFIX: &str = "staex-ioa-purchase";
+// Memo is matched only with payments made at most this many blocks after it.
+const MEMO_MAX_AGE: u64 = 20;
+const MAX_REFERENCE_LENGTH: usize = 64;
+const POLL_INTERVAL: Duration = Duration::from_secs(6);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum PurchaseKind {
+    /// Membership in device access group.
+    Access,
+    /// Retention of device data in time range.
+    Pin,
+}
+
+impl fmt::Display for PurchaseKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Access => write!(f, "access"),
+            Self::Pin => write!(f, "pin"),
+        }
+    }
+}
+
+impl FromStr for PurchaseKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "access" => Ok(Self::Access),
+            "pin" => Ok(Self::Pin),
+            _ => Err(format!("unknown purchase kind {s}").into()),
+        }
+    }
+}
+
+/// Time range of device data in unix seconds, e.g. "1712000000-1712086400".
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct PinRange {
+    pub(crate) from: u64,
+    pub(crate) to: u64,
+}
+
+impl fmt::Display for PinRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.from, self.to)
+    }
+}
+
+impl FromStr for PinRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = s.split_once('-').ok_or_else(|| format!("invalid pin range: {s}"))?;
+        let parse = |v: &str| v.parse::<u64>().map_err(|_| format!("invalid pin range: {s}"));
+        let range = Self {
+            from: parse(from)?,
+            to: parse(to)?,
+        };
+        if range.from >= range.to {
+            return Err(format!("pin range {s} must start before its end").into());
+        }
+        Ok(range)
+    }
+}
+
+/// Purchase description, e.g. "staex-ioa-purchase:access:<device address>:<reference>"
+/// or "staex-ioa-purchase:pin:<device address>:<reference>:<from>-<to>".
+///
+/// Payment to device account doesn't need memo, but payment to owner of several devices
+/// and pin purchase do.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Memo {
+    pub(crate) kind: PurchaseKind,
+    pub(crate) device: AccountId32,
+    /// Buyer's reference to find payment later, can be empty.
+    pub(crate) reference: String,
+    /// Pinned range, it is set only for pins.
+    pub(crate) range: Option<PinRange>,
+}
+
+impl Memo {
+    pub(crate) fn parse(remark: &[u8]) -> Option<Self> {
+        let remark = std::str::from_utf8(remark).ok()?;
+        let mut parts = remark.splitn(5, ':');
+        if parts.next()? != PURCHASE_MEMO_PREFIX {
+            return None;
+        }
+        let kind = parts.next()?.parse().ok()?;
+        let device = parts.next()?.parse().ok()?;
+        let reference = parts.next().unwrap_or_default().to_string();
+        if !is_valid_reference(&reference) {
+            return None;
+        }
+        let range = match (kind, parts.next()) {
+            (PurchaseKind::Access, None) => None,
+            (PurchaseKind::Pin, Some(range)) => Some(range.parse().ok()?),
+            _ => return None,
+        };
+        Some(Self {
+            kind,
+            device,
+            reference,
+            range,
+        })
+    }
+}
+
+impl fmt::Display for Memo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}:{}", PURCHASE_MEMO_PREFIX, self.kind, self.device, self.reference)?;
+        if let Some(range) = &self.range {
+            write!(f, ":{range}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that reference is short and can't be confused with other memo parts.
+pub(crate) fn is_valid_reference(reference: &str) -> bool {
+    reference.len() <= MAX_REFERENCE_LENGTH
+        && reference.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// How long bought access or pin lasts, e.g. "30 days", "12 hours" or "14400 blocks".
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Period {
+    Blocks(u64),
+    Seconds(u64),
+}
+
+impl Period {
+    /// Returns expiry of grant paid at given block and time.
+    ///
+    /// Payment before expiry of current grant extends it, otherwise period starts at payment.
+    fn expiry(self, current: Option<Expiry>, block: u64, time: u64) -> Expiry {
+        match (self, current) {
+            (Self::Blocks(blocks), Some(Expiry::Block(current))) => {
+                Expiry::Block(current.max(block).saturating_add(blocks))
+            }
+            (Self::Blocks(blocks), _) => Expiry::Block(block.saturating_add(blocks)),
+            (Self::Seconds(seconds), Some(Expiry::Time(current))) => {
+                Expiry::Time(current.max(time).saturating_add(seconds))
+            }
+            (Self::Seconds(seconds), _) => Expiry::Time(time.saturating_add(seconds)),
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Blocks(blocks) => write!(f, "{blocks} blocks"),
+            Self::Seconds(seconds) => {
+                for (unit, length) in [("days", 86400), ("hours", 3600), ("minutes", 60)] {
+                    if seconds > 0 && seconds % length == 0 {
+                        return write!(f, "{} {unit}", seconds / length);
+                    }
+                }
+                write!(f, "{seconds} seconds")
+            }
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = s
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("period {s:?} has no unit"))?;
+        let number: u64 = number.parse().map_err(|_| format!("invalid period: {s}"))?;
+        let length = match unit.trim().trim_end_matches('s') {
+            "block" => return Ok(Self::Blocks(number)),
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 60 * 60,
+            "day" => 24 * 60 * 60,
+            _ => return Err(format!("unknown unit of period: {s}").into()),
+        };
+        number
+            .checked_mul(length)
+            .map(Self::Seconds)
+            .ok_or_else(|| format!("period {s} is too long").into())
+    }
+}
+
+impl Serialize for Period {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Period {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Finalized block or unix time when grant is revoked.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Expiry {
+    Block(u64),
+    Time(u64),
+}
+
+impl fmt::Display for Expiry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Block(block) => write!(f, "{block} block"),
+            Self::Time(time) => write!(f, "{time} unix time"),
+        }
+    }
+}
+
+/// Device which sells access to its RBAC group or pins of its data.
+struct SoldDevice {
+    name: String,
+    address: AccountId32,
+    owner: Option<AccountId32>,
+    price_access: Planck,
+    /// Access is sold only by devices with access group.
+    group: Option<[u8; ENTITY_ID_LENGTH]>,
+    /// Pins are sold by every device when pin period is set.
+    price_pin: Option<Planck>,
+}
+
+impl SoldDevice {
+    fn price(&self, kind: PurchaseKind) -> Option<Planck> {
+        match kind {
+            PurchaseKind::Access => self.group.map(|_| self.price_access),
+            PurchaseKind::Pin => self.price_pin,
+        }
+    }
+}
+
+/// Payment which is matched with device and waits until access is granted or data is pinned.
+#[derive(sqlx::FromRow)]
+struct PendingPayment {
+    id: i64,
+    block: i64,
+    payer: String,
+    device: String,
+    kind: String,
+    memo: Option<String>,
+    attempts: i64,
+    created_at: i64,
+}
+
+/// Access group membership of payer.
+#[derive(sqlx::FromRow, Debug)]
+struct Grant {
+    id: i64,
+    payer: String,
+    access_group: String,
+    device: String,
+    expires_block: Option<i64>,
+    expires_at: Option<i64>,
+    status: String,
+    attempts: i64,
+}
+
+impl Grant {
+    fn expiry(&self) -> Option<Expiry> {
+        match (self.expires_block, self.expires_at) {
+            (Some(block), _) => Some(Expiry::Block(block as u64)),
+            (None, Some(time)) => Some(Expiry::Time(time as u64)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Pin which is created together with granted payment.
+struct NewPin {
+    device: String,
+    buyer: String,
+    range: PinRange,
+    reference: Option<String>,
+    expiry: Expiry,
+}
+
+#[derive(sqlx::FromRow)]
+struct PinRow {
+    buyer: String,
+    range_from: i64,
+    range_to: i64,
+    reference: Option<String>,
+    expires_block: Option<i64>,
+    expires_at: Option<i64>,
+}
+
+/// Pinned range of device data which device must keep until expiry.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct PinResponse {
+    pub(crate) buyer: String,
+    pub(crate) from: u64,
+    pub(crate) to: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) reference: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) expires_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) expires_at: Option<u64>,
+}
+
+/// Grant which is created or renewed together with granted payment.
+struct NewGrant {
+    payer: String,
+    access_group: String,
+    device: String,
+    expiry: Option<Expiry>,
+}
+
+/// Payment found in block.
+#[derive(Debug, PartialEq, Clone)]
+struct NewPayment {
+    event_index: u32,
+    payer: AccountId32,
+    recipient: AccountId32,
+    amount: Planck,
+    memo: Option<Memo>,
+    // Matched device and purchase kind, or the reason why payment is rejected.
+    purchase: Result<(AccountId32, PurchaseKind), String>,
+}
+
+impl App {
+    /// Watches transfers to sold devices and to their owners in finalized blocks,
+    /// assigns payers to access group and records pins.
+    ///
+    /// Payments and processed block are saved in one transaction, so every payment is seen once.
+    /// Payments are granted until success and survive restarts. Grants with expiry are revoked
+    /// when it passes, unless payer pays again before that. Private fields of devices are
+    /// resealed when members of their access groups change.
+    pub(crate) async fn watch_payments(&self) -> Result<(), Error> {
+        let devices = self.sold_devices()?;
+        if devices.is_empty() {
+            return Err("no device has access_group and payments.pin_period is not set, \
+                 there is nothing to sell"
+                .into());
+        }
+        let mut database = Database::new(&self.payments.dsn).await?;
+        let owner = self.main_device().peaq_client.address();
+        run_api(&self.payments, self.client.clone(), owner).await?;
+        let mut next_block = match database.cursor().await? {
+            Some(block) => block + 1,
+            None => match self.payments.from_block {
+                Some(block) => block,
+                None => self.client.get_finalized_block_number().await?,
+            },
+        };
+        for device in &devices {
+            let prices: Vec<String> = [PurchaseKind::Access, PurchaseKind::Pin]
+                .into_iter()
+                .filter_map(|kind| {
+                    Some(format!("{kind} at {}", self.token.format(device.price(kind)?)))
+                })
+                .collect();
+            info!(
+                "{}: watching payments to {} for {}",
+                device.name,
+                device.address,
+                prices.join(" and ")
+            );
+        }
+        loop {
+            let finalized = self.client.get_finalized_block_number().await?;
+            while next_block <= finalized {
+                trace!("processing payments in {} block", next_block);
+                self.process_block(&devices, &mut database, next_block).await?;
+                next_block += 1;
+            }
+            // Renewals are granted first, so they are not revoked.
+            let granted = self.grant_pending(&devices, &mut database).await?;
+            let revoked = self.revoke_expired(&mut database, finalized).await?;
+            if granted || revoked {
+                self.reseal().await;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    fn sold_devices(&self) -> Result<Vec<SoldDevice>, Error> {
+        let mut devices = Vec::new();
+        for device in &self.devices {
+            let group = device.device.access_group.as_deref().map(parse_group).transpose()?;
+            let price_pin = match self.payments.pin_period {
+                Some(_) => Some(device.device.attributes.price_pin.to_planck(&self.token)?),
+                None => None,
+            };
+            if group.is_none() && price_pin.is_none() {
+                continue;
+            }
+            devices.push(SoldDevice {
+                name: device.name.clone(),
+                address: device.peaq_client.address(),
+                owner: device.device.owner.clone(),
+                price_access: device.device.attributes.price_access.to_planck(&self.token)?,
+                group,
+                price_pin,
+            });
+        }
+        Ok(devices)
+    }
+
+    async fn process_block(
+        &self,
+        devices: &[SoldDevice],
+        database: &mut Database,
+        index: u64,
+    ) -> Result<(), Error> {
+        let block = self
+            .client
+            .get_block(index)
+            .await?
+            .ok_or_else(|| format!("finalized block {index} is not found"))?;
+        let events = block.events().await?;
+        let mut transfers: HashMap<u32, Vec<(u32, Transfer)>> = HashMap::new();
+        for event in events.iter() {
+            let event = event?;
+            let Phase::ApplyExtrinsic(extrinsic_index) = event.phase() else {
+                continue;
+            };
+            if let Some(transfer) = event.as_event::<Transfer>()? {
+                if is_watched(devices, &transfer.to) {
+                    transfers.entry(extrinsic_index).or_default().push((event.index(), transfer));
+                }
+            }
+        }
+
+        // Memo changes of this block, None is used memo.
+        let mut memos: BTreeMap<AccountId32, Option<(Memo, u64)>> = BTreeMap::new();
+        let mut payments = Vec::new();
+        for extrinsic in block.extrinsics().await?.iter() {
+            let extrinsic = extrinsic?;
+            if let Some(remark) = remark(&extrinsic)? {
+                let signer = extrinsic
+                    .address_bytes()
+                    .and_then(|mut bytes| MultiAddress::<AccountId32, ()>::decode(&mut bytes).ok());
+                if let (Some(MultiAddress::Id(signer)), Some(memo)) = (signer, Memo::parse(&remark))
+                {
+                    memos.insert(signer, Some((memo, index)));
+                }
+                continue;
+            }
+            for (event_index, transfer) in transfers.remove(&extrinsic.index()).unwrap_or_default()
+            {
+                let memo = match memos.get(&transfer.from) {
+                    Some(memo) => memo.clone(),
+                    None => database.memo(&transfer.from).await?,
+                };
+                let memo = memo
+                    .filter(|(_, block)| index.saturating_sub(*block) <= MEMO_MAX_AGE)
+                    .map(|(memo, _)| memo);
+                // Memo describes only one payment.
+                memos.insert(transfer.from.clone(), None);
+                let amount = Planck(transfer.amount);
+                let purchase = match_payment(devices, &transfer.to, amount, memo.as_ref());
+                payments.push(NewPayment {
+                    event_index,
+                    payer: transfer.from,
+                    recipient: transfer.to,
+                    amount,
+                    memo,
+                    purchase,
+                });
+            }
+        }
+        for payment in &payments {
+            match &payment.purchase {
+                Ok((device, kind)) => info!(
+                    "received {} from {} for {} to {}",
+                    self.token.format(payment.amount),
+                    payment.payer,
+                    kind,
+                    device
+                ),
+                Err(e) => warn!(
+                    "rejected {} from {} to {}: {}",
+                    self.token.format(payment.amount),
+                    payment.payer,
+                    payment.recipient,
+                    e
+                ),
+            }
+        }
+        database.save_block(index, &memos, &payments).await
+    }
+
+    // Returns true if any payer is assigned to access group.
+    async fn grant_pending(
+        &self,
+        devices: &[SoldDevice],
+        database: &mut Database,
+    ) -> Result<bool, Error> {
+        let mut changed = false;
+        for payment in database.pending().await? {
+            let kind: PurchaseKind = payment.kind.parse()?;
+            let device = devices
+                .iter()
+                .find(|v| v.address.to_string() == payment.device && v.price(kind).is_some());
+            let Some(device) = device else {
+                let reason = format!("device {} doesn't sell {} anymore", payment.device, kind);
+                warn!("payment {}: {}", payment.id, reason);
+                database.reject(payment.id, &reason).await?;
+                continue;
+            };
+            match kind {
+                PurchaseKind::Access => {
+                    changed |= self.grant_access(device, database, payment).await?;
+                }
+                PurchaseKind::Pin => self.pin(device, database, payment).await?,
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Re-syncs devices with private fields, so they are sealed to current access group members.
+    async fn reseal(&self) {
+        // Access groups are read again on every reseal, they have just changed.
+        let groups = AccessGroups::default();
+        for device in &self.devices {
+            if device.device.attributes.private.is_none() || device.device.access_group.is_none() {
+                continue;
+            }
+            match self.sync(device, &groups).await {
+                Ok(status) => info!("{}: resealed private fields: {}", device.name, status),
+                Err(e) => error!("{}: failed to reseal private fields: {}", device.name, e),
+            }
+        }
+    }
+
+    async fn grant_access(
+        &self,
+        device: &SoldDevice,
+        database: &mut Database,
+        payment: PendingPayment,
+    ) -> Result<bool, Error> {
+        let group = device.group.ok_or("device doesn't sell access")?;
+        let access_group = format!("0x{}", hex::encode(group));
+        let current = database.active_grant(&payment.payer, &access_group).await?;
+        database.attempt(payment.id).await?;
+        match self.grant(group, &payment, current.is_some()).await {
+            Ok(tx) => {
+                let expiry = self.payments.access_period.map(|period| {
+                    period.expiry(
+                        current.as_ref().and_then(Grant::expiry),
+                        payment.block as u64,
+                        payment.created_at as u64,
+                    )
+                });
+                let until = expiry.map_or("forever".to_string(), |v| format!("until {v}"));
+                info!(
+                    "{}: granted access to {} {} in {:?} transaction",
+                    device.name, payment.payer, until, tx
+                );
+                let grant = NewGrant {
+                    payer: payment.payer,
+                    access_group,
+                    device: payment.device,
+                    expiry,
+                };
+                let changed = tx.is_some();
+                database.grant(payment.id, tx.map(|tx| format!("{tx:?}")), &grant).await?;
+                Ok(changed)
+            }
+            Err(e) => {
+                error!("{}: failed to grant access to {}: {}", device.name, payment.payer, e);
+                database.fail(payment.id, &e.to_string()).await?;
+                Ok(false)
+            }
+        }
+    }
+
+    // Pin needs no transaction, device learns about it from pin ledger.
+    async fn pin(
+        &self,
+        device: &SoldDevice,
+        database: &mut Database,
+        payment: PendingPayment,
+    ) -> Result<(), Error> {
+        let memo = payment.memo.as_deref().and_then(|memo| Memo::parse(memo.as_bytes()));
+        let (
+            Some(period),
+            Some(Memo {
+                range: Some(range),
+                reference,
+                ..
+            }),
+        ) = (self.payments.pin_period, memo)
+        else {
+            return database.reject(payment.id, "pin payment has no range").await;
+        };
+        let expiry = period.expiry(None, payment.block as u64, payment.created_at as u64);
+        info!(
+            "{}: pinned {} range of data for {} until {}",
+            device.name, range, payment.payer, expiry
+        );
+        let pin = NewPin {
+            device: payment.device,
+            buyer: payment.payer,
+            range,
+            reference: Some(reference).filter(|v| !v.is_empty()),
+            expiry,
+        };
+        database.pin(payment.id, &pin).await
+    }
+
+    // Returns None if payer is already in group, e.g. grant was not saved before restart
+    // or payment renews active grant. Payer of renewal is assigned again if it was
+    // removed from group, e.g. by hand.
+    async fn grant(
+        &self,
+        group: [u8; ENTITY_ID_LENGTH],
+        payment: &PendingPayment,
+        renewal: bool,
+    ) -> Result<Option<H256>, Error> {
+        let admin = &self.main_device().peaq_client;
+        let payer = AccountId32::from_str(&payment.payer)?;
+        // Membership is read from storage, but it scans all assignments, so it is checked
+        // only when payer is likely in group already.
+        if renewal || payment.attempts > 0 {
+            let groups = admin.get_user_groups(&payer.0).await?;
+            if groups.contains(&group) {
+                return Ok(None);
+            }
+        }
+        Ok(Some(admin.rbac().assign_user_to_group(payer.0, group).await?))
+    }
+
+    // Returns true if any payer is unassigned from access group.
+    async fn revoke_expired(&self, database: &mut Database, finalized: u64) -> Result<bool, Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let mut changed = false;
+        for grant in database.expired_grants(finalized, now).await? {
+            database.attempt_revoke(grant.id).await?;
+            match self.revoke(&grant).await {
+                Ok(tx) => {
+                    info!(
+                        "revoked access of {} to {} group in {:?} transaction",
+                        grant.payer, grant.access_group, tx
+                    );
+                    changed |= tx.is_some();
+                    database.revoke(grant.id, tx.map(|tx| format!("{tx:?}"))).await?;
+                }
+                Err(e) => {
+                    error!(
+                        "failed to revoke access of {} to {} group: {}",
+                        grant.payer, grant.access_group, e
+                    );
+                    database.fail_revoke(grant.id, &e.to_string()).await?;
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    // Returns None if payer is not in group anymore, e.g. it was removed by hand.
+    async fn revoke(&self, grant: &Grant) -> Result<Option<H256>, Error> {
+        let admin = &self.main_device().peaq_client;
+        let payer = AccountId32::from_str(&grant.payer)?;
+        let group = parse_group(&grant.access_group)?;
+        if grant.attempts > 0 {
+            let groups = admin.get_user_groups(&payer.0).await?;
+            if !groups.contains(&group) {
+                return Ok(None);
+            }
+        }
+        Ok(Some(admin.rbac().unassign_user_to_group(payer.0, group).await?))
+    }
+}
+
+fn is_watched(devices: &[SoldDevice], recipient: &AccountId32) -> bool {
+    devices.iter().any(|v| v.address == *recipient || v.owner.as_ref() == Some(recipient))
+}
+
+/// Finds device and purchase kind which payment is for.
+fn match_payment(
+    devices: &[SoldDevice],
+    recipient: &AccountId32,
+    amount: Planck,
+    memo: Option<&Memo>,
+) -> Result<(AccountId32, PurchaseKind), String> {
+    let (device, kind) = match memo {
+        Some(memo) => {
+            let device = devices
+                .iter()
+                .find(|v| v.address == memo.device)
+                .ok_or_else(|| format!("device {} from memo is not sold", memo.device))?;
+            if device.address != *recipient && device.owner.as_ref() != Some(recipient) {
+                return Err(format!("recipient is neither device {} nor its owner", memo.device));
+            }
+            (device, memo.kind)
+        }
+        None => {
+            let device = devices
+                .iter()
+                .find(|v| v.address == *recipient)
+                .ok_or("payment to device owner requires memo")?;
+            (device, PurchaseKind::Access)
+        }
+    };
+    let price = device
+        .price(kind)
+        .ok_or_else(|| format!("device {} doesn't sell {kind}", device.address))?;
+    if amount < price {
+        return Err(format!("amount {amount} is less than {kind} price {price} in planck"));
+    }
+    Ok((device.address.clone(), kind))
+}
+
+fn remark(
+    extrinsic: &subxt::blocks::ExtrinsicDetails<
+        subxt::PolkadotConfig,
+        subxt::OnlineClient<subxt::PolkadotConfig>,
+    >,
+) -> Result<Option<Vec<u8>>, Error> {
+    if let Some(call) = extrinsic.as_extrinsic::<Remark>()? {
+        return Ok(Some(call.remark));
+    }
+    Ok(extrinsic.as_extrinsic::<RemarkWithEvent>()?.map(|call| call.remark))
+}
+
+fn expiry_columns(expiry: Option<Expiry>) -> (Option<i64>, Option<i64>) {
+    match expiry {
+        Some(Expiry::Block(block)) => (Some(block as i64), None),
+        Some(Expiry::Time(time)) => (None, Some(time as i64)),
+        None => (None, None),
+    }
+}
+
+const GRANT_COLUMNS: &str =
+    "id, payer, access_group, device, expires_block, expires_at, status, attempts";
+
+struct Database {
+    conn: SqliteConnection,
+}
+
+impl Database {
+    async fn new(dsn: &str) -> Result<Self, Error> {
+        let mut conn = open_sqlite(dsn).await?;
+        let migrator = sqlx::migrate!("./migrations/payments/");
+        migrator.run_direct(&mut conn).await?;
+        Ok(Self { conn })
+    }
+
+    /// Returns the last processed block.
+    async fn cursor(&mut self) -> Result<Option<u64>, Error> {
+        let block: Option<i64> = sqlx::query_scalar("select block from cursor where id = 1")
+            .fetch_optional(&mut self.conn)
+            .await?;
+        Ok(block.map(|v| v as u64))
+    }
+
+    async fn memo(&mut self, payer: &AccountId32) -> Result<Option<(Memo, u64)>, Error> {
+        let memo: Option<(String, i64)> =
+            sqlx::query_as("select memo, block from memos where payer = ?1")
+                .bind(payer.to_string())
+                .fetch_optional(&mut self.conn)
+                .await?;
+        Ok(memo.and_then(|(memo, block)| Some((Memo::parse(memo.as_bytes())?, block as u64))))
+    }
+
+    async fn save_block(
+        &mut self,
+        index: u64,
+        memos: &BTreeMap<AccountId32, Option<(Memo, u64)>>,
+        payments: &[NewPayment],
+    ) -> Result<(), Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let mut tx = self.conn.begin().await?;
+        for (payer, memo) in memos {
+            match memo {
+                Some((memo, block)) => {
+                    sqlx::query(
+                        r#"
+                            insert into memos (payer, memo, block)
+                            values (?1, ?2, ?3)
+                            on conflict(payer) do update
+                            set memo = ?2, block = ?3
+                        "#,
+                    )
+                    .bind(payer.to_string())
+                    .bind(memo.to_string())
+                    .bind(*block as i64)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                None => {
+                    sqlx::query("delete from memos where payer = ?1")
+                        .bind(payer.to_string())
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+        }
+        for payment in payments {
+            let (device, kind, status, error) = match &payment.purchase {
+                Ok((device, kind)) => {
+                    (Some(device.to_string()), Some(kind.to_string()), "pending", None)
+                }
+                Err(e) => (None, None, "rejected", Some(e.clone())),
+            };
+            sqlx::query(
+                r#"
+                    insert into payments (
+                      block, event_index, payer, recipient, amount, memo, device, kind, reference,
+                      status, error, created_at, updated_at
+                    )
+                    values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?12)
+                    on conflict(block, event_index) do nothing
+                "#,
+            )
+            .bind(index as i64)
+            .bind(payment.event_index)
+            .bind(payment.payer.to_string())
+            .bind(payment.recipient.to_string())
+            .bind(payment.amount.to_string())
+            .bind(payment.memo.as_ref().map(ToString::to_string))
+            .bind(device)
+            .bind(kind)
+            .bind(payment.memo.as_ref().map(|v| v.reference.clone()))
+            .bind(status)
+            .bind(error)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query(
+            r#"
+                insert into cursor (id, block)
+                values (1, ?1)
+                on conflict(id) do update
+                set block = ?1
+            "#,
+        )
+        .bind(index as i64)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn pending(&mut self) -> Result<Vec<PendingPayment>, Error> {
+        Ok(sqlx::query_as(
+            r#"
+                select id, block, payer, device, kind, memo, attempts, created_at
+                from payments
+                where status = 'pending'
+                order by id
+            "#,
+        )
+        .fetch_all(&mut self.conn)
+        .await?)
+    }
+
+    async fn attempt(&mut self, id: i64) -> Result<(), Error> {
+        self.update("payments", id, "attempts = attempts + 1", None).await
+    }
+
+    /// Marks payment as granted and creates or renews grant in one transaction.
+    async fn grant(&mut self, id: i64, tx: Option<String>, grant: &NewGrant) -> Result<(), Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let (expires_block, expires_at) = expiry_columns(grant.expiry);
+        let mut db_tx = self.conn.begin().await?;
+        sqlx::query(
+            r#"
+                update payments
+                set status = 'granted', error = null, grant_tx = ?2, updated_at = ?3
+                where id = ?1
+            "#,
+        )
+        .bind(id)
+        .bind(tx)
+        .bind(now)
+        .execute(&mut *db_tx)
+        .await?;
+        sqlx::query(
+            r#"
+                insert into grants (
+                  payer, access_group, device, expires_block, expires_at, status,
+                  created_at, updated_at
+                )
+                values (?1, ?2, ?3, ?4, ?5, 'active', ?6, ?6)
+                on conflict(payer, access_group) do update
+                set device = ?3, expires_block = ?4, expires_at = ?5, status = 'active',
+                  error = null, attempts = 0, revoke_tx = null, updated_at = ?6
+            "#,
+        )
+        .bind(&grant.payer)
+        .bind(&grant.access_group)
+        .bind(&grant.device)
+        .bind(expires_block)
+        .bind(expires_at)
+        .bind(now)
+        .execute(&mut *db_tx)
+        .await?;
+        db_tx.commit().await?;
+        Ok(())
+    }
+
+    /// Marks payment as granted and creates pin in one transaction.
+    async fn pin(&mut self, id: i64, pin: &NewPin) -> Result<(), Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let (expires_block, expires_at) = expiry_columns(Some(pin.expiry));
+        let mut db_tx = self.conn.begin().await?;
+        sqlx::query(
+            "update payments set status = 'granted', error = null, updated_at = ?2 where id = ?1",
+        )
+        .bind(id)
+        .bind(now)
+        .execute(&mut *db_tx)
+        .await?;
+        sqlx::query(
+            r#"
+                insert into pins (
+                  payment_id, device, buyer, range_from, range_to, reference,
+                  expires_block, expires_at, created_at
+                )
+                values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                on conflict(payment_id) do nothing
+            "#,
+        )
+        .bind(id)
+        .bind(&pin.device)
+        .bind(&pin.buyer)
+        .bind(pin.range.from as i64)
+        .bind(pin.range.to as i64)
+        .bind(&pin.reference)
+        .bind(expires_block)
+        .bind(expires_at)
+        .bind(now)
+        .execute(&mut *db_tx)
+        .await?;
+        db_tx.commit().await?;
+        Ok(())
+    }
+
+    /// Returns pins of device which are not expired at given finalized block and time.
+    async fn pins(
+        &mut self,
+        device: &str,
+        buyer: Option<&str>,
+        finalized: u64,
+        now: u64,
+    ) -> Result<Vec<PinResponse>, Error> {
+        let pins: Vec<PinRow> = sqlx::query_as(
+            r#"
+                    select buyer, range_from, range_to, reference, expires_block, expires_at
+                    from pins
+                    where device = ?1 and (?2 is null or buyer = ?2)
+                      and (expires_block > ?3 or expires_at > ?4)
+                    order by range_from, id
+                "#,
+        )
+        .bind(device)
+        .bind(buyer)
+        .bind(finalized as i64)
+        .bind(now as i64)
+        .fetch_all(&mut self.conn)
+        .await?;
+        Ok(pins
+            .into_iter()
+            .map(|pin| PinResponse {
+                buyer: pin.buyer,
+                from: pin.range_from as u64,
+                to: pin.range_to as u64,
+                reference: pin.reference,
+                expires_block: pin.expires_block.map(|v| v as u64),
+                expires_at: pin.expires_at.map(|v| v as u64),
+            })
+            .collect())
+    }
+
+    async fn fail(&mut self, id: i64, error: &str) -> Result<(), Error> {
+        self.update("payments", id, "error = ?3", Some(error.to_string())).await
+    }
+
+    async fn reject(&mut self, id: i64, error: &str) -> Result<(), Error> {
+        self.update("payments", id, "status = 'rejected', error = ?3", Some(error.to_string()))
+            .await
+    }
+
+    async fn active_grant(
+        &mut self,
+        payer: &str,
+        access_group: &str,
+    ) -> Result<Option<Grant>, Error> {
+        Ok(sqlx::query_as(&format!(
+            "select {GRANT_COLUMNS} from grants \
+             where payer = ?1 and access_group = ?2 and status = 'active'"
+        ))
+        .bind(payer)
+        .bind(access_group)
+        .fetch_optional(&mut self.conn)
+        .await?)
+    }
+
+    async fn grants(&mut self, payer: &str) -> Result<Vec<Grant>, Error> {
+        Ok(sqlx::query_as(&format!(
+            "select {GRANT_COLUMNS} from grants where payer = ?1 order by id"
+        ))
+        .bind(payer)
+        .fetch_all(&mut self.conn)
+        .await?)
+    }
+
+    async fn expired_grants(&mut self, finalized: u64, now: u64) -> Result<Vec<Grant>, Error> {
+        Ok(sqlx::query_as(&format!(
+            "select {GRANT_COLUMNS} from grants \
+             where status = 'active' and (expires_block <= ?1 or expires_at <= ?2) \
+             order by id"
+        ))
+        .bind(finalized as i64)
+        .bind(now as i64)
+        .fetch_all(&mut self.conn)
+        .await?)
+    }
+
+    async fn attempt_revoke(&mut self, id: i64) -> Result<(), Error> {
+        self.update("grants", id, "attempts = attempts + 1", None).await
+    }
+
+    async fn revoke(&mut self, id: i64, tx: Option<String>) -> Result<(), Error> {
+        self.update("grants", id, "status = 'revoked', error = null, revoke_tx = ?3", tx).await
+    }
+
+    async fn fail_revoke(&mut self, id: i64, error: &str) -> Result<(), Error> {
+        self.update("grants", id, "error = ?3", Some(error.to_string())).await
+    }
+
+    async fn update(
+        &mut self,
+        table: &str,
+        id: i64,
+        set: &str,
+        value: Option<String>,
+    ) -> Result<(), Error> {
+        sqlx::query(&format!("update {table} set {set}, updated_at = ?2 where id = ?1"))
+            .bind(id)
+            .bind(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64)
+            .bind(value)
+            .execute(&mut self.conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Grant of payer with time left before it is revoked, owner is account which owns access group.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct GrantResponse {
+    pub(crate) access_group: String,
+    pub(crate) owner: String,
+    pub(crate) device: String,
+    pub(crate) status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) expires_block: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) expires_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) remaining_blocks: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) remaining_seconds: Option<u64>,
+}
+
+impl GrantResponse {
+    fn new(grant: Grant, owner: &AccountId32, finalized: u64, now: u64) -> Self {
+        let expiry = grant.expiry();
+        // Expired grant which is not revoked yet has no time left too.
+        let remaining = |expires: u64, current: u64| match grant.status.as_str() {
+            "active" => expires.saturating_sub(current),
+            _ => 0,
+        };
+        Self {
+            remaining_blocks: match expiry {
+                Some(Expiry::Block(block)) => Some(remaining(block, finalized)),
+                _ => None,
+            },
+            remaining_seconds: match expiry {
+                Some(Expiry::Time(time)) => Some(remaining(time, now)),
+                _ => None,
+            },
+            expires_block: grant.expires_block.map(|v| v as u64),
+            expires_at: grant.expires_at.map(|v| v as u64),
+            access_group: grant.access_group,
+            owner: owner.to_string(),
+            device: grant.device,
+            status: grant.status,
+        }
+    }
+}
+
+/// Pins in payments database, indexer serves them to devices and buyers.
+pub(crate) struct PinLedger {
+    database: Database,
+}
+
+impl PinLedger {
+    pub(crate) async fn open(dsn: &str) -> Result<Self, Error> {
+        Ok(Self {
+            database: Database::new(dsn).await?,
+        })
+    }
+
+    /// Returns ranges which device must keep at given finalized block and time.
+    pub(crate) async fn pins(
+        &mut self,
+        device: &str,
+        buyer: Option<&str>,
+        finalized: u64,
+        now: u64,
+    ) -> Result<Vec<PinResponse>, Error> {
+        self.database.pins(device, buyer, finalized, now).await
+    }
+}
+
+type DatabasePointer = Arc<Mutex<Database>>;
+
+async fn run_api(
+    cfg: &config::Payments,
+    client: Arc<Client>,
+    owner: AccountId32,
+) -> Result<(), Error> {
+    // API has its own connection to not wait for block processing.
+    let database: DatabasePointer = Arc::new(Mutex::new(Database::new(&cfg.dsn).await?));
+    let addr = format!("{}:{}", cfg.server.host, cfg.server.port);
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    info!("listen on {addr} for HTTP requests");
+    tokio::spawn(async move {
+        let app = Router::new()
+            .route("/grants/:address", get(get_grants))
+            .layer(Extension(database))
+            .layer(Extension(client))
+            .layer(Extension(Arc::new(owner)));
+        if let Err(e) = axum::serve(listener, app).await {
+            error!("failed to run payments api: {e}");
+        }
+    });
+    Ok(())
+}
+
+/// Returns grants of payer with remaining blocks or seconds.
+async fn get_grants(
+    Extension(database): Extension<DatabasePointer>,
+    Extension(client): Extension<Arc<Client>>,
+    Extension(owner): Extension<Arc<AccountId32>>,
+    Path(address): Path<String>,
+) -> Response {
+    let Ok(address) = AccountId32::from_str(&address) else {
+        return (StatusCode::BAD_REQUEST, "invalid address").into_response();
+    };
+    match grants(&database, &client, &owner, &address).await {
+        Ok(grants) => (StatusCode::OK, Json(grants)).into_response(),
+        Err(e) => {
+            error!("failed to get grants of {}: {}", address, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn grants(
+    database: &Mutex<Database>,
+    client: &Client,
+    owner: &AccountId32,
+    address: &AccountId32,
+) -> Result<Vec<GrantResponse>, Error> {
+    let finalized = client.get_finalized_block_number().await?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let grants = database.lock().await.grants(&address.to_string()).await?;
+    Ok(grants.into_iter().map(|grant| GrantResponse::new(grant, owner, finalized, now)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDb;
+
+    fn device(id: u8, owner: Option<u8>) -> SoldDevice {
+        SoldDevice {
+            name: format!("sensor-{id}"),
+            address: AccountId32([id; 32]),
+            owner: owner.map(|v| AccountId32([v; 32])),
+            price_access: Planck(100),
+            group: Some([id; ENTITY_ID_LENGTH]),
+            price_pin: Some(Planck(200)),
+        }
+    }
+
+    #[test]
+    fn memo() {
+        let device = AccountId32([1; 32]);
+        let memo = Memo {
+            kind: PurchaseKind::Access,
+            device: device.clone(),
+            reference: "order-1".to_string(),
+            range: None,
+        };
+        assert_eq!(Memo::parse(memo.to_string().as_bytes()), Some(memo));
+        let memo = Memo {
+            kind: PurchaseKind::Pin,
+            device: device.clone(),
+            reference: String::new(),
+            range: Some(PinRange { from: 10, to: 20 }),
+        };
+        assert_eq!(memo.to_string(), format!("{PURCHASE_MEMO_PREFIX}:pin:{device}::10-20"));
+        assert_eq!(Memo::parse(memo.to_string().as_bytes()), Some(memo));
+        for range in ["", ":20-10", ":10", ":10-20:30"] {
+            let remark = format!("{PURCHASE_MEMO_PREFIX}:pin:{device}:order-1{range}");
+            assert!(Memo::parse(remark.as_bytes()).is_none(), "{remark}");
+        }
+        let remark = format!("{PURCHASE_MEMO_PREFIX}:access:{device}");
+        assert_eq!(Memo::parse(remark.as_bytes()).unwrap().reference, "");
+        assert!(
+            Memo::parse(format!("{PURCHASE_MEMO_PREFIX}:access:{device}:a:b").as_bytes()).is_none()
+        );
+        assert!(Memo::parse(format!("{PURCHASE_MEMO_PREFIX}:rent:{device}").as_bytes()).is_none());
+        assert!(Memo::parse(format!("other:access:{device}").as_bytes()).is_none());
+    }
+
+    #[test]
+    fn matching() {
+        let devices = [device(1, Some(9)), device(2, Some(9))];
+        let owner = AccountId32([9; 32]);
+        let memo = |id: u8| Memo {
+            kind: PurchaseKind::Access,
+            device: AccountId32([id; 32]),
+            reference: String::new(),
+            range: None,
+        };
+        assert_eq!(
+            match_payment(&devices, &devices[0].address, Planck(100), None),
+            Ok((devices[0].address.clone(), PurchaseKind::Access))
+        );
+        assert!(match_payment(&devices, &devices[0].address, Planck(99), None).is_err());
+        assert!(match_payment(&devices, &owner, Planck(100), None).is_err());
+        assert_eq!(
+            match_payment(&devices, &owner, Planck(100), Some(&memo(2))),
+            Ok((devices[1].address.clone(), PurchaseKind::Access))
+        );
+        assert!(match_payment(&devices, &devices[0].address, Planck(100), Some(&memo(2))).is_err());
+        assert!(match_payment(&devices, &owner, Planck(100), Some(&memo(3))).is_err());
+
+        let pin = Memo {
+            kind: PurchaseKind::Pin,
+            range: Some(PinRange { from: 10, to: 20 }),
+            ..memo(1)
+        };
+        assert_eq!(
+            match_payment(&devices, &owner, Planck(200), Some(&pin)),
+            Ok((devices[0].address.clone(), PurchaseKind::Pin))
+        );
+        assert!(match_payment(&devices, &owner, Planck(199), Some(&pin)).is_err());
+        // Device sells only pins or only access.
+        let devices = [
+            SoldDevice {
+                group: None,
+                ..device(1, None)
+            },
+            SoldDevice {
+                price_pin: None,
+                ..device(2, None)
+            },
+        ];
+        assert!(match_payment(&devices, &devices[0].address, Planck(100), None).is_err());
+        assert!(match_payment(&devices, &devices[0].address, Planck(200), Some(&pin)).is_ok());
+        let pin = Memo {
+            device: devices[1].address.clone(),
+            ..pin
+        };
+        assert!(match_payment(&devices, &devices[1].address, Planck(200), Some(&pin)).is_err());
+    }
+
+    #[tokio::test]
+    async fn payments() {
+        let db = TempDb::new("payments");
+        let mut database = Database::new(&db.dsn()).await.unwrap();
+        assert_eq!(database.cursor().await.unwrap(), None);
+
+        let payer = AccountId32([5; 32]);
+        let device = AccountId32([1; 32]);
+        let memo = Memo {
+            kind: PurchaseKind::Access,
+            device: device.clone(),
+            reference: "order-1".to_string(),
+            range: None,
+        };
+        let memos = BTreeMap::from([(payer.clone(), Some((memo.clone(), 10)))]);
+        database.save_block(10, &memos, &[]).await.unwrap();
+        assert_eq!(database.memo(&payer).await.unwrap(), Some((memo.clone(), 10)));
+
+        let payments = [NewPayment {
+            event_index: 3,
+            payer: payer.clone(),
+            recipient: device.clone(),
+            amount: Planck(u128::MAX),
+            memo: Some(memo),
+            purchase: Ok((device.clone(), PurchaseKind::Access)),
+        }];
+        let memos = BTreeMap::from([(payer.clone(), None)]);
+        database.save_block(11, &memos, &payments).await.unwrap();
+        // Block is processed again after crash before cursor was read.
+        database.save_block(11, &memos, &payments).await.unwrap();
+        assert_eq!(database.cursor().await.unwrap(), Some(11));
+        assert_eq!(database.memo(&payer).await.unwrap(), None);
+
+        let pending = database.pending().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].device, device.to_string());
+        database.attempt(pending[0].id).await.unwrap();
+        database.fail(pending[0].id, "network error").await.unwrap();
+        let pending = database.pending().await.unwrap();
+        assert_eq!(pending[0].attempts, 1);
+        assert_eq!(pending[0].block, 11);
+        let group = format!("0x{}", "01".repeat(ENTITY_ID_LENGTH));
+        let grant = NewGrant {
+            payer: payer.to_string(),
+            access_group: group.clone(),
+            device: device.to_string(),
+            expiry: Some(Expiry::Block(111)),
+        };
+        database.grant(pending[0].id, Some("0x1".to_string()), &grant).await.unwrap();
+        assert!(database.pending().await.unwrap().is_empty());
+        let active = database.active_grant(&payer.to_string(), &group).await.unwrap().unwrap();
+        assert_eq!(active.expiry(), Some(Expiry::Block(111)));
+        assert!(database.expired_grants(110, 0).await.unwrap().is_empty());
+
+        let expired = database.expired_grants(111, 0).await.unwrap();
+        assert_eq!(expired.len(), 1);
+        database.attempt_revoke(expired[0].id).await.unwrap();
+        database.revoke(expired[0].id, Some("0x2".to_string())).await.unwrap();
+        assert!(database.expired_grants(111, 0).await.unwrap().is_empty());
+        assert!(database.active_grant(&payer.to_string(), &group).await.unwrap().is_none());
+
+        // Payment after revocation starts new grant.
+        database
+            .save_block(
+                12,
+                &BTreeMap::new(),
+                &[NewPayment {
+                    event_index: 1,
+                    ..payments[0].clone()
+                }],
+            )
+            .await
+            .unwrap();
+        let pending = database.pending().await.unwrap();
+        let grant = NewGrant {
+            expiry: Some(Expiry::Block(212)),
+            ..grant
+        };
+        database.grant(pending[0].id, None, &grant).await.unwrap();
+        let grants = database.grants(&payer.to_string()).await.unwrap();
+        assert_eq!(grants.len(), 1);
+        assert_eq!(grants[0].status, "active");
+        assert_eq!(grants[0].attempts, 0);
+        assert_eq!(grants[0].expiry(), Some(Expiry::Block(212)));
+    }
+
+    #[tokio::test]
+    async fn pins() {
+        let db = TempDb::new("pins");
+        let dsn = db.dsn();
+        let mut database = Database::new(&dsn).await.unwrap();
+        let buyer = AccountId32([5; 32]);
+        let device = AccountId32([1; 32]);
+        let range = PinRange { from: 10, to: 20 };
+        let memo = Memo {
+            kind: PurchaseKind::Pin,
+            device: device.clone(),
+            reference: "pin-1".to_string(),
+            range: Some(range),
+        };
+        let payments = [NewPayment {
+            event_index: 3,
+            payer: buyer.clone(),
+            recipient: device.clone(),
+            amount: Planck(200),
+            memo: Some(memo.clone()),
+            purchase: Ok((device.clone(), PurchaseKind::Pin)),
+        }];
+        database.save_block(11, &BTreeMap::new(), &payments).await.unwrap();
+        let pending = database.pending().await.unwrap();
+        assert_eq!(pending[0].kind, "pin");
+        assert_eq!(pending[0].memo.as_deref(), Some(memo.to_string().as_str()));
+        let pin = NewPin {
+            device: device.to_string(),
+            buyer: buyer.to_string(),
+            range,
+            reference: Some(memo.reference),
+            expiry: Expiry::Time(1000),
+        };
+        database.pin(pending[0].id, &pin).await.unwrap();
+        // Pin is recorded once even if payment is processed again.
+        database.pin(pending[0].id, &pin).await.unwrap();
+        assert!(database.pending().await.unwrap().is_empty());
+
+        let mut ledger = PinLedger::open(&dsn).await.unwrap();
+        let pins = ledger.pins(&device.to_string(), None, 0, 999).await.unwrap();
+        assert_eq!(
+            pins,
+            [PinResponse {
+                buyer: buyer.to_string(),
+                from: 10,
+                to: 20,
+                reference: Some("pin-1".to_string()),
+                expires_block: None,
+                expires_at: Some(1000),
+            }]
+        );
+        let other = AccountId32([6; 32]).to_string();
+        assert!(ledger.pins(&device.to_string(), Some(&other), 0, 999).await.unwrap().is_empty());
+        assert!(ledger.pins(&device.to_string(), None, 0, 1000).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn pin_range() {
+        assert_eq!("10-20".parse::<PinRange>().unwrap(), PinRange { from: 10, to: 20 });
+        assert!("20-10".parse::<PinRange>().is_err());
+        assert!("10-10".parse::<PinRange>().is_err());
+        assert!("10".parse::<PinRange>().is_err());
+        assert!("-10-20".parse::<PinRange>().is_err());
+    }
+
+    #[test]
+    fn access_period() {
+        assert_eq!("30 days".parse::<Period>().unwrap(), Period::Seconds(2592000));
+        assert_eq!("1 hour".parse::<Period>().unwrap(), Period::Seconds(3600));
+        assert_eq!("14400 blocks".parse::<Period>().unwrap(), Period::Blocks(14400));
+        assert!("30".parse::<Period>().is_err());
+        assert!("30 weeks".parse::<Period>().is_err());
+        assert!("-1 days".parse::<Period>().is_err());
+        for period in ["30 days", "90 minutes", "61 seconds", "1 blocks"] {
+            assert_eq!(period.parse::<Period>().unwrap().to_string(), period);
+        }
+    }
+
+    #[test]
+    fn expiry() {
+        let blocks = Period::Blocks(100);
+        assert_eq!(blocks.expiry(None, 10, 1000), Expiry::Block(110));
+        // Renewal before expiry extends grant, renewal after it starts from payment.
+        assert_eq!(blocks.expiry(Some(Expiry::Block(50)), 10, 1000), Expiry::Block(150));
+        assert_eq!(blocks.expiry(Some(Expiry::Block(5)), 10, 1000), Expiry::Block(110));
+        // Period unit was changed in config.
+        assert_eq!(blocks.expiry(Some(Expiry::Time(5000)), 10, 1000), Expiry::Block(110));
+        let seconds = Period::Seconds(60);
+        assert_eq!(seconds.expiry(Some(Expiry::Time(1030)), 10, 1000), Expiry::Time(1090));
+
+        let grant = |status: &str| Grant {
+            id: 1,
+            payer: String::new(),
+            access_group: String::new(),
+            device: String::new(),
+            expires_block: None,
+            expires_at: Some(1090),
+            status: status.to_string(),
+            attempts: 0,
+        };
+        let owner = AccountId32([9; 32]);
+        let response = GrantResponse::new(grant("active"), &owner, 10, 1000);
+        assert_eq!(response.owner, owner.to_string());
+        assert_eq!(response.remaining_seconds, Some(90));
+        assert_eq!(response.remaining_blocks, None);
+        let response = GrantResponse::new(grant("revoked"), &owner, 10, 1000);
+        assert_eq!(response.remaining_seconds, Some(0));
+    }
+}
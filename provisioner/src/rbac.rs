@@ -0,0 +1,744 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use clap::{Args, Subcommand};
+use log::info;
+use peaq_client::{OwnedAssignments, OwnedEntities, RBACRecord, TrySigner, ENTITY_ID_LENGTH, RBAC};
+use serde::{Deserialize, Serialize};
+use subxt::utils::AccountId32;
+
+use crate::{
+    config::{self, Config},
+    decommission::confirm,
+    App, Error,
+};
+
+type EntityId = [u8; ENTITY_ID_LENGTH];
+
+#[derive(Subcommand)]
+pub(crate) enum RbacCommand {
+    /// Show changes needed to make on-chain RBAC match policy.
+    Plan {
+        #[command(flatten)]
+        args: PolicyArgs,
+    },
+    /// Make on-chain RBAC match policy.
+    Apply {
+        #[command(flatten)]
+        args: PolicyArgs,
+        /// Don't ask for confirmation.
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Args)]
+pub(crate) struct PolicyArgs {
+    /// Policy file with permissions, roles and groups.
+    #[arg(long, default_value = "rbac.toml")]
+    policy: PathBuf,
+    /// File with on-chain ids of policy entities [default: <POLICY>.ids.toml].
+    #[arg(long)]
+    ids: Option<PathBuf>,
+    /// Device which owns RBAC entities, main device by default.
+    #[arg(long, default_value = config::MAIN_DEVICE_NAME)]
+    device: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub(crate) enum EntityKind {
+    Permission,
+    Role,
+    Group,
+}
+
+impl std::fmt::Display for EntityKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Role => write!(f, "role"),
+            Self::Permission => write!(f, "permission"),
+            Self::Group => write!(f, "group"),
+        }
+    }
+}
+
+const ENTITY_KINDS: [EntityKind; 3] = [EntityKind::Permission, EntityKind::Role, EntityKind::Group];
+
+/// RBAC entities and their assignments declared by name.
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+struct Policy {
+    #[serde(default)]
+    permissions: BTreeSet<String>,
+    #[serde(default)]
+    roles: BTreeMap<String, RolePolicy>,
+    #[serde(default)]
+    groups: BTreeMap<String, GroupPolicy>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+struct RolePolicy {
+    #[serde(default)]
+    permissions: BTreeSet<String>,
+    /// Device names or addresses.
+    #[serde(default)]
+    users: BTreeSet<String>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+struct GroupPolicy {
+    #[serde(default)]
+    roles: BTreeSet<String>,
+    /// Device names or addresses.
+    #[serde(default)]
+    users: BTreeSet<String>,
+}
+
+/// On-chain ids of entities created from policy.
+///
+/// Entity names are not unique on chain, so ids are the only way to find entities again.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Ids {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner: Option<AccountId32>,
+    #[serde(default, with = "hex_ids")]
+    permissions: BTreeMap<String, EntityId>,
+    #[serde(default, with = "hex_ids")]
+    roles: BTreeMap<String, EntityId>,
+    #[serde(default, with = "hex_ids")]
+    groups: BTreeMap<String, EntityId>,
+}
+
+/// Entity which is referenced by policy name, or only by id if it is not in policy.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Ref {
+    Name(String),
+    Id(EntityId),
+}
+
+/// Assignment with entity which holds it on chain, e.g. role for its permissions, on the right.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Assignment {
+    PermissionToRole { permission: Ref, role: String },
+    RoleToGroup { role: Ref, group: String },
+    RoleToUser { role: String, user: AccountId32 },
+    UserToGroup { user: AccountId32, group: String },
+}
+
+enum Action {
+    /// Use enabled on-chain entity with the same name which is missing in ids file.
+    Adopt {
+        kind: EntityKind,
+        name: String,
+        id: EntityId,
+    },
+    Add {
+        kind: EntityKind,
+        name: String,
+    },
+    Unassign(Assignment),
+    Assign(Assignment),
+    Disable {
+        kind: EntityKind,
+        name: String,
+        id: EntityId,
+    },
+    /// Remove already disabled or missing entity from ids file.
+    Forget {
+        kind: EntityKind,
+        name: String,
+    },
+}
+
+pub(crate) async fn run(cfg: Config, command: RbacCommand) -> Result<(), Error> {
+    let (args, apply, yes) = match &command {
+        RbacCommand::Plan { args } => (args, false, false),
+        RbacCommand::Apply { args, yes } => (args, true, *yes),
+    };
+    let app = App::new(cfg).await?;
+    let peaq_client = &app.device(&args.device)?.peaq_client;
+    let owner = peaq_client.address();
+    let policy = Policy::load(&args.policy)?;
+    let ids_path = args.ids.clone().unwrap_or_else(|| args.policy.with_extension("ids.toml"));
+    let mut ids = Ids::load(&ids_path)?;
+    if let Some(ids_owner) = &ids.owner {
+        if *ids_owner != owner {
+            return Err(format!(
+                "{} belongs to {}, but device {} is {}",
+                ids_path.display(),
+                ids_owner,
+                args.device,
+                owner
+            )
+            .into());
+        }
+    }
+    let users = app.resolve_users(&policy)?;
+    let owned = peaq_client.get_owned_entities(&owner).await?;
+    let assignments = peaq_client.get_owned_assignments(&owner).await?;
+    let actions = plan(&policy, &users, &ids, &owned, &assignments)?;
+
+    if actions.is_empty() {
+        eprintln!("On-chain RBAC of {} matches policy", owner);
+        return Ok(());
+    }
+    eprintln!("Changes to RBAC of {}:", owner);
+    for action in &actions {
+        eprintln!("  {}", action);
+    }
+    if !apply {
+        return Ok(());
+    }
+    if !yes && !confirm(&format!("Apply {} changes?", actions.len()))? {
+        eprintln!("Applying is cancelled");
+        return Ok(());
+    }
+
+    ids.owner = Some(owner);
+    let rbac = peaq_client.rbac();
+    for (i, action) in actions.iter().enumerate() {
+        info!("{}", action);
+        if let Err(e) = apply_action(&rbac, &mut ids, &ids_path, action).await {
+            return Err(format!(
+                "failed to {}: {}; {} of {} changes are applied, run apply again",
+                action,
+                e,
+                i,
+                actions.len()
+            )
+            .into());
+        }
+    }
+    eprintln!("Applied {} changes", actions.len());
+    Ok(())
+}
+
+impl App {
+    /// Maps policy users to addresses; user is either address or device name.
+    fn resolve_users(&self, policy: &Policy) -> Result<HashMap<String, AccountId32>, Error> {
+        policy
+            .users()
+            .map(|user| {
+                let address = match AccountId32::from_str(user) {
+                    Ok(address) => address,
+                    Err(_) => self
+                        .device(user)
+                        .map_err(|_| format!("user {user} is neither address nor device name"))?
+                        .peaq_client
+                        .address(),
+                };
+                Ok((user.to_string(), address))
+            })
+            .collect()
+    }
+}
+
+// Ids file is saved after every change, so ids of created entities are never lost.
+async fn apply_action<S: TrySigner>(
+    rbac: &RBAC<'_, S>,
+    ids: &mut Ids,
+    ids_path: &Path,
+    action: &Action,
+) -> Result<(), Error> {
+    match action {
+        Action::Adopt { kind, name, id } => {
+            ids.get_mut(*kind).insert(name.clone(), *id);
+        }
+        Action::Add { kind, name } => {
+            let id = match kind {
+                EntityKind::Permission => rbac.add_permission(name.clone()).await?,
+                EntityKind::Role => rbac.add_role(name.clone()).await?,
+                EntityKind::Group => rbac.add_group(name.clone()).await?,
+            };
+            ids.get_mut(*kind).insert(name.clone(), id);
+        }
+        Action::Unassign(assignment) => {
+            let tx = match assignment {
+                Assignment::PermissionToRole { permission, role } => {
+                    let permission = ids.resolve(EntityKind::Permission, permission)?;
+                    let role = ids.id(EntityKind::Role, role)?;
+                    rbac.unassign_permission_to_role(permission, role).await?
+                }
+                Assignment::RoleToGroup { role, group } => {
+                    let role = ids.resolve(EntityKind::Role, role)?;
+                    let group = ids.id(EntityKind::Group, group)?;
+                    rbac.unassign_role_to_group(role, group).await?
+                }
+                Assignment::RoleToUser { role, user } => {
+                    rbac.unassign_role_to_user(ids.id(EntityKind::Role, role)?, user.0).await?
+                }
+                Assignment::UserToGroup { user, group } => {
+                    rbac.unassign_user_to_group(user.0, ids.id(EntityKind::Group, group)?).await?
+                }
+            };
+            info!("unassigned in {:?} transaction", tx);
+            return Ok(());
+        }
+        Action::Assign(assignment) => {
+            let tx = match assignment {
+                Assignment::PermissionToRole { permission, role } => {
+                    let permission = ids.resolve(EntityKind::Permission, permission)?;
+                    let role = ids.id(EntityKind::Role, role)?;
+                    rbac.assign_permission_to_role(permission, role).await?
+                }
+                Assignment::RoleToGroup { role, group } => {
+                    let role = ids.resolve(EntityKind::Role, role)?;
+                    let group = ids.id(EntityKind::Group, group)?;
+                    rbac.assign_role_to_group(role, group).await?
+                }
+                Assignment::RoleToUser { role, user } => {
+                    rbac.assign_role_to_user(ids.id(EntityKind::Role, role)?, user.0).await?
+                }
+                Assignment::UserToGroup { user, group } => {
+                    rbac.assign_user_to_group(user.0, ids.id(EntityKind::Group, group)?).await?
+                }
+            };
+            info!("assigned in {:?} transaction", tx);
+            return Ok(());
+        }
+        Action::Disable { kind, name, id } => {
+            let tx = match kind {
+                EntityKind::Permission => rbac.disable_permission(*id).await?,
+                EntityKind::Role => rbac.disable_role(*id).await?,
+                EntityKind::Group => rbac.disable_group(*id).await?,
+            };
+            info!("disabled in {:?} transaction", tx);
+            ids.get_mut(*kind).remove(name);
+        }
+        Action::Forget { kind, name } => {
+            ids.get_mut(*kind).remove(name);
+        }
+    }
+    ids.save(ids_path)
+}
+
+/// Compares policy with on-chain entities and assignments of owner.
+///
+/// Only entities from ids file and policy are managed, other entities of owner are left as is.
+fn plan(
+    policy: &Policy,
+    users: &HashMap<String, AccountId32>,
+    ids: &Ids,
+    owned: &OwnedEntities,
+    assignments: &OwnedAssignments,
+) -> Result<Vec<Action>, Error> {
+    let mut actions = Vec::new();
+    let mut names: HashMap<(EntityKind, EntityId), &str> = HashMap::new();
+    for (kind, name) in policy.entities() {
+        let records = records(owned, kind);
+        let is_enabled = |id: &EntityId| records.iter().any(|r| r.id == *id && r.enabled);
+        if let Some(id) = ids.get(kind).get(name).filter(|id| is_enabled(id)) {
+            names.insert((kind, *id), name);
+            continue;
+        }
+        // Entity is created, but ids file is lost or was not saved.
+        let known: BTreeSet<&EntityId> = ids.get(kind).values().collect();
+        let candidates: Vec<&RBACRecord> = records
+            .iter()
+            .filter(|r| r.enabled && r.name == name.as_bytes() && !known.contains(&r.id))
+            .collect();
+        match candidates.as_slice() {
+            [] => actions.push(Action::Add {
+                kind,
+                name: name.clone(),
+            }),
+            [record] => {
+                names.insert((kind, record.id), name);
+                actions.push(Action::Adopt {
+                    kind,
+                    name: name.clone(),
+                    id: record.id,
+                });
+            }
+            _ => {
+                return Err(format!(
+                    "there are {} enabled {kind}s named {name}, add id of one of them to ids file",
+                    candidates.len()
+                )
+                .into())
+            }
+        }
+    }
+
+    let entity_ref = |kind: EntityKind, id: EntityId| match names.get(&(kind, id)) {
+        Some(name) => Ref::Name(name.to_string()),
+        None => Ref::Id(id),
+    };
+    let name = |kind: EntityKind, id: EntityId| names.get(&(kind, id)).map(|v| v.to_string());
+    let mut current = BTreeSet::new();
+    for (permission, role) in &assignments.permission_roles {
+        if let Some(role) = name(EntityKind::Role, *role) {
+            let permission = entity_ref(EntityKind::Permission, *permission);
+            current.insert(Assignment::PermissionToRole { permission, role });
+        }
+    }
+    for (role, group) in &assignments.role_groups {
+        if let Some(group) = name(EntityKind::Group, *group) {
+            let role = entity_ref(EntityKind::Role, *role);
+            current.insert(Assignment::RoleToGroup { role, group });
+        }
+    }
+    for (role, user) in &assignments.role_users {
+        if let Some(role) = name(EntityKind::Role, *role) {
+            current.insert(Assignment::RoleToUser {
+                role,
+                user: AccountId32(*user),
+            });
+        }
+    }
+    for (user, group) in &assignments.user_groups {
+        if let Some(group) = name(EntityKind::Group, *group) {
+            current.insert(Assignment::UserToGroup {
+                user: AccountId32(*user),
+                group,
+            });
+        }
+    }
+
+    let user = |name: &String| {
+        users.get(name).cloned().ok_or_else(|| format!("user {name} is not resolved"))
+    };
+    let mut desired = BTreeSet::new();
+    for (role, role_policy) in &policy.roles {
+        for permission in &role_policy.permissions {
+            desired.insert(Assignment::PermissionToRole {
+                permission: Ref::Name(permission.clone()),
+                role: role.clone(),
+            });
+        }
+        for name in &role_policy.users {
+            desired.insert(Assignment::RoleToUser {
+                role: role.clone(),
+                user: user(name)?,
+            });
+        }
+    }
+    for (group, group_policy) in &policy.groups {
+        for role in &group_policy.roles {
+            desired.insert(Assignment::RoleToGroup {
+                role: Ref::Name(role.clone()),
+                group: group.clone(),
+            });
+        }
+        for name in &group_policy.users {
+            desired.insert(Assignment::UserToGroup {
+                user: user(name)?,
+                group: group.clone(),
+            });
+        }
+    }
+    // Payments watcher adds buyers to access groups, so group members which are not
+    // policy users are left as is.
+    let policy_users: BTreeSet<&AccountId32> = users.values().collect();
+    let managed = |assignment: &&Assignment| match assignment {
+        Assignment::UserToGroup { user, .. } => policy_users.contains(user),
+        _ => true,
+    };
+    actions.extend(current.difference(&desired).filter(managed).cloned().map(Action::Unassign));
+    actions.extend(desired.difference(&current).cloned().map(Action::Assign));
+
+    for kind in ENTITY_KINDS {
+        let declared: BTreeSet<&String> =
+            policy.entities().filter(|v| v.0 == kind).map(|v| v.1).collect();
+        for (name, id) in ids.get(kind).iter().filter(|(name, _)| !declared.contains(name)) {
+            if records(owned, kind).iter().any(|r| r.id == *id && r.enabled) {
+                actions.push(Action::Disable {
+                    kind,
+                    name: name.clone(),
+                    id: *id,
+                });
+            } else {
+                actions.push(Action::Forget {
+                    kind,
+                    name: name.clone(),
+                });
+            }
+        }
+    }
+    Ok(actions)
+}
+
+fn records(owned: &OwnedEntities, kind: EntityKind) -> &[RBACRecord] {
+    match kind {
+        EntityKind::Permission => &owned.permissions,
+        EntityKind::Role => &owned.roles,
+        EntityKind::Group => &owned.groups,
+    }
+}
+
+impl Policy {
+    fn load(path: &Path) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let policy: Self = toml::from_str(&content)
+            .map_err(|e| format!("failed to parse {}: {e}", path.display()))?;
+        policy.check()?;
+        Ok(policy)
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if let Some((kind, _)) = self.entities().find(|(_, name)| name.trim().is_empty()) {
+            return Err(format!("{kind} name must not be empty").into());
+        }
+        for (role, role_policy) in &self.roles {
+            if let Some(permission) =
+                role_policy.permissions.iter().find(|v| !self.permissions.contains(*v))
+            {
+                return Err(format!("role {role}: permission {permission} is not declared").into());
+            }
+        }
+        for (group, group_policy) in &self.groups {
+            if let Some(role) = group_policy.roles.iter().find(|v| !self.roles.contains_key(*v)) {
+                return Err(format!("group {group}: role {role} is not declared").into());
+            }
+        }
+        Ok(())
+    }
+
+    fn entities(&self) -> impl Iterator<Item = (EntityKind, &String)> {
+        let permissions = self.permissions.iter().map(|v| (EntityKind::Permission, v));
+        let roles = self.roles.keys().map(|v| (EntityKind::Role, v));
+        let groups = self.groups.keys().map(|v| (EntityKind::Group, v));
+        permissions.chain(roles).chain(groups)
+    }
+
+    fn users(&self) -> impl Iterator<Item = &String> {
+        let role_users = self.roles.values().flat_map(|v| &v.users);
+        let group_users = self.groups.values().flat_map(|v| &v.users);
+        role_users.chain(group_users).collect::<BTreeSet<_>>().into_iter()
+    }
+}
+
+impl Ids {
+    fn load(path: &Path) -> Result<Self, Error> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        Ok(toml::from_str(&content)
+            .map_err(|e| format!("failed to parse {}: {e}", path.display()))?)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Error> {
+        std::fs::write(path, toml::to_string_pretty(self)?)
+            .map_err(|e| format!("failed to write {}: {e}", path.display()).into())
+    }
+
+    fn get(&self, kind: EntityKind) -> &BTreeMap<String, EntityId> {
+        match kind {
+            EntityKind::Permission => &self.permissions,
+            EntityKind::Role => &self.roles,
+            EntityKind::Group => &self.groups,
+        }
+    }
+
+    fn get_mut(&mut self, kind: EntityKind) -> &mut BTreeMap<String, EntityId> {
+        match kind {
+            EntityKind::Permission => &mut self.permissions,
+            EntityKind::Role => &mut self.roles,
+            EntityKind::Group => &mut self.groups,
+        }
+    }
+
+    fn id(&self, kind: EntityKind, name: &str) -> Result<EntityId, Error> {
+        self.get(kind).get(name).copied().ok_or_else(|| format!("{kind} {name} has no id").into())
+    }
+
+    fn resolve(&self, kind: EntityKind, entity: &Ref) -> Result<EntityId, Error> {
+        match entity {
+            Ref::Name(name) => self.id(kind, name),
+            Ref::Id(id) => Ok(*id),
+        }
+    }
+}
+
+impl std::fmt::Display for Ref {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "{name}"),
+            Self::Id(id) => write!(f, "0x{}", hex::encode(id)),
+        }
+    }
+}
+
+impl Assignment {
+    fn describe(&self, preposition: &str) -> String {
+        match self {
+            Self::PermissionToRole { permission, role } => {
+                format!("permission {permission} {preposition} role {role}")
+            }
+            Self::RoleToGroup { role, group } => {
+                format!("role {role} {preposition} group {group}")
+            }
+            Self::RoleToUser { role, user } => format!("role {role} {preposition} user {user}"),
+            Self::UserToGroup { user, group } => {
+                format!("user {user} {preposition} group {group}")
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Adopt { kind, name, id } => {
+                write!(f, "use existing {} {} (0x{})", kind, name, hex::encode(id))
+            }
+            Self::Add { kind, name } => write!(f, "add {} {}", kind, name),
+            Self::Unassign(assignment) => write!(f, "unassign {}", assignment.describe("from")),
+            Self::Assign(assignment) => write!(f, "assign {}", assignment.describe("to")),
+            Self::Disable { kind, name, id } => {
+                write!(f, "disable {} {} (0x{})", kind, name, hex::encode(id))
+            }
+            Self::Forget { kind, name } => write!(f, "forget disabled {} {}", kind, name),
+        }
+    }
+}
+
+// Ids are written in hex as everywhere else in provisioner.
+mod hex_ids {
+    use std::collections::BTreeMap;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::EntityId;
+
+    pub(super) fn serialize<S: Serializer>(
+        ids: &BTreeMap<String, EntityId>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer
+            .collect_map(ids.iter().map(|(name, id)| (name, format!("0x{}", hex::encode(id)))))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<String, EntityId>, D::Error> {
+        BTreeMap::<String, String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(name, id)| {
+                let id = hex::decode(id.trim_start_matches("0x"))
+                    .ok()
+                    .and_then(|id| id.try_into().ok())
+                    .ok_or_else(|| D::Error::custom(format!("{name}: invalid entity id {id}")))?;
+                Ok((name, id))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: u8, name: &str, enabled: bool) -> RBACRecord {
+        RBACRecord {
+            id: [id; ENTITY_ID_LENGTH],
+            name: name.as_bytes().to_vec(),
+            enabled,
+        }
+    }
+
+    #[test]
+    fn policy() {
+        let policy: Policy = toml::from_str(
+            r#"
+            permissions = ["mqtt_access"]
+
+            [roles.accessor]
+            permissions = ["mqtt_access"]
+
+            [groups.subscribers]
+            roles = ["accessor"]
+            users = ["sensor-1"]
+            "#,
+        )
+        .unwrap();
+        assert!(policy.check().is_ok());
+        assert_eq!(policy.users().collect::<Vec<_>>(), ["sensor-1"]);
+
+        let policy: Policy = toml::from_str("[roles.accessor]\npermissions = [\"mqtt\"]").unwrap();
+        assert!(policy.check().is_err());
+        let policy: Policy = toml::from_str("[groups.subscribers]\nroles = [\"reader\"]").unwrap();
+        assert!(policy.check().is_err());
+    }
+
+    #[test]
+    fn actions() {
+        let policy: Policy = toml::from_str(
+            r#"
+            permissions = ["mqtt_access"]
+
+            [roles.accessor]
+            permissions = ["mqtt_access"]
+
+            [groups.subscribers]
+            roles = ["accessor"]
+            users = ["sensor-1"]
+
+            [groups.viewers]
+            "#,
+        )
+        .unwrap();
+        let sensor = AccountId32([7; 32]);
+        let stranger = AccountId32([8; 32]);
+        let users = HashMap::from([("sensor-1".to_string(), sensor.clone())]);
+        let ids: Ids = toml::from_str(&format!(
+            "[permissions]\nmqtt_access = \"0x{}\"\n[roles]\naccessor = \"0x{}\"\n\
+             [groups]\nold = \"0x{}\"\ngone = \"0x{}\"\n",
+            hex::encode([1; 32]),
+            hex::encode([2; 32]),
+            hex::encode([9; 32]),
+            hex::encode([10; 32]),
+        ))
+        .unwrap();
+        let owned = OwnedEntities {
+            permissions: vec![record(1, "mqtt_access", true)],
+            roles: vec![record(2, "accessor", true), record(3, "unmanaged", true)],
+            groups: vec![
+                record(5, "subscribers", true),
+                record(9, "old", true),
+                record(10, "gone", false),
+            ],
+        };
+        let assignments = OwnedAssignments {
+            permission_roles: vec![([1; 32], [2; 32])],
+            role_users: vec![([2; 32], stranger.0), ([3; 32], stranger.0)],
+            user_groups: vec![(sensor.0, [9; 32]), (stranger.0, [5; 32])],
+            ..Default::default()
+        };
+        let actions: Vec<String> = plan(&policy, &users, &ids, &owned, &assignments)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                format!("use existing group subscribers (0x{})", hex::encode([5; 32])),
+                "add group viewers".to_string(),
+                format!("unassign role accessor from user {stranger}"),
+                "assign role accessor to group subscribers".to_string(),
+                format!("assign user {sensor} to group subscribers"),
+                "forget disabled group gone".to_string(),
+                format!("disable group old (0x{})", hex::encode([9; 32])),
+            ]
+        );
+
+        let owned = OwnedEntities {
+            groups: vec![
+                record(5, "subscribers", true),
+                record(6, "subscribers", true),
+            ],
+            ..owned
+        };
+        assert!(plan(&policy, &users, &ids, &owned, &assignments).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use std::{collections::HashSet, path::PathBuf, str::from_utf8};
+
+use log::{info, warn};
+use peaq_client::{DIDAttribute, PairSigner, SignerClient, TrySigner};
+use subxt::utils::AccountId32;
+
+use crate::{
+    amount::Planck,
+    device::{self, Device},
+    open_or_create_keystore,
+    signer::AnySigner,
+    App, Error, DEVICE_ATTRIBUTE_NAME, SUPERSEDED_BY_ATTRIBUTE_NAME,
+};
+
+/// Key rotation options from command line.
+pub(crate) struct RotateKey {
+    pub(crate) device: String,
+    pub(crate) keystore: PathBuf,
+    pub(crate) password: String,
+}
+
+impl App {
+    /// Moves device attributes and RBAC memberships to new account.
+    ///
+    /// Old account gets superseded-by attribute with new address, so indexer can follow it.
+    /// Every step checks on-chain state first, so rotation can be run again after failure.
+    pub(crate) async fn rotate_key(&self, opts: &RotateKey) -> Result<(), Error> {
+        let device = self.device(&opts.device)?;
+        let name = &device.name;
+        let old_client = &device.peaq_client;
+        let old_address = old_client.address();
+        let keypair = open_or_create_keystore(&opts.keystore, &opts.password, name)?;
+        let new_client = SignerClient::with_client(
+            self.client.clone(),
+            AnySigner::Sr25519(Box::new(PairSigner::new(keypair))),
+        );
+        let new_address = new_client.address();
+        if new_address == old_address {
+            return Err(format!("device {name}: keystore contains current device account").into());
+        }
+        info!("{}: rotating key from {} to {}", name, old_address, new_address);
+
+        let (superseded_by, old_attributes): (Vec<DIDAttribute>, Vec<DIDAttribute>) =
+            account_attributes(old_client, &old_address)
+                .await?
+                .into_iter()
+                .partition(|attribute| attribute.name == SUPERSEDED_BY_ATTRIBUTE_NAME.as_bytes());
+        let superseded_by = superseded_by.into_iter().next();
+        if let Some(superseded_by) = &superseded_by {
+            if superseded_by.value != new_address.to_string().as_bytes() {
+                return Err(format!(
+                    "device {name}: account is already superseded by {}",
+                    String::from_utf8_lossy(&superseded_by.value)
+                )
+                .into());
+            }
+        }
+        let owned = old_client.get_owned_entities(&old_address).await?;
+        if !owned.roles.is_empty() || !owned.permissions.is_empty() || !owned.groups.is_empty() {
+            warn!("{}: RBAC entities owned by {} are not moved", name, old_address);
+        }
+
+        // New account pays for its attributes.
+        let amount = self.faucet.amount.to_planck(&self.token)?;
+        let balance = new_client.get_balance(&new_address).await?;
+        if balance < amount.0 {
+            info!("{}: sending {} to {}", name, self.token.format(amount), new_address);
+            let faucet_client = SignerClient::with_client(
+                self.client.clone(),
+                AnySigner::from_config(&self.faucet.signer)?,
+            );
+            faucet_client.transfer(amount.0, new_address.clone()).await?;
+        } else {
+            info!("{}: new account has {}", name, self.token.format(Planck(balance)));
+        }
+
+        let new_names: HashSet<Vec<u8>> = account_attributes(&new_client, &new_address)
+            .await?
+            .into_iter()
+            .map(|attribute| attribute.name)
+            .collect();
+        for attribute in &old_attributes {
+            if new_names.contains(&attribute.name) {
+                continue;
+            }
+            let attribute_name = from_utf8(&attribute.name)?;
+            let mut value = attribute.value.clone();
+            if attribute_name == DEVICE_ATTRIBUTE_NAME {
+                let (device, dropped) = without_attestation(&value)?;
+                if dropped {
+                    warn!(
+                        "{}: attestation signs old address, so it is dropped; sign output of \
+                         attestation-message command with device key again",
+                        name
+                    );
+                }
+                value = device;
+            }
+            let tx = new_client.did().add_attribute(attribute_name, value).await?;
+            info!("{}: copied attribute {} in {:?} transaction", name, attribute_name, tx);
+        }
+
+        // Memberships are assigned by main signer, e.g. during onboarding.
+        let admin = &self.main_device().peaq_client;
+        let rbac = admin.rbac();
+        let groups = rbac.fetch_user_groups(admin.address(), old_address.0).await?;
+        let roles = rbac.fetch_user_roles(admin.address(), old_address.0).await?;
+        let new_groups = rbac.fetch_user_groups(admin.address(), new_address.0).await?;
+        let new_roles = rbac.fetch_user_roles(admin.address(), new_address.0).await?;
+        for group in groups.iter().filter(|group| !new_groups.contains(group)) {
+            let tx = rbac.assign_user_to_group(new_address.0, *group).await?;
+            info!("{}: assigned to group 0x{} in {:?} transaction", name, hex::encode(group), tx);
+        }
+        for role in roles.iter().filter(|role| !new_roles.contains(role)) {
+            let tx = rbac.assign_role_to_user(*role, new_address.0).await?;
+            info!("{}: assigned to role 0x{} in {:?} transaction", name, hex::encode(role), tx);
+        }
+
+        // Link is added before removal, so indexer never loses device.
+        if superseded_by.is_none() {
+            let tx = old_client
+                .did()
+                .add_attribute(SUPERSEDED_BY_ATTRIBUTE_NAME, new_address.to_string().into_bytes())
+                .await?;
+            info!("{}: linked {} to {} in {:?} transaction", name, old_address, new_address, tx);
+        }
+        for attribute in &old_attributes {
+            let tx = old_client
+                .did()
+                .remove_account_attribute(old_address.clone(), attribute.name.clone())
+                .await?;
+            info!(
+                "{}: removed attribute {} of {} in {:?} transaction",
+                name,
+                String::from_utf8_lossy(&attribute.name),
+                old_address,
+                tx
+            );
+        }
+        for group in &groups {
+            rbac.unassign_user_to_group(old_address.0, *group).await?;
+        }
+        for role in &roles {
+            rbac.unassign_role_to_user(*role, old_address.0).await?;
+        }
+
+        eprintln!("Device {} is moved from {} to {}", name, old_address, new_address);
+        eprintln!(
+            "Replace signer of device {} in config with keystore {}",
+            name,
+            opts.keystore.display()
+        );
+        Ok(())
+    }
+}
+
+// Only attributes of account itself, attributes it created for other accounts stay.
+async fn account_attributes<S: TrySigner>(
+    client: &SignerClient<S>,
+    address: &AccountId32,
+) -> Result<Vec<DIDAttribute>, Error> {
+    Ok(client
+        .get_owned_attributes(address)
+        .await?
+        .into_iter()
+        .filter(|(did_account, _)| did_account == address)
+        .map(|(_, attribute)| attribute)
+        .collect())
+}
+
+// Attestation message includes device address, so it is invalid for new account.
+// Returns device without attestation and true if it was dropped.
+fn without_attestation(data: &[u8]) -> Result<(Vec<u8>, bool), Error> {
+    match device::decode(data)? {
+        Device::V2(mut device) if device.attestation.is_some() => {
+            device.attestation = None;
+            Ok((device::encode(Device::V2(device), device::encoding(data))?, true))
+        }
+        _ => Ok((data.to_vec(), false)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use subxt::ext::sp_core::{ed25519, Pair};
+
+    use super::*;
+    use crate::device::{DeviceV2, Encoding};
+
+    #[test]
+    fn dropped_attestation() {
+        let mut device = DeviceV2 {
+            data_type: "cctv-camera".to_string(),
+            location: "40.1949288120072,44.55177253802097".to_string(),
+            location_accuracy: None,
+            price_access: Planck(42),
+            price_pin: Planck(445),
+            additional: None,
+            private: None,
+            attestation: None,
+        };
+        let plain = device::encode(Device::V2(device.clone()), Encoding::Compact).unwrap();
+        assert_eq!(without_attestation(&plain).unwrap(), (plain.clone(), false));
+
+        device.attest(&AccountId32([1; 32]), &ed25519::Pair::from_seed(&[2; 32])).unwrap();
+        for encoding in Encoding::ALL {
+            let data = device::encode(Device::V2(device.clone()), encoding).unwrap();
+            let (copied, dropped) = without_attestation(&data).unwrap();
+            assert!(dropped);
+            assert_eq!(device::encoding(&copied), encoding);
+            let Device::V2(copied) = device::decode(&copied).unwrap() else {
+                panic!("device must be v2");
+            };
+            assert!(copied.attestation.is_none());
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use crypto_secretbox::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    XSalsa20Poly1305,
+};
+use curve25519_dalek::scalar::Scalar;
+use schnorrkel::{ExpansionMode, MiniSecretKey, PublicKey, SecretKey};
+use subxt::{
+    ext::sp_core::{blake2_256, sr25519},
+    utils::AccountId32,
+};
+
+use crate::{
+    device::{Sealed, SealedKey, MAX_SEALED_KEYS},
+    Error,
+};
+
+// Key encryption key is derived from shared secret together with this context.
+const SEAL_CONTEXT: &[u8] = b"staex-ioa-seal";
+
+pub(crate) type Fields = HashMap<String, toml::Value>;
+
+/// Returns true if account key is sr25519, i.e. fields can be sealed to it.
+pub(crate) fn can_seal_to(account: &AccountId32) -> bool {
+    PublicKey::from_bytes(&account.0).is_ok()
+}
+
+/// Encrypts fields with random content key and seals the key to every recipient.
+///
+/// Recipient key is derived from Diffie-Hellman of ephemeral key and recipient sr25519 account
+/// on Ristretto25519, so buyers don't need to publish separate encryption keys.
+pub(crate) fn seal(fields: &Fields, recipients: &[AccountId32]) -> Result<Sealed, Error> {
+    if recipients.len() > MAX_SEALED_KEYS as usize {
+        return Err(format!(
+            "private fields can be sealed to at most {MAX_SEALED_KEYS} accounts, got {}",
+            recipients.len()
+        )
+        .into());
+    }
+    let mut content_key = [0u8; 32];
+    OsRng.fill_bytes(&mut content_key);
+    let nonce = XSalsa20Poly1305::generate_nonce(&mut OsRng);
+    let plaintext = serde_json::to_vec(&serde_json::to_value(fields)?)?;
+    let ciphertext = XSalsa20Poly1305::new(&content_key.into())
+        .encrypt(&nonce, plaintext.as_slice())
+        .map_err(|_| "failed to encrypt private fields")?;
+
+    let mut seed = [0u8; 32];
+    OsRng.fill_bytes(&mut seed);
+    let ephemeral = MiniSecretKey::from_bytes(&seed)
+        .map_err(|e| format!("invalid ephemeral key: {e}"))?
+        .expand_to_keypair(ExpansionMode::Ed25519);
+    let mut keys = Vec::with_capacity(recipients.len());
+    for recipient in recipients {
+        let public = PublicKey::from_bytes(&recipient.0)
+            .map_err(|_| format!("{recipient} is not sr25519 account"))?;
+        let key = recipient_key(&ephemeral.secret, &public, &ephemeral.public, &public)?;
+        // Every recipient key encrypts one message, so nonce can be constant.
+        let sealed_key = XSalsa20Poly1305::new(&key.into())
+            .encrypt(&Default::default(), content_key.as_slice())
+            .map_err(|_| "failed to seal content key")?;
+        keys.push(SealedKey {
+            recipient: recipient.to_string(),
+            key: encode_hex(&sealed_key),
+        });
+    }
+    Ok(Sealed {
+        ephemeral: encode_hex(&ephemeral.public.to_bytes()),
+        nonce: encode_hex(&nonce),
+        ciphertext: encode_hex(&ciphertext),
+        keys,
+    })
+}
+
+/// Decrypts fields sealed to account of given key.
+pub(crate) fn open(sealed: &Sealed, pair: &sr25519::Pair) -> Result<Fields, Error> {
+    let keypair: &schnorrkel::Keypair = pair.as_ref();
+    let address = AccountId32(keypair.public.to_bytes()).to_string();
+    let sealed_key = sealed
+        .keys
+        .iter()
+        .find(|v| v.recipient == address)
+        .ok_or_else(|| format!("private fields are not sealed to {address}"))?;
+    let ephemeral = PublicKey::from_bytes(&decode_hex(&sealed.ephemeral)?)
+        .map_err(|e| format!("invalid ephemeral key: {e}"))?;
+    let key = recipient_key(&keypair.secret, &ephemeral, &ephemeral, &keypair.public)?;
+    let content_key: [u8; 32] = XSalsa20Poly1305::new(&key.into())
+        .decrypt(&Default::default(), decode_hex(&sealed_key.key)?.as_slice())
+        .map_err(|_| "failed to decrypt content key")?
+        .try_into()
+        .map_err(|_| "content key must be 32 bytes")?;
+    let nonce: [u8; 24] =
+        decode_hex(&sealed.nonce)?.try_into().map_err(|_| "nonce must be 24 bytes")?;
+    let plaintext = XSalsa20Poly1305::new(&content_key.into())
+        .decrypt(&nonce.into(), decode_hex(&sealed.ciphertext)?.as_slice())
+        .map_err(|_| "failed to decrypt private fields")?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// Returns list of accounts fields are sealed to.
+pub(crate) fn recipients(sealed: &Sealed) -> Vec<&str> {
+    sealed.keys.iter().map(|v| v.recipient.as_str()).collect()
+}
+
+// Both sides get the same key: ephemeral secret with recipient public key
+// or recipient secret with ephemeral public key.
+fn recipient_key(
+    secret: &SecretKey,
+    public: &PublicKey,
+    ephemeral: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<[u8; 32], Error> {
+    let scalar: [u8; 32] = secret.to_bytes()[..32].try_into()?;
+    let scalar = Option::<Scalar>::from(Scalar::from_canonical_bytes(scalar))
+        .ok_or("secret key is not canonical scalar")?;
+    let shared = (scalar * public.as_point()).compress();
+    let mut buf = SEAL_CONTEXT.to_vec();
+    buf.extend(shared.as_bytes());
+    buf.extend(ephemeral.to_bytes());
+    buf.extend(recipient.to_bytes());
+    Ok(blake2_256(&buf))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, Error> {
+    Ok(hex::decode(value.trim_start_matches("0x"))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use subxt::ext::sp_core::{ed25519, Pair};
+
+    use super::*;
+
+    #[test]
+    fn seal_and_open() {
+        let fields: Fields = HashMap::from([
+            ("location".to_string(), "40.19492,44.55177".into()),
+            ("port".to_string(), 1883.into()),
+        ]);
+        let (buyer, other, outsider) = (
+            sr25519::Pair::from_seed(&[1; 32]),
+            sr25519::Pair::from_seed(&[2; 32]),
+            sr25519::Pair::from_seed(&[3; 32]),
+        );
+        let accounts = [AccountId32(buyer.public().0), AccountId32(other.public().0)];
+        let sealed = seal(&fields, &accounts).unwrap();
+        assert_eq!(recipients(&sealed).len(), 2);
+        assert_eq!(open(&sealed, &buyer).unwrap(), fields);
+        assert_eq!(open(&sealed, &other).unwrap(), fields);
+        assert!(open(&sealed, &outsider).is_err());
+
+        // Key of one recipient can't be used by other one.
+        let mut stolen = sealed.clone();
+        stolen.keys[1].key.clone_from(&sealed.keys[0].key);
+        assert!(open(&stolen, &other).is_err());
+
+        let value = serde_json::to_value(&sealed).unwrap();
+        let device = serde_json::json!({"v2": {
+            "data_type": "cctv-camera",
+            "location": "40.1949288120072,44.55177253802097",
+            "price_access": "1",
+            "price_pin": "1",
+            "private": value,
+        }});
+        crate::device::validate(&device).unwrap();
+    }
+
+    #[test]
+    fn recipients_limit() {
+        let ed25519 = AccountId32(ed25519::Pair::from_seed(&[1; 32]).public().0);
+        let sr25519 = AccountId32(sr25519::Pair::from_seed(&[1; 32]).public().0);
+        assert!(can_seal_to(&sr25519));
+        // Ed25519 key is not valid Ristretto point in most cases, this one is checked.
+        assert!(!can_seal_to(&ed25519));
+        assert!(seal(&Fields::new(), &[ed25519]).is_err());
+        let many = vec![sr25519; MAX_SEALED_KEYS as usize + 1];
+        assert!(seal(&Fields::new(), &many).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use std::str::FromStr;
+
+use peaq_client::{PairSigner, RemoteSigner, TrySigner};
+use subxt::{
+    ext::sp_core::{ecdsa, ed25519, sr25519, Pair},
+    tx::Signer,
+    utils::{AccountId32, MultiSignature},
+    PolkadotConfig,
+};
+use subxt_signer::bip39;
+
+use crate::{
+    config::{self, Scheme, SignerType},
+    keystore::Keystore,
+    Error,
+};
+
+/// Signer chosen in config.
+pub(crate) enum AnySigner {
+    // Keypairs differ in size a lot, so they are boxed.
+    Sr25519(Box<PairSigner<PolkadotConfig, sr25519::Pair>>),
+    Ed25519(Box<PairSigner<PolkadotConfig, ed25519::Pair>>),
+    Ecdsa(Box<PairSigner<PolkadotConfig, ecdsa::Pair>>),
+    Remote(RemoteSigner),
+}
+
+impl AnySigner {
+    pub(crate) fn from_config(cfg: &config::Signer) -> Result<Self, Error> {
+        let secret = cfg.secret()?;
+        let password = cfg.password()?;
+        let uri = match cfg.typ {
+            SignerType::Phrase => {
+                // Check phrase itself, otherwise it is parsed as part of secret uri.
+                bip39::Mnemonic::from_str(&secret)?;
+                format!("{}{}", secret, cfg.derivation_path.as_deref().unwrap_or_default())
+            }
+            SignerType::SecretUri => secret,
+            SignerType::Keystore => {
+                let keystore: Keystore = serde_json::from_str(&secret)?;
+                let keypair = keystore.decrypt(&password.ok_or("keystore password is not set")?)?;
+                return Ok(Self::Sr25519(Box::new(PairSigner::new(keypair))));
+            }
+            SignerType::Remote => return Ok(Self::Remote(RemoteSigner::new(secret)?)),
+        };
+        let password = password.as_deref();
+        Ok(match cfg.scheme {
+            Scheme::Sr25519 => {
+                Self::Sr25519(Box::new(PairSigner::new(from_string(&uri, password)?)))
+            }
+            Scheme::Ed25519 => {
+                Self::Ed25519(Box::new(PairSigner::new(from_string(&uri, password)?)))
+            }
+            Scheme::Ecdsa => Self::Ecdsa(Box::new(PairSigner::new(from_string(&uri, password)?))),
+        })
+    }
+
+    /// Returns local sr25519 keypair, e.g. to decrypt data sealed to account.
+    pub(crate) fn sr25519(&self) -> Option<&sr25519::Pair> {
+        match self {
+            Self::Sr25519(signer) => Some(signer.signer()),
+            Self::Ed25519(_) | Self::Ecdsa(_) | Self::Remote(_) => None,
+        }
+    }
+}
+
+impl TrySigner for AnySigner {
+    fn account_id(&self) -> AccountId32 {
+        match self {
+            Self::Sr25519(signer) => signer.account_id().clone(),
+            Self::Ed25519(signer) => signer.account_id().clone(),
+            Self::Ecdsa(signer) => signer.account_id().clone(),
+            Self::Remote(signer) => signer.account_id(),
+        }
+    }
+
+    async fn try_sign(&self, payload: &[u8]) -> Result<MultiSignature, peaq_client::Error> {
+        match self {
+            Self::Sr25519(signer) => Ok(signer.sign(payload)),
+            Self::Ed25519(signer) => Ok(signer.sign(payload)),
+            Self::Ecdsa(signer) => Ok(signer.sign(payload)),
+            Self::Remote(signer) => signer.try_sign(payload).await,
+        }
+    }
+}
+
+fn from_string<P: Pair>(uri: &str, password: Option<&str>) -> Result<P, Error> {
+    P::from_string(uri, password).map_err(|e| format!("failed to create keypair: {e:?}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signer_schemes() {
+        let cfg = |scheme| config::Signer {
+            scheme,
+            ..Default::default()
+        };
+        let alice = |scheme| AnySigner::from_config(&cfg(scheme)).unwrap().account_id();
+        // Well-known development accounts.
+        assert_eq!(
+            alice(Scheme::Sr25519).to_string(),
+            "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"
+        );
+        assert_eq!(
+            alice(Scheme::Ed25519).to_string(),
+            "5FA9nQDVg267DEd8m1ZypXLBnvN7SFxYwV7ndqSYGiN9TTpu"
+        );
+        assert_eq!(
+            alice(Scheme::Ecdsa).to_string(),
+            "5C7C2Z5sWbytvHpuLTvzKunnnRwQxft1jiqrLD5rhucQ5S9X"
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! Fixtures shared by tests of several modules.
+
+use std::path::PathBuf;
+
+use crate::amount::Token;
+
+pub(crate) fn agung() -> Token {
+    Token {
+        symbol: "AGUNG".to_string(),
+        decimals: 18,
+    }
+}
+
+/// Sqlite database file in temporary directory, removed on drop even if test fails.
+pub(crate) struct TempDb(PathBuf);
+
+impl TempDb {
+    pub(crate) fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("{name}-{}.sqlite", std::process::id())))
+    }
+
+    pub(crate) fn dsn(&self) -> String {
+        format!("sqlite:{}", self.0.display())
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}